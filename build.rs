@@ -2,7 +2,7 @@ use std::env;
 
 extern crate gl_generator;
 
-use gl_generator::{Api, Fallbacks, Profile, Registry, StaticGenerator};
+use gl_generator::{Api, Fallbacks, GlobalGenerator, Profile, Registry, StaticGenerator};
 use std::fs::File;
 use std::path::Path;
 
@@ -10,17 +10,41 @@ fn main() {
     let dest = env::var("OUT_DIR").unwrap();
     let mut file = File::create(&Path::new(&dest).join("bindings.rs")).unwrap();
 
-    Registry::new(Api::Gles2, (3, 0), Profile::Core, Fallbacks::All, [])
+    Registry::new(Api::Gles2, (3, 0), Profile::Core, Fallbacks::All, ["GL_KHR_debug"])
         .write_bindings(StaticGenerator, &mut file)
         .unwrap();
 
+    // desktop GL is loaded at runtime: it can't be linked along with GLESv2
+    if env::var("CARGO_FEATURE_GL33").is_ok() {
+        let mut file = File::create(Path::new(&dest).join("gl33_bindings.rs")).unwrap();
+        Registry::new(
+            Api::Gl,
+            (3, 3),
+            Profile::Core,
+            Fallbacks::All,
+            [
+                "GL_KHR_debug",
+                "GL_ARB_ES2_compatibility",
+                "GL_ARB_ES3_compatibility",
+            ],
+        )
+        .write_bindings(GlobalGenerator, &mut file)
+        .unwrap();
+    }
+
     let target = env::var("TARGET");
     match target {
         Ok(s) if s.contains("wasm32") => (),
         _ => {
-            println!("cargo:rustc-link-lib=glfw"); // the "-l" flag
+            // only the windowing (ui) needs glfw, headless builds don't
+            if env::var("CARGO_FEATURE_UI").is_ok() {
+                println!("cargo:rustc-link-lib=glfw"); // the "-l" flag
+            }
             println!("cargo:rustc-link-lib=c"); // the "-l" flag
             println!("cargo:rustc-link-lib=GLESv2"); // the "-l" flag
+            if env::var("CARGO_FEATURE_HEADLESS").is_ok() {
+                println!("cargo:rustc-link-lib=EGL");
+            }
         }
     }
 }
@@ -80,6 +80,7 @@ fn init_render_objects(driver: &mut DriverPtr) -> PipelinePtr {
 
         pixel_uniforms: Vec::new(),
         pixel_surfaces: Vec::new(),
+        label: None,
     };
 
     let model_program = driver.create_shader(model_shader_desc).unwrap();
@@ -103,6 +104,8 @@ fn init_render_objects(driver: &mut DriverPtr) -> PipelinePtr {
         depth_test: true,
         blend: BlendOp::None,
         polygon_offset: PolygonOffset::None,
+        primitive_restart: false,
+        label: None,
     };
 
     driver.create_pipeline(tri_pipeline_desc).unwrap()
@@ -152,7 +155,7 @@ fn main() {
     ];
 
     let mut vertex_buffer = driver
-        .create_device_buffer(DeviceBufferDesc::Vertex(Usage::Dynamic(
+        .create_device_buffer(DeviceBufferDesc::vertex(Usage::Dynamic(
             3 * std::mem::size_of::<Vertex>(),
         )))
         .unwrap();
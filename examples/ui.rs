@@ -172,6 +172,7 @@ impl<'a> State<'a> {
 
             pixel_uniforms: Vec::new(),
             pixel_surfaces: Vec::new(),
+            label: None,
         };
 
         let model_program = driver.create_shader(model_shader_desc).unwrap();
@@ -195,6 +196,8 @@ impl<'a> State<'a> {
             depth_test: true,
             blend: BlendOp::None,
             polygon_offset: PolygonOffset::None,
+            primitive_restart: false,
+            label: None,
         };
 
         driver.create_pipeline(tri_pipeline_desc).unwrap()
@@ -228,7 +231,7 @@ impl<'a> State<'a> {
             Some(_) => (),
             None => {
                 let vertex_buffer = driver
-                    .create_device_buffer(DeviceBufferDesc::Vertex(Usage::Dynamic(
+                    .create_device_buffer(DeviceBufferDesc::vertex(Usage::Dynamic(
                         3 * std::mem::size_of::<Vertex>(),
                     )))
                     .unwrap();
@@ -30,6 +30,7 @@
 use rs_math3d::*;
 use std::sync::*;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ResourceType {
     DeviceBuffer,
     Texture,
@@ -37,6 +38,7 @@ pub enum ResourceType {
     Shader,
     Pipeline,
     FrameBuffer,
+    Query,
 }
 
 #[repr(C)]
@@ -67,8 +69,32 @@ impl<Desc> Resource<Desc> {
     pub fn desc(&self) -> &Desc {
         &self.desc
     }
+
+    ///
+    /// name the resource in the driver & external GL debuggers
+    ///
+    pub fn set_label(&self, label: &str) {
+        match &self.depends_on {
+            Some(driver) => {
+                driver
+                    .lock()
+                    .as_deref_mut()
+                    .unwrap()
+                    .set_label(&self.res_type, self.res_id, label)
+            }
+            _ => panic!("No driver!"),
+        }
+    }
 }
 
+//
+// The driver is only reached from a resource to label or delete it. Resources
+// labeled or dropped on threads other than the GL one are handled later by the
+// driver on its own thread.
+//
+unsafe impl<Desc: Send> Send for Resource<Desc> {}
+unsafe impl<Desc: Sync> Sync for Resource<Desc> {}
+
 impl<Desc> Drop for Resource<Desc> {
     fn drop(&mut self) {
         match &mut self.depends_on {
@@ -83,7 +109,7 @@ impl<Desc> Drop for Resource<Desc> {
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-/// Attributes
+// Attributes
 ////////////////////////////////////////////////////////////////////////////////
 
 pub trait AttributeDataTypeGetter {
@@ -260,7 +286,7 @@ impl AttributeDataTypeGetter for Matrix4<f32> {
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-/// Uniforms
+// Uniforms
 ////////////////////////////////////////////////////////////////////////////////
 
 pub trait UniformDataTypeGetter {
@@ -358,7 +384,7 @@ impl UniformDataTypeGetter for Matrix4<f32> {
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-/// Macros
+// Macros
 ////////////////////////////////////////////////////////////////////////////////
 #[macro_export]
 macro_rules! offset_of {
@@ -475,7 +501,7 @@ macro_rules! render_data {
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-/// VertexAttributeDesc
+// VertexAttributeDesc
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Clone)]
@@ -515,6 +541,34 @@ pub enum VertexFormat {
     Float4x4,
 }
 
+impl VertexFormat {
+    ///
+    /// size in bytes of one attribute
+    ///
+    pub fn size(&self) -> usize {
+        match self {
+            VertexFormat::Byte | VertexFormat::SByte => 1,
+            VertexFormat::Byte2 | VertexFormat::SByte2 => 2,
+            VertexFormat::Byte3 | VertexFormat::SByte3 => 3,
+            VertexFormat::Byte4 | VertexFormat::SByte4 => 4,
+
+            VertexFormat::Short => 2,
+            VertexFormat::Short2 => 2 * 2,
+            VertexFormat::Short3 => 3 * 2,
+            VertexFormat::Short4 => 4 * 2,
+
+            VertexFormat::Int | VertexFormat::UInt | VertexFormat::Float => 4,
+            VertexFormat::Int2 | VertexFormat::UInt2 | VertexFormat::Float2 => 2 * 4,
+            VertexFormat::Int3 | VertexFormat::UInt3 | VertexFormat::Float3 => 3 * 4,
+            VertexFormat::Int4 | VertexFormat::UInt4 | VertexFormat::Float4 => 4 * 4,
+
+            VertexFormat::Float2x2 => 4 * 4,
+            VertexFormat::Float3x3 => 9 * 4,
+            VertexFormat::Float4x4 => 16 * 4,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct VertexAttributeDesc {
     name: String,
@@ -549,7 +603,7 @@ pub trait VertexTrait {
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-/// UniformBlock
+// UniformBlock
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Clone)]
@@ -571,6 +625,23 @@ pub enum UniformDataType {
     Float4x4,
 }
 
+impl UniformDataType {
+    ///
+    /// size in bytes of one element
+    ///
+    pub fn size(&self) -> usize {
+        match self {
+            UniformDataType::UInt | UniformDataType::Int | UniformDataType::Float => 4,
+            UniformDataType::UInt2 | UniformDataType::Int2 | UniformDataType::Float2 => 2 * 4,
+            UniformDataType::UInt3 | UniformDataType::Int3 | UniformDataType::Float3 => 3 * 4,
+            UniformDataType::UInt4 | UniformDataType::Int4 | UniformDataType::Float4 => 4 * 4,
+            UniformDataType::Float2x2 => 4 * 4,
+            UniformDataType::Float3x3 => 9 * 4,
+            UniformDataType::Float4x4 => 16 * 4,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct UniformDesc {
     name: String,
@@ -624,7 +695,7 @@ pub trait UniformBlockTrait {
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-/// Buffers
+// Buffers
 ////////////////////////////////////////////////////////////////////////////////
 
 pub trait Payload: Send + Sync {
@@ -702,25 +773,59 @@ pub struct DeviceBufferMapping {
     pub buff: DeviceBufferPtr,
 }
 
-pub enum DeviceBufferDesc {
-    Vertex(Usage),
-    Index(Usage),
-    Pixel(Usage),
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceBufferType {
+    Vertex,
+    Index,
+    Pixel,
+}
+
+pub struct DeviceBufferDesc {
+    pub buffer_type: DeviceBufferType,
+    pub usage: Usage,
+    pub label: Option<String>,
 }
 
 impl DeviceBufferDesc {
-    pub fn size(&self) -> usize {
-        match self {
-            Self::Vertex(u) | Self::Index(u) | Self::Pixel(u) => u.size(),
+    pub fn vertex(usage: Usage) -> Self {
+        Self {
+            buffer_type: DeviceBufferType::Vertex,
+            usage,
+            label: None,
+        }
+    }
+
+    pub fn index(usage: Usage) -> Self {
+        Self {
+            buffer_type: DeviceBufferType::Index,
+            usage,
+            label: None,
         }
     }
+
+    pub fn pixel(usage: Usage) -> Self {
+        Self {
+            buffer_type: DeviceBufferType::Pixel,
+            usage,
+            label: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(String::from(label));
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.usage.size()
+    }
 }
 
 pub type DeviceBuffer = Resource<DeviceBufferDesc>;
 pub type DeviceBufferPtr = Arc<DeviceBuffer>;
 
 ////////////////////////////////////////////////////////////////////////////////
-/// ImageDesc
+// ImageDesc
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Clone, Copy)]
@@ -761,7 +866,7 @@ pub enum SamplerType {
     Sampler2D(PixelChannel, PixelChannel),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Filter {
     Nearest,
     Linear,
@@ -771,7 +876,7 @@ pub enum Filter {
     LinearMipmapLinear,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum PixelFormat {
     RGB8U,
     RGBA8U,
@@ -794,7 +899,7 @@ pub enum PixelFormat {
     R8(MinMagFilter),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct MinMagFilter {
     pub min_filter: Filter,
     pub mag_filter: Filter,
@@ -819,7 +924,7 @@ impl MinMagFilter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OrigSurfaceType {
     UInt,
     Float,
@@ -852,6 +957,47 @@ impl PixelFormat {
             PixelFormat::R8(_) => OrigSurfaceType::Float,
         }
     }
+
+    ///
+    /// size in bytes of one pixel as uploaded
+    ///
+    pub fn pixel_size(&self) -> usize {
+        match self {
+            PixelFormat::RGB8U => 3,
+            PixelFormat::RGBA8U => 4,
+            PixelFormat::R8U => 1,
+            PixelFormat::RGB32U => 3 * 4,
+            PixelFormat::RGBA32U => 4 * 4,
+            PixelFormat::R32U => 4,
+
+            PixelFormat::RGB32F => 3 * 4,
+            PixelFormat::RGBA32F => 4 * 4,
+            PixelFormat::R32F => 4,
+
+            PixelFormat::D16 => 2,
+            PixelFormat::D32 => 4,
+            PixelFormat::D24S8 => 4,
+            PixelFormat::D32S8 => 5,
+
+            PixelFormat::RGB8(_) => 3,
+            PixelFormat::RGBA8(_) => 4,
+            PixelFormat::R8(_) => 1,
+        }
+    }
+
+    pub fn is_depth(&self) -> bool {
+        match self {
+            PixelFormat::D16 | PixelFormat::D32 | PixelFormat::D24S8 | PixelFormat::D32S8 => true,
+            _ => false,
+        }
+    }
+
+    pub fn has_stencil(&self) -> bool {
+        match self {
+            PixelFormat::D24S8 | PixelFormat::D32S8 => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -911,11 +1057,13 @@ impl SamplerDesc {
 pub struct TextureDesc {
     pub sampler_desc: SamplerDesc,
     pub payload: Option<Arc<dyn Payload>>,
+    pub label: Option<String>,
 }
 
 pub struct RenderTargetDesc {
     pub sampler_desc: SamplerDesc,
     pub sample_count: usize,
+    pub label: Option<String>,
 }
 
 pub type Texture = Resource<TextureDesc>;
@@ -925,7 +1073,7 @@ pub type RenderTarget = Resource<RenderTargetDesc>;
 pub type RenderTargetPtr = Arc<RenderTarget>;
 
 ////////////////////////////////////////////////////////////////////////////////
-/// ShaderDesc
+// ShaderDesc
 ////////////////////////////////////////////////////////////////////////////////
 #[derive(Clone)]
 pub struct ShaderDesc {
@@ -938,6 +1086,8 @@ pub struct ShaderDesc {
 
     pub pixel_uniforms: Vec<String>,
     pub pixel_surfaces: Vec<String>,
+
+    pub label: Option<String>,
 }
 
 unsafe impl Send for ShaderDesc {}
@@ -947,7 +1097,7 @@ pub type Shader = Resource<ShaderDesc>;
 pub type ShaderPtr = Arc<Shader>;
 
 ////////////////////////////////////////////////////////////////////////////////
-/// Binding
+// Binding
 ////////////////////////////////////////////////////////////////////////////////
 #[derive(Clone, Eq, PartialEq)]
 pub enum IndexType {
@@ -982,15 +1132,33 @@ pub struct Bindings {
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-/// PipelineDesc
+// PipelineDesc
 ////////////////////////////////////////////////////////////////////////////////
 #[derive(Clone)]
 pub enum PrimitiveType {
     Points,
     Lines,
     LineStrip,
+    LineLoop,
     Triangles,
     TriangleStrip,
+    TriangleFan,
+}
+
+impl PrimitiveType {
+    ///
+    /// number of vertices (or indices) consumed by `prim_count` primitives
+    ///
+    pub fn element_count(&self, prim_count: u32) -> u32 {
+        match self {
+            PrimitiveType::Points => prim_count,
+            PrimitiveType::Lines => 2 * prim_count,
+            PrimitiveType::LineStrip => 1 + prim_count,
+            PrimitiveType::LineLoop => prim_count,
+            PrimitiveType::Triangles => 3 * prim_count,
+            PrimitiveType::TriangleStrip | PrimitiveType::TriangleFan => 2 + prim_count,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -1090,6 +1258,25 @@ pub struct PipelineDesc {
 
     pub blend: BlendOp,
     pub polygon_offset: PolygonOffset,
+
+    /// the maximum index (0xFFFF or 0xFFFFFFFF) restarts strips, loops & fans.
+    /// prim_count of the draws is then the number of indices
+    pub primitive_restart: bool,
+
+    pub label: Option<String>,
+}
+
+impl PipelineDesc {
+    ///
+    /// number of vertices (or indices) read by a draw of `prim_count` primitives
+    ///
+    pub fn element_count(&self, prim_count: u32) -> u32 {
+        if self.primitive_restart {
+            prim_count
+        } else {
+            self.primitive_type.element_count(prim_count)
+        }
+    }
 }
 
 unsafe impl Send for PipelineDesc {}
@@ -1099,18 +1286,43 @@ pub type Pipeline = Resource<PipelineDesc>;
 pub type PipelinePtr = Arc<Pipeline>;
 
 ////////////////////////////////////////////////////////////////////////////////
-/// Pass
+// Pass
 ////////////////////////////////////////////////////////////////////////////////
+///
+/// clear value matching the attachment type: Float4 for normalized & float
+/// surfaces, UInt4 for unsigned integer surfaces and Int4 for signed ones.
+/// There is no signed integer pixel format yet: `ValidatingDriver` always rejects Int4
+///
+#[derive(Clone, Copy)]
+pub enum ClearValue {
+    Float4(Vec4f),
+    UInt4(Vector4<u32>),
+    Int4(Vector4<i32>),
+}
+
 #[derive(Clone, Copy)]
 pub enum ColorPassAction {
     Clear(Color4b),
+    ClearValue(ClearValue),
     Previous,
+    /// the previous content is undefined (invalidated)
+    DontCare,
 }
 
 #[derive(Clone, Copy)]
 pub enum DepthPassAction {
     Clear(f32),
+    ClearDepthStencil(f32, u8),
     Previous,
+    /// the previous content is undefined (invalidated)
+    DontCare,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StoreAction {
+    Store,
+    /// the content is not needed after the pass (invalidated)
+    DontCare,
 }
 
 #[derive(Clone)]
@@ -1132,6 +1344,7 @@ impl SurfaceAttachment {
 pub struct FrameBufferDesc {
     pub color_attachements: [Option<SurfaceAttachment>; 4],
     pub depth_stencil_attachement: SurfaceAttachment,
+    pub label: Option<String>,
 }
 
 unsafe impl Send for FrameBufferDesc {}
@@ -1140,12 +1353,128 @@ unsafe impl Sync for FrameBufferDesc {}
 pub type FrameBuffer = Resource<FrameBufferDesc>;
 pub type FrameBufferPtr = Arc<FrameBuffer>;
 
+////////////////////////////////////////////////////////////////////////////////
+// Queries
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryType {
+    /// true if any sample passed the depth & stencil tests
+    AnySamplesPassed,
+    /// same as AnySamplesPassed, the implementation may answer true for hidden samples
+    AnySamplesPassedConservative,
+}
+
+#[derive(Clone)]
+pub struct QueryDesc {
+    pub query_type: QueryType,
+    pub label: Option<String>,
+}
+
+impl QueryDesc {
+    pub fn new(query_type: QueryType) -> Self {
+        Self {
+            query_type,
+            label: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(String::from(label));
+        self
+    }
+}
+
+pub type Query = Resource<QueryDesc>;
+pub type QueryPtr = Arc<Query>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DrawBlend {
+    Opaque,
+    Transparent,
+}
+
+///
+/// sort key of a draw. Layers run in increasing order, opaque draws before the transparent
+/// ones. Opaque draws are grouped by pipeline & textures then sorted front to back,
+/// transparent draws are sorted back to front. depth is the distance to the viewer
+///
+#[derive(Clone, Copy, Debug)]
+pub struct DrawKey {
+    pub layer: u8,
+    pub blend: DrawBlend,
+    pub depth: f32,
+}
+
+impl DrawKey {
+    pub fn opaque(layer: u8, depth: f32) -> Self {
+        Self {
+            layer,
+            blend: DrawBlend::Opaque,
+            depth,
+        }
+    }
+
+    pub fn transparent(layer: u8, depth: f32) -> Self {
+        Self {
+            layer,
+            blend: DrawBlend::Transparent,
+            depth,
+        }
+    }
+
+    ///
+    /// | layer: 8 | blend: 1 | opaque: pipeline 16, texture 16, depth 23
+    ///                       | transparent: inverted depth 23, pipeline 16, texture 16
+    ///
+    fn sort_key(&self, pipeline: usize, texture: usize) -> u64 {
+        // the bits of a positive float sort like the float
+        let depth = (self.depth.max(0.0).to_bits() >> 8) as u64 & 0x7F_FFFF;
+        let state = ((pipeline as u64 & 0xFFFF) << 16) | (texture as u64 & 0xFFFF);
+        let sorted = match self.blend {
+            DrawBlend::Opaque => (state << 23) | depth,
+            DrawBlend::Transparent => ((0x7F_FFFF - depth) << 32) | state,
+        };
+        ((self.layer as u64) << 56) | ((self.blend as u64) << 55) | sorted
+    }
+}
+
 pub(crate) struct DrawCommand {
     pub pipe: PipelinePtr,
     pub bindings: Bindings,
     pub uniforms: Arc<dyn Payload>,
     pub prim_count: u32,
     pub instance_count: u32,
+    pub key: Option<DrawKey>,
+}
+
+impl DrawCommand {
+    fn sort_key(&self) -> u64 {
+        let texture = match (
+            self.bindings.pixel_images.first(),
+            self.bindings.vertex_images.first(),
+        ) {
+            (Some(t), _) | (None, Some(t)) => t.res_id(),
+            (None, None) => 0,
+        };
+        self.key.unwrap().sort_key(self.pipe.res_id(), texture)
+    }
+
+    fn uses_buffer(&self, buffer: &DeviceBufferPtr) -> bool {
+        self.bindings
+            .vertex_buffers
+            .iter()
+            .chain(self.bindings.index_buffer.iter())
+            .any(|b| b.res_id() == buffer.res_id())
+    }
+
+    fn uses_texture(&self, tex: &TexturePtr) -> bool {
+        self.bindings
+            .vertex_images
+            .iter()
+            .chain(self.bindings.pixel_images.iter())
+            .any(|t| t.res_id() == tex.res_id())
+    }
 }
 
 pub(crate) struct UpdateDeviceBufferCommand {
@@ -1159,12 +1488,41 @@ pub(crate) struct UpdateTextureCommand {
     pub payload: Arc<dyn Payload>,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BlitFilter {
+    Nearest,
+    Linear,
+}
+
+pub(crate) struct BlitCommand {
+    pub src: Option<FrameBufferPtr>,
+    pub src_attachment: usize,
+    pub src_rect: Recti,
+    pub dst: Option<FrameBufferPtr>,
+    pub dst_rect: Recti,
+    pub color: bool,
+    pub depth: bool,
+    pub filter: BlitFilter,
+}
+
+pub(crate) struct CopyTextureCommand {
+    pub src: TexturePtr,
+    pub src_rect: Recti,
+    pub dst: TexturePtr,
+    pub dst_x: i32,
+    pub dst_y: i32,
+}
+
 pub(crate) enum RenderPassCommand {
     Viewport(i32, i32, u32, u32),
     Scissor(i32, i32, u32, u32),
     Draw(DrawCommand),
     UpdateDeviceBuffer(UpdateDeviceBufferCommand),
     UpdateTexture(UpdateTextureCommand),
+    Blit(BlitCommand),
+    CopyTexture(CopyTextureCommand),
+    BeginQuery(QueryPtr),
+    EndQuery(QueryPtr),
 }
 
 pub struct Pass {
@@ -1173,9 +1531,43 @@ pub struct Pass {
     pub frame_buffer: Option<FrameBufferPtr>,
     pub color_actions: [ColorPassAction; 4],
     pub depth_action: DepthPassAction,
+    pub color_store_actions: [StoreAction; 4],
+    pub depth_store_action: StoreAction,
     pub queue: PassCommandQueue,
-}
-
+    /// debug group name of the pass
+    pub label: Option<String>,
+}
+
+///
+/// Queues are Send: they can be recorded by worker threads then appended to the pass
+/// queue in a fixed order on the GL thread, which keeps the frame deterministic:
+///
+/// ```ignore
+/// let queues: Vec<PassCommandQueue> = std::thread::scope(|s| {
+///     let workers: Vec<_> = nodes
+///         .chunks(1024)
+///         .map(|chunk| {
+///             let um_renderer = &um_renderer;
+///             s.spawn(move || {
+///                 let mut queue = PassCommandQueue::new();
+///                 for node in chunk {
+///                     um_renderer.draw_node(&mut queue, &pvm, node);
+///                 }
+///                 queue
+///             })
+///         })
+///         .collect();
+///     workers.into_iter().map(|w| w.join().unwrap()).collect()
+/// });
+///
+/// for queue in queues {
+///     pass.queue.append(queue);
+/// }
+/// driver.render_pass(&mut pass);
+/// ```
+///
+/// Resources can be created & labeled on the GL thread only.
+///
 #[derive(Default)]
 pub struct PassCommandQueue {
     pub(crate) commands: Vec<RenderPassCommand>,
@@ -1210,12 +1602,81 @@ impl PassCommandQueue {
             uniforms,
             prim_count,
             instance_count,
+            key: None,
+        }));
+    }
+
+    ///
+    /// draw that can be reordered by sort_draws
+    ///
+    pub fn draw_keyed(
+        &mut self,
+        key: DrawKey,
+        pipe: &PipelinePtr,
+        bindings: &Bindings,
+        uniforms: Arc<dyn Payload>,
+        prim_count: u32,
+        instance_count: u32,
+    ) {
+        self.commands.push(RenderPassCommand::Draw(DrawCommand {
+            pipe: pipe.clone(),
+            bindings: bindings.clone(),
+            uniforms,
+            prim_count,
+            instance_count,
+            key: Some(key),
         }));
     }
 
+    ///
+    /// sort the keyed draws by key (stable). Viewport, scissor, blit & copy commands and
+    /// unkeyed draws stay in place and split the sorting. Resource updates are moved
+    /// ahead of the sorted draws, unless a draw recorded before them uses the resource
+    ///
+    pub fn sort_draws(&mut self) {
+        let mut sorted = Vec::with_capacity(self.commands.len());
+        let mut updates: Vec<RenderPassCommand> = Vec::new();
+        let mut draws: Vec<DrawCommand> = Vec::new();
+
+        fn flush(
+            sorted: &mut Vec<RenderPassCommand>,
+            updates: &mut Vec<RenderPassCommand>,
+            draws: &mut Vec<DrawCommand>,
+        ) {
+            sorted.append(updates);
+            draws.sort_by_key(|d| d.sort_key());
+            sorted.extend(draws.drain(..).map(RenderPassCommand::Draw));
+        }
+
+        for cmd in self.commands.drain(..) {
+            match cmd {
+                RenderPassCommand::Draw(draw) if draw.key.is_some() => draws.push(draw),
+                RenderPassCommand::UpdateDeviceBuffer(update) => {
+                    if draws.iter().any(|d| d.uses_buffer(&update.buffer)) {
+                        flush(&mut sorted, &mut updates, &mut draws);
+                    }
+                    updates.push(RenderPassCommand::UpdateDeviceBuffer(update));
+                }
+                RenderPassCommand::UpdateTexture(update) => {
+                    if draws.iter().any(|d| d.uses_texture(&update.tex)) {
+                        flush(&mut sorted, &mut updates, &mut draws);
+                    }
+                    updates.push(RenderPassCommand::UpdateTexture(update));
+                }
+                cmd => {
+                    flush(&mut sorted, &mut updates, &mut draws);
+                    sorted.push(cmd);
+                }
+            }
+        }
+        flush(&mut sorted, &mut updates, &mut draws);
+
+        self.commands = sorted;
+    }
+
     pub fn update_device_buffer(
         &mut self,
-        dev_buf: &mut DeviceBufferPtr,
+        dev_buf: &DeviceBufferPtr,
         offset: usize,
         pl: Arc<dyn Payload>,
     ) {
@@ -1228,7 +1689,7 @@ impl PassCommandQueue {
         ));
     }
 
-    pub fn update_texture(&mut self, tex: &mut TexturePtr, pl: Arc<dyn Payload>) {
+    pub fn update_texture(&mut self, tex: &TexturePtr, pl: Arc<dyn Payload>) {
         self.commands
             .push(RenderPassCommand::UpdateTexture(UpdateTextureCommand {
                 tex: tex.clone(),
@@ -1236,6 +1697,88 @@ impl PassCommandQueue {
             }));
     }
 
+    ///
+    /// copy (and scale) a region of a color attachment, None stands for the default frame buffer.
+    /// The destination color attachments are the draw buffers of the destination frame buffer
+    ///
+    pub fn blit_color(
+        &mut self,
+        src: Option<&FrameBufferPtr>,
+        src_attachment: usize,
+        src_rect: Recti,
+        dst: Option<&FrameBufferPtr>,
+        dst_rect: Recti,
+        filter: BlitFilter,
+    ) {
+        self.commands.push(RenderPassCommand::Blit(BlitCommand {
+            src: src.cloned(),
+            src_attachment,
+            src_rect,
+            dst: dst.cloned(),
+            dst_rect,
+            color: true,
+            depth: false,
+            filter,
+        }));
+    }
+
+    ///
+    /// copy (and scale) a region of the depth/stencil attachment, always nearest filtered
+    ///
+    pub fn blit_depth(
+        &mut self,
+        src: Option<&FrameBufferPtr>,
+        src_rect: Recti,
+        dst: Option<&FrameBufferPtr>,
+        dst_rect: Recti,
+    ) {
+        self.commands.push(RenderPassCommand::Blit(BlitCommand {
+            src: src.cloned(),
+            src_attachment: 0,
+            src_rect,
+            dst: dst.cloned(),
+            dst_rect,
+            color: false,
+            depth: true,
+            filter: BlitFilter::Nearest,
+        }));
+    }
+
+    ///
+    /// copy a region of a texture to another texture of the same kind (color or depth)
+    ///
+    pub fn copy_texture(
+        &mut self,
+        src: &TexturePtr,
+        src_rect: Recti,
+        dst: &TexturePtr,
+        dst_x: i32,
+        dst_y: i32,
+    ) {
+        self.commands
+            .push(RenderPassCommand::CopyTexture(CopyTextureCommand {
+                src: src.clone(),
+                src_rect,
+                dst: dst.clone(),
+                dst_x,
+                dst_y,
+            }));
+    }
+
+    ///
+    /// the draws recorded until end_query are counted by the query. Only one query of
+    /// each type can be active at a time and it must end in the same pass
+    ///
+    pub fn begin_query(&mut self, query: &QueryPtr) {
+        self.commands
+            .push(RenderPassCommand::BeginQuery(query.clone()));
+    }
+
+    pub fn end_query(&mut self, query: &QueryPtr) {
+        self.commands
+            .push(RenderPassCommand::EndQuery(query.clone()));
+    }
+
     pub fn drain(&mut self) {
         self.commands.clear();
     }
@@ -1245,6 +1788,12 @@ impl PassCommandQueue {
     }
 }
 
+// parallel recording relies on it
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<PassCommandQueue>();
+};
+
 impl Pass {
     pub fn new(
         width: usize,
@@ -1259,25 +1808,44 @@ impl Pass {
             frame_buffer,
             color_actions,
             depth_action,
+            color_store_actions: [StoreAction::Store; 4],
+            depth_store_action: StoreAction::Store,
             queue: PassCommandQueue {
                 commands: Vec::new(),
             },
+            label: None,
         }
     }
 
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(String::from(label));
+        self
+    }
+
+    pub fn with_store_actions(
+        mut self,
+        color_store_actions: [StoreAction; 4],
+        depth_store_action: StoreAction,
+    ) -> Self {
+        self.color_store_actions = color_store_actions;
+        self.depth_store_action = depth_store_action;
+        self
+    }
+
     pub fn clone_with_no_commands(&self) -> Self {
         Self {
             queue: PassCommandQueue {
                 commands: Vec::new(),
             },
             frame_buffer: self.frame_buffer.clone(),
+            label: self.label.clone(),
             ..*self
         }
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-/// Readback surface
+// Readback surface
 ////////////////////////////////////////////////////////////////////////////////
 pub enum ReadbackPayload {
     RGB32U(Vec<Vector3<u32>>),
@@ -1291,6 +1859,7 @@ pub enum ReadbackPayload {
     Depth(Vec<f32>),
 }
 
+#[derive(Debug)]
 pub enum ReadbackError {
     NoReadbackFromRenderTarget,
     RectOutOfBound,
@@ -1302,15 +1871,151 @@ pub enum ReadbackResult {
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-/// Capabilities
+// Capabilities
 ////////////////////////////////////////////////////////////////////////////////
 #[derive(Copy, Clone)]
 pub struct DriverCaps {
     pub max_2d_surface_dimension: Dimensioni,
+    /// RGBA32F can be rendered to (EXT_color_buffer_float on GLES 3)
+    pub float_render_targets: bool,
+    /// RGBA32F render targets can be blended to (EXT_float_blend on GLES 3)
+    pub float_blending: bool,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Statistics
+////////////////////////////////////////////////////////////////////////////////
+#[derive(Copy, Clone, Default, Debug)]
+pub struct ResourceStats {
+    pub live: usize,
+    pub bytes: usize,
+    pub peak_live: usize,
+    pub peak_bytes: usize,
+}
+
+impl ResourceStats {
+    pub(crate) fn add(&mut self, bytes: usize) {
+        self.live += 1;
+        self.bytes += bytes;
+        self.peak_live = usize::max(self.peak_live, self.live);
+        self.peak_bytes = usize::max(self.peak_bytes, self.bytes);
+    }
+
+    pub(crate) fn remove(&mut self, bytes: usize) {
+        self.live -= 1;
+        self.bytes -= bytes;
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct FrameStats {
+    pub passes: usize,
+    pub draw_calls: usize,
+    /// draws using a different pipeline than the previous draw of the same pass
+    pub pipeline_changes: usize,
+    pub uploaded_bytes: usize,
+    /// GL calls skipped by the state & vertex array caches
+    pub saved_calls: usize,
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct DriverStats {
+    pub device_buffers: ResourceStats,
+    pub textures: ResourceStats,
+    pub render_targets: ResourceStats,
+    pub shaders: ResourceStats,
+    pub pipelines: ResourceStats,
+    pub frame_buffers: ResourceStats,
+    pub queries: ResourceStats,
+
+    /// counters of the frame being recorded
+    pub current_frame: FrameStats,
+    /// counters of the last frame closed by `Driver::end_frame`
+    pub last_frame: FrameStats,
+}
+
+impl DriverStats {
+    pub fn resource(&self, res_type: ResourceType) -> &ResourceStats {
+        match res_type {
+            ResourceType::DeviceBuffer => &self.device_buffers,
+            ResourceType::Texture => &self.textures,
+            ResourceType::RenderTarget => &self.render_targets,
+            ResourceType::Shader => &self.shaders,
+            ResourceType::Pipeline => &self.pipelines,
+            ResourceType::FrameBuffer => &self.frame_buffers,
+            ResourceType::Query => &self.queries,
+        }
+    }
+
+    pub(crate) fn resource_mut(&mut self, res_type: ResourceType) -> &mut ResourceStats {
+        match res_type {
+            ResourceType::DeviceBuffer => &mut self.device_buffers,
+            ResourceType::Texture => &mut self.textures,
+            ResourceType::RenderTarget => &mut self.render_targets,
+            ResourceType::Shader => &mut self.shaders,
+            ResourceType::Pipeline => &mut self.pipelines,
+            ResourceType::FrameBuffer => &mut self.frame_buffers,
+            ResourceType::Query => &mut self.queries,
+        }
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.device_buffers.bytes + self.textures.bytes + self.render_targets.bytes
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.last_frame = self.current_frame;
+        self.current_frame = FrameStats::default();
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LiveResource {
+    pub res_type: ResourceType,
+    pub res_id: usize,
+    pub bytes: usize,
+    pub label: Option<String>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Debug output
+////////////////////////////////////////////////////////////////////////////////
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugSeverity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugMessageType {
+    Error,
+    DeprecatedBehavior,
+    UndefinedBehavior,
+    Portability,
+    Performance,
+    Marker,
+    Group,
+    Other,
 }
 
+#[derive(Clone, Debug)]
+pub struct DebugMessage {
+    pub id: u32,
+    pub msg_type: DebugMessageType,
+    pub severity: DebugSeverity,
+    pub message: String,
+}
+
+///
+/// receives the driver debug output. It is called from within the driver
+/// while it is locked: it must not call back into the driver
+///
+pub type DebugCallback = Box<dyn FnMut(&DebugMessage) + Send>;
+
 ////////////////////////////////////////////////////////////////////////////////
-/// Driver
+// Driver
 ////////////////////////////////////////////////////////////////////////////////
 pub trait Driver {
     fn get_caps(&self) -> DriverCaps;
@@ -1320,8 +2025,10 @@ pub trait Driver {
     fn create_shader(&mut self, desc: ShaderDesc) -> Option<ShaderPtr>;
     fn create_pipeline(&mut self, desc: PipelineDesc) -> Option<PipelinePtr>;
     fn create_frame_buffer(&mut self, desc: FrameBufferDesc) -> Option<FrameBufferPtr>;
+    fn create_query(&mut self, desc: QueryDesc) -> Option<QueryPtr>;
 
     fn delete_resource(&mut self, resource_type: &ResourceType, res_id: usize);
+    fn set_label(&mut self, resource_type: &ResourceType, res_id: usize, label: &str);
 
     fn render_pass(&mut self, pass: &mut Pass);
 
@@ -1333,6 +2040,22 @@ pub trait Driver {
         w: u32,
         h: u32,
     ) -> Option<ReadbackPayload>;
+
+    ///
+    /// result of an ended query, None until the GPU made it available (never blocks)
+    ///
+    fn query_result(&mut self, query: &QueryPtr) -> Option<bool>;
+
+    fn stats(&self) -> DriverStats;
+    fn end_frame(&mut self);
+
+    fn live_resources(&self) -> Vec<LiveResource>;
+    /// report the resources still alive when the driver is dropped, to the debug
+    /// callback if there is one, to stderr otherwise
+    fn set_leak_report(&mut self, enabled: bool);
+
+    /// route the GL debug output (KHR_debug) to the callback, None disables it
+    fn set_debug_callback(&mut self, callback: Option<DebugCallback>);
 }
 
 //
@@ -1411,6 +2134,14 @@ impl Driver for DriverPtr {
             .create_frame_buffer(desc)
     }
 
+    fn create_query(&mut self, desc: QueryDesc) -> Option<QueryPtr> {
+        self.driver
+            .lock()
+            .as_deref_mut()
+            .unwrap()
+            .create_query(desc)
+    }
+
     fn delete_resource(&mut self, resource_type: &ResourceType, res_id: usize) {
         self.driver
             .lock()
@@ -1419,6 +2150,14 @@ impl Driver for DriverPtr {
             .delete_resource(resource_type, res_id)
     }
 
+    fn set_label(&mut self, resource_type: &ResourceType, res_id: usize, label: &str) {
+        self.driver
+            .lock()
+            .as_deref_mut()
+            .unwrap()
+            .set_label(resource_type, res_id, label)
+    }
+
     fn render_pass(&mut self, pass: &mut Pass) {
         self.driver.lock().as_deref_mut().unwrap().render_pass(pass)
     }
@@ -1437,4 +2176,206 @@ impl Driver for DriverPtr {
             .unwrap()
             .read_back(surface, x, y, w, h)
     }
+
+    fn query_result(&mut self, query: &QueryPtr) -> Option<bool> {
+        self.driver
+            .lock()
+            .as_deref_mut()
+            .unwrap()
+            .query_result(query)
+    }
+
+    fn stats(&self) -> DriverStats {
+        self.driver.lock().as_deref_mut().unwrap().stats()
+    }
+
+    fn end_frame(&mut self) {
+        self.driver.lock().as_deref_mut().unwrap().end_frame()
+    }
+
+    fn live_resources(&self) -> Vec<LiveResource> {
+        self.driver.lock().as_deref_mut().unwrap().live_resources()
+    }
+
+    fn set_leak_report(&mut self, enabled: bool) {
+        self.driver
+            .lock()
+            .as_deref_mut()
+            .unwrap()
+            .set_leak_report(enabled)
+    }
+
+    fn set_debug_callback(&mut self, callback: Option<DebugCallback>) {
+        self.driver
+            .lock()
+            .as_deref_mut()
+            .unwrap()
+            .set_debug_callback(callback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::null_driver::NullDriver;
+    use super::*;
+
+    struct Fixture {
+        pipeline: PipelinePtr,
+        a: DeviceBufferPtr,
+        b: DeviceBufferPtr,
+        queue: PassCommandQueue,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let mut driver = NullDriver::new(Vec::new());
+            let shader = driver
+                .create_shader(ShaderDesc {
+                    vertex_shader: String::new(),
+                    pixel_shader: String::new(),
+
+                    vertex_attributes: Vec::new(),
+                    vertex_uniforms: Vec::new(),
+                    vertex_surfaces: Vec::new(),
+
+                    pixel_uniforms: Vec::new(),
+                    pixel_surfaces: Vec::new(),
+                    label: None,
+                })
+                .unwrap();
+            let pipeline = driver
+                .create_pipeline(PipelineDesc {
+                    primitive_type: PrimitiveType::Triangles,
+                    shader,
+                    buffer_layouts: Vec::new(),
+                    uniform_descs: Vec::new(),
+                    index_type: IndexType::None,
+                    face_winding: FaceWinding::CCW,
+                    cull_mode: CullMode::None,
+                    depth_write: true,
+                    depth_test: true,
+                    blend: BlendOp::None,
+                    polygon_offset: PolygonOffset::None,
+                    primitive_restart: false,
+                    label: None,
+                })
+                .unwrap();
+            let mut buffer = || {
+                driver
+                    .create_device_buffer(DeviceBufferDesc::vertex(Usage::Dynamic(64)))
+                    .unwrap()
+            };
+            let (a, b) = (buffer(), buffer());
+            Self {
+                pipeline,
+                a,
+                b,
+                queue: PassCommandQueue::new(),
+            }
+        }
+
+        /// the draws are told apart by their primitive count
+        fn draw(&mut self, key: DrawKey, buffer: &DeviceBufferPtr, id: u32) {
+            let bindings = Bindings {
+                vertex_buffers: vec![buffer.clone()],
+                index_buffer: None,
+
+                vertex_images: Vec::new(),
+                pixel_images: Vec::new(),
+            };
+            self.queue.draw_keyed(
+                key,
+                &self.pipeline,
+                &bindings,
+                Arc::new(Vec::<u8>::new()),
+                id,
+                1,
+            );
+        }
+
+        /// the updates are told apart by their offset
+        fn update(&mut self, buffer: &DeviceBufferPtr, id: usize) {
+            self.queue
+                .update_device_buffer(buffer, id, Arc::new(vec![0u8; 4]));
+        }
+
+        fn sorted(mut self) -> Vec<String> {
+            self.queue.sort_draws();
+            self.queue
+                .commands
+                .iter()
+                .map(|cmd| match cmd {
+                    RenderPassCommand::Draw(d) => format!("draw {}", d.prim_count),
+                    RenderPassCommand::UpdateDeviceBuffer(u) => format!("update {}", u.offset),
+                    RenderPassCommand::Viewport(..) => String::from("viewport"),
+                    RenderPassCommand::Scissor(..) => String::from("scissor"),
+                    _ => String::from("other"),
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn update_hoisted_past_unrelated_draws() {
+        let mut f = Fixture::new();
+        let (a, b) = (f.a.clone(), f.b.clone());
+        f.draw(DrawKey::opaque(0, 0.5), &a, 1);
+        f.draw(DrawKey::opaque(0, 0.2), &a, 2);
+        f.update(&b, 3);
+        assert_eq!(f.sorted(), ["update 3", "draw 2", "draw 1"]);
+    }
+
+    #[test]
+    fn update_splits_draws_using_the_buffer() {
+        let mut f = Fixture::new();
+        let a = f.a.clone();
+        f.draw(DrawKey::opaque(0, 0.5), &a, 1);
+        f.update(&a, 2);
+        f.draw(DrawKey::opaque(0, 0.2), &a, 3);
+        f.draw(DrawKey::opaque(0, 0.1), &a, 4);
+        assert_eq!(f.sorted(), ["draw 1", "update 2", "draw 4", "draw 3"]);
+    }
+
+    #[test]
+    fn viewport_and_scissor_are_barriers() {
+        let mut f = Fixture::new();
+        let a = f.a.clone();
+        f.draw(DrawKey::opaque(0, 0.5), &a, 1);
+        f.draw(DrawKey::opaque(0, 0.2), &a, 2);
+        f.queue.set_viewport(0, 0, 4, 4);
+        f.draw(DrawKey::opaque(0, 0.9), &a, 3);
+        f.draw(DrawKey::opaque(0, 0.1), &a, 4);
+        f.queue.set_scissor(0, 0, 4, 4);
+        f.draw(DrawKey::opaque(0, 0.0), &a, 5);
+        assert_eq!(
+            f.sorted(),
+            ["draw 2", "draw 1", "viewport", "draw 4", "draw 3", "scissor", "draw 5"]
+        );
+    }
+
+    #[test]
+    fn equal_keys_are_stable() {
+        let mut f = Fixture::new();
+        let a = f.a.clone();
+        for id in 1..=4 {
+            f.draw(DrawKey::opaque(0, 0.5), &a, id);
+        }
+        assert_eq!(f.sorted(), ["draw 1", "draw 2", "draw 3", "draw 4"]);
+    }
+
+    #[test]
+    fn transparent_draws_are_back_to_front_after_opaque() {
+        let mut f = Fixture::new();
+        let a = f.a.clone();
+        f.draw(DrawKey::transparent(0, 0.2), &a, 1);
+        f.draw(DrawKey::opaque(0, 0.5), &a, 2);
+        f.draw(DrawKey::transparent(0, 0.8), &a, 3);
+        f.draw(DrawKey::opaque(0, 0.1), &a, 4);
+        f.draw(DrawKey::transparent(0, 0.5), &a, 5);
+        f.draw(DrawKey::opaque(1, 0.0), &a, 6);
+        assert_eq!(
+            f.sorted(),
+            ["draw 4", "draw 2", "draw 3", "draw 5", "draw 1", "draw 6"]
+        );
+    }
 }
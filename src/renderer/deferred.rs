@@ -0,0 +1,714 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+//
+// Deferred shading
+//
+// G-buffer (3 of the 4 GLES 3 color attachments):
+//  - 0: albedo     RGBA8   rgb albedo
+//  - 1: normal     RGBA32F xyz view space normal
+//  - 2: material   RGBA8   r metallic, g roughness, b occlusion, a emissive
+//  - depth         D32     sampled to reconstruct the view space position
+//
+// Point & spot lights are drawn as instanced light volumes (bounding
+// icosahedrons, back faces only so the camera can be inside) & accumulated
+// additively in a float light buffer. The composition adds the ambient &
+// emissive terms & writes the background where nothing was drawn.
+//
+// The normal target is rendered to & the light buffer is blended to as RGBA32F:
+// on GLES 3 this needs EXT_color_buffer_float & EXT_float_blend, the renderer
+// can't be created without them.
+//
+use super::*;
+use crate::rs_math3d::*;
+use std::sync::*;
+
+////////////////////////////////////////////////////////////////////////////////
+// Lights
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug)]
+pub struct PointLight {
+    /// world space
+    pub position: Vec3f,
+    /// the light has no effect past the radius
+    pub radius: f32,
+    pub color: Vec3f,
+    pub intensity: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct SpotLight {
+    /// world space
+    pub position: Vec3f,
+    /// world space
+    pub direction: Vec3f,
+    pub radius: f32,
+    /// full intensity inside this half angle (radians)
+    pub inner_angle: f32,
+    /// no light past this half angle (radians)
+    pub outer_angle: f32,
+    pub color: Vec3f,
+    pub intensity: f32,
+}
+
+#[derive(Clone, Debug)]
+pub enum Light {
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Shaders
+////////////////////////////////////////////////////////////////////////////////
+
+///
+/// outputs of the geometry pass pixel shaders
+///
+pub static GBUFFER_OUTPUTS: &'static str = "
+layout(location = 0) out vec4 gAlbedo;
+layout(location = 1) out vec4 gNormal;
+layout(location = 2) out vec4 gMaterial;
+";
+
+crate::render_data! {
+    vertex VolumeVertex {
+        position    : Vec3f,
+    }
+
+    vertex LightInstance {
+        light_position  : Vec4f,
+        light_color     : Vec4f,
+        light_direction : Vec4f,
+        light_cone      : Vec4f,
+    }
+
+    uniforms LightUniforms {
+        projection      : Mat4f,
+        inv_projection  : Mat4f,
+    }
+
+    uniforms CompositeUniforms {
+        ambient         : Vec4f,
+        background      : Vec4f,
+    }
+}
+
+// the icosahedron faces are at 0.795 from the center: scale it to contain the sphere
+static LIGHT_VERTEX_SHADER: &'static str = "
+#version 300 es
+precision highp float;
+in          vec3        position;
+in          vec4        light_position;
+in          vec4        light_color;
+in          vec4        light_direction;
+in          vec4        light_cone;
+
+uniform     mat4        projection;
+
+flat out    vec4        vPosition;
+flat out    vec4        vColor;
+flat out    vec4        vDirection;
+flat out    vec4        vCone;
+
+void main() {
+    vPosition = light_position;
+    vColor = light_color;
+    vDirection = light_direction;
+    vCone = light_cone;
+    gl_Position = projection * vec4(light_position.xyz + position * light_position.w * 1.26, 1.0);
+}";
+
+static LIGHT_PIXEL_SHADER: &'static str = "
+#version 300 es
+precision highp float;
+
+flat in     vec4        vPosition;
+flat in     vec4        vColor;
+flat in     vec4        vDirection;
+flat in     vec4        vCone;
+
+uniform     sampler2D   uAlbedo;
+uniform     sampler2D   uNormal;
+uniform     sampler2D   uMaterial;
+uniform     sampler2D   uDepth;
+uniform     mat4        inv_projection;
+
+out         vec4        fragColor;
+
+void main() {
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    float depth = texelFetch(uDepth, p, 0).r;
+    if (depth == 1.0) {
+        discard;
+    }
+
+    vec2 ndc = gl_FragCoord.xy / vec2(textureSize(uDepth, 0)) * 2.0 - 1.0;
+    vec4 v = inv_projection * vec4(ndc, depth * 2.0 - 1.0, 1.0);
+    vec3 pos = v.xyz / v.w;
+
+    vec3 to_light = vPosition.xyz - pos;
+    float dist = length(to_light);
+    if (dist > vPosition.w) {
+        discard;
+    }
+    vec3 l = to_light / dist;
+
+    // smooth window to zero at the radius
+    float falloff = clamp(1.0 - pow(dist / vPosition.w, 4.0), 0.0, 1.0);
+    float attenuation = falloff * falloff / (dist * dist + 1.0);
+
+    // spot cone: (cos outer, cos inner), a point light has cos outer = -1
+    attenuation *= smoothstep(vDirection.w, vCone.x, dot(-l, vDirection.xyz));
+
+    vec3 albedo = texelFetch(uAlbedo, p, 0).rgb;
+    vec3 n = normalize(texelFetch(uNormal, p, 0).xyz);
+    vec4 material = texelFetch(uMaterial, p, 0);
+    float metallic = material.r;
+    float roughness = material.g;
+
+    vec3 view = normalize(-pos);
+    vec3 h = normalize(l + view);
+    float ndl = max(dot(n, l), 0.0);
+    float shininess = exp2(10.0 * (1.0 - roughness) + 1.0);
+    vec3 spec_color = mix(vec3(0.04), albedo, metallic);
+    vec3 specular = spec_color * pow(max(dot(n, h), 0.0), shininess) * (shininess + 8.0) / 25.1327;
+    vec3 diffuse = albedo * (1.0 - metallic) / 3.14159265;
+
+    fragColor = vec4(vColor.rgb * vColor.a * attenuation * ndl * (diffuse + specular), 1.0);
+}";
+
+static COMPOSITE_PIXEL_SHADER: &'static str = "
+#version 300 es
+precision highp float;
+
+in highp    vec2        vUV;
+
+uniform     sampler2D   uAlbedo;
+uniform     sampler2D   uMaterial;
+uniform     sampler2D   uLight;
+uniform     sampler2D   uDepth;
+uniform     vec4        ambient;
+uniform     vec4        background;
+
+out         vec4        fragColor;
+
+void main() {
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    if (texelFetch(uDepth, p, 0).r == 1.0) {
+        fragColor = background;
+        return;
+    }
+
+    vec3 albedo = texelFetch(uAlbedo, p, 0).rgb;
+    vec4 material = texelFetch(uMaterial, p, 0);
+    vec3 c = texelFetch(uLight, p, 0).rgb
+           + albedo * ambient.rgb * material.b
+           + albedo * material.a;
+    fragColor = vec4(c, 1.0);
+}";
+
+////////////////////////////////////////////////////////////////////////////////
+// Renderer
+////////////////////////////////////////////////////////////////////////////////
+
+fn icosahedron() -> (Vec<VolumeVertex>, Vec<u32>) {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let points = [
+        Vec3f::new(-1.0, t, 0.0),
+        Vec3f::new(1.0, t, 0.0),
+        Vec3f::new(-1.0, -t, 0.0),
+        Vec3f::new(1.0, -t, 0.0),
+        Vec3f::new(0.0, -1.0, t),
+        Vec3f::new(0.0, 1.0, t),
+        Vec3f::new(0.0, -1.0, -t),
+        Vec3f::new(0.0, 1.0, -t),
+        Vec3f::new(t, 0.0, -1.0),
+        Vec3f::new(t, 0.0, 1.0),
+        Vec3f::new(-t, 0.0, -1.0),
+        Vec3f::new(-t, 0.0, 1.0),
+    ];
+    let faces: [[u32; 3]; 20] = [
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    let vertices = points
+        .iter()
+        .map(|p| VolumeVertex {
+            position: Vec3f::normalize(p),
+        })
+        .collect();
+
+    // counter clockwise seen from the outside
+    let mut indices = Vec::with_capacity(60);
+    for f in faces.iter() {
+        let (a, b, c) = (
+            points[f[0] as usize],
+            points[f[1] as usize],
+            points[f[2] as usize],
+        );
+        let n = Vec3f::cross(&(b - a), &(c - a));
+        if Vec3f::dot(&n, &(a + b + c)) < 0.0 {
+            indices.extend_from_slice(&[f[0], f[2], f[1]]);
+        } else {
+            indices.extend_from_slice(f);
+        }
+    }
+    (vertices, indices)
+}
+
+struct GBuffer {
+    frame_buffer: FrameBufferPtr,
+    albedo: TexturePtr,
+    normal: TexturePtr,
+    material: TexturePtr,
+    depth: TexturePtr,
+    light_frame_buffer: FrameBufferPtr,
+    light: TexturePtr,
+}
+
+impl GBuffer {
+    fn new(driver: &mut DriverPtr, width: usize, height: usize) -> Self {
+        let mut texture = |pixel_format: PixelFormat, label: &str| {
+            driver
+                .create_texture(TextureDesc {
+                    sampler_desc: SamplerDesc::default(width, height)
+                        .with_pixel_format(pixel_format)
+                        .with_wrap_mode(WrapMode::ClampToEdge),
+                    payload: None,
+                    label: Some(String::from(label)),
+                })
+                .unwrap()
+        };
+
+        let albedo = texture(
+            PixelFormat::RGBA8(MinMagFilter::default()),
+            "gbuffer: albedo",
+        );
+        let normal = texture(PixelFormat::RGBA32F, "gbuffer: normal");
+        let material = texture(
+            PixelFormat::RGBA8(MinMagFilter::default()),
+            "gbuffer: material",
+        );
+        let depth = texture(PixelFormat::D32, "gbuffer: depth");
+        let light = texture(PixelFormat::RGBA32F, "gbuffer: light");
+
+        let frame_buffer = driver
+            .create_frame_buffer(FrameBufferDesc {
+                color_attachements: [
+                    Some(SurfaceAttachment::Texture(albedo.clone())),
+                    Some(SurfaceAttachment::Texture(normal.clone())),
+                    Some(SurfaceAttachment::Texture(material.clone())),
+                    None,
+                ],
+                depth_stencil_attachement: SurfaceAttachment::Texture(depth.clone()),
+                label: Some(String::from("gbuffer")),
+            })
+            .unwrap();
+
+        // the light volumes are not depth tested: the depth attachment is only
+        // there for completeness
+        let light_depth = driver
+            .create_render_target(RenderTargetDesc {
+                sampler_desc: SamplerDesc::default(width, height)
+                    .with_pixel_format(PixelFormat::D16),
+                sample_count: 0,
+                label: Some(String::from("gbuffer: light depth")),
+            })
+            .unwrap();
+        let light_frame_buffer = driver
+            .create_frame_buffer(FrameBufferDesc {
+                color_attachements: [
+                    Some(SurfaceAttachment::Texture(light.clone())),
+                    None,
+                    None,
+                    None,
+                ],
+                depth_stencil_attachement: SurfaceAttachment::RenderTarget(light_depth),
+                label: Some(String::from("gbuffer: light")),
+            })
+            .unwrap();
+
+        Self {
+            frame_buffer,
+            albedo,
+            normal,
+            material,
+            depth,
+            light_frame_buffer,
+            light,
+        }
+    }
+}
+
+///
+/// record the scene in `geometry_pass` with pixel shaders writing the
+/// `GBUFFER_OUTPUTS`, then `render` lights & composes it into the output
+///
+pub struct DeferredRenderer {
+    /// rgb ambient light
+    pub ambient: Vec3f,
+    /// written where no geometry was drawn
+    pub background: Vec4f,
+
+    width: usize,
+    height: usize,
+    gbuffer: GBuffer,
+
+    volume_vb: DeviceBufferPtr,
+    volume_ib: DeviceBufferPtr,
+    instances: DeviceBufferPtr,
+    instance_capacity: usize,
+    light_pipeline: PipelinePtr,
+
+    composite: FullscreenEffect<CompositeUniforms>,
+}
+
+impl DeferredRenderer {
+    ///
+    /// returns None if the driver can't render & blend to float targets
+    ///
+    pub fn new(driver: &mut DriverPtr, width: usize, height: usize) -> Option<Self> {
+        let caps = driver.get_caps();
+        if !caps.float_render_targets || !caps.float_blending {
+            return None;
+        }
+
+        let (vertices, indices) = icosahedron();
+        let volume_vb = driver
+            .create_device_buffer(DeviceBufferDesc::vertex(Usage::Static(Arc::new(vertices))))
+            .unwrap();
+        let volume_ib = driver
+            .create_device_buffer(DeviceBufferDesc::index(Usage::Static(Arc::new(indices))))
+            .unwrap();
+
+        let instance_capacity = 64;
+        let instances = Self::create_instances(driver, instance_capacity);
+
+        let shader = driver
+            .create_shader(ShaderDesc {
+                vertex_shader: String::from(LIGHT_VERTEX_SHADER),
+                pixel_shader: String::from(LIGHT_PIXEL_SHADER),
+
+                vertex_attributes: vec![
+                    VolumeVertex::get_attribute_names(),
+                    LightInstance::get_attribute_names(),
+                ],
+                vertex_uniforms: vec![String::from("projection")],
+                vertex_surfaces: Vec::new(),
+
+                pixel_uniforms: vec![String::from("inv_projection")],
+                pixel_surfaces: vec![
+                    String::from("uAlbedo"),
+                    String::from("uNormal"),
+                    String::from("uMaterial"),
+                    String::from("uDepth"),
+                ],
+                label: Some(String::from("deferred: lights")),
+            })
+            .unwrap();
+
+        let additive = Blend {
+            src_factor_rgb: BlendFactor::One,
+            src_factor_alpha: BlendFactor::One,
+            dst_factor_rgb: BlendFactor::One,
+            dst_factor_alpha: BlendFactor::One,
+        };
+
+        // back faces only (clockwise from the camera), without depth test
+        let light_pipeline = driver
+            .create_pipeline(PipelineDesc {
+                primitive_type: PrimitiveType::Triangles,
+                shader,
+                buffer_layouts: vec![
+                    VertexBufferLayout {
+                        buffer_id: 0,
+                        vertex_attributes: VolumeVertex::get_attribute_descriptors(),
+                        stride: VolumeVertex::stride(),
+                        divisor: 0,
+                    },
+                    VertexBufferLayout {
+                        buffer_id: 1,
+                        vertex_attributes: LightInstance::get_attribute_descriptors(),
+                        stride: LightInstance::stride(),
+                        divisor: 1,
+                    },
+                ],
+                uniform_descs: LightUniforms::get_uniform_descriptors(),
+                index_type: IndexType::UInt32,
+                face_winding: FaceWinding::CW,
+                cull_mode: CullMode::Winding,
+                depth_write: false,
+                depth_test: false,
+                blend: BlendOp::Add(additive),
+                polygon_offset: PolygonOffset::None,
+                primitive_restart: false,
+                label: Some(String::from("deferred: lights")),
+            })
+            .unwrap();
+
+        let quad = ScreenQuad::new(driver);
+        let composite = FullscreenEffect::new(
+            driver,
+            &quad,
+            FullscreenEffectDesc::new(COMPOSITE_PIXEL_SHADER)
+                .with_input("uAlbedo")
+                .with_input("uMaterial")
+                .with_input("uLight")
+                .with_input("uDepth")
+                .with_label("deferred: composite"),
+        )
+        .unwrap();
+
+        Some(Self {
+            ambient: Vec3f::new(0.03, 0.03, 0.03),
+            background: Vec4f::new(0.0, 0.0, 0.0, 1.0),
+            width,
+            height,
+            gbuffer: GBuffer::new(driver, width, height),
+            volume_vb,
+            volume_ib,
+            instances,
+            instance_capacity,
+            light_pipeline,
+            composite,
+        })
+    }
+
+    fn create_instances(driver: &mut DriverPtr, capacity: usize) -> DeviceBufferPtr {
+        driver
+            .create_device_buffer(DeviceBufferDesc::vertex(
+                Usage::new_dynamic::<LightInstance>(capacity),
+            ))
+            .unwrap()
+    }
+
+    ///
+    /// reallocate the G-buffer if the size changed
+    ///
+    pub fn resize(&mut self, driver: &mut DriverPtr, width: usize, height: usize) {
+        if self.width == width && self.height == height {
+            return;
+        }
+        self.gbuffer = GBuffer::new(driver, width, height);
+        self.width = width;
+        self.height = height;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn gbuffer(&self) -> &FrameBufferPtr {
+        &self.gbuffer.frame_buffer
+    }
+
+    pub fn albedo_texture(&self) -> &TexturePtr {
+        &self.gbuffer.albedo
+    }
+
+    pub fn normal_texture(&self) -> &TexturePtr {
+        &self.gbuffer.normal
+    }
+
+    pub fn material_texture(&self) -> &TexturePtr {
+        &self.gbuffer.material
+    }
+
+    pub fn depth_texture(&self) -> &TexturePtr {
+        &self.gbuffer.depth
+    }
+
+    ///
+    /// a pass clearing the G-buffer, to record the scene into
+    ///
+    pub fn geometry_pass(&self) -> Pass {
+        let clear = ColorPassAction::ClearValue(ClearValue::Float4(Vec4f::new(0.0, 0.0, 0.0, 0.0)));
+        Pass::new(
+            self.width,
+            self.height,
+            Some(self.gbuffer.frame_buffer.clone()),
+            [clear, clear, clear, ColorPassAction::DontCare],
+            DepthPassAction::Clear(1.0),
+        )
+        .with_label("deferred: geometry")
+    }
+
+    fn light_instance(light: &Light, view: &Mat4f) -> LightInstance {
+        let to_view = |p: &Vec3f| {
+            let v = *view * Vec4f::new(p.x, p.y, p.z, 1.0);
+            Vec3f::new(v.x, v.y, v.z)
+        };
+        let dir_to_view = |d: &Vec3f| {
+            let v = *view * Vec4f::new(d.x, d.y, d.z, 0.0);
+            Vec3f::normalize(&Vec3f::new(v.x, v.y, v.z))
+        };
+
+        match light {
+            Light::Point(l) => {
+                let p = to_view(&l.position);
+                LightInstance {
+                    light_position: Vec4f::new(p.x, p.y, p.z, l.radius),
+                    light_color: Vec4f::new(l.color.x, l.color.y, l.color.z, l.intensity),
+                    // cos outer = -1: no cone
+                    light_direction: Vec4f::new(0.0, 0.0, -1.0, -2.0),
+                    light_cone: Vec4f::new(-1.0, 0.0, 0.0, 0.0),
+                }
+            }
+            Light::Spot(l) => {
+                let p = to_view(&l.position);
+                let d = dir_to_view(&l.direction);
+                LightInstance {
+                    light_position: Vec4f::new(p.x, p.y, p.z, l.radius),
+                    light_color: Vec4f::new(l.color.x, l.color.y, l.color.z, l.intensity),
+                    light_direction: Vec4f::new(d.x, d.y, d.z, l.outer_angle.cos()),
+                    light_cone: Vec4f::new(l.inner_angle.cos(), 0.0, 0.0, 0.0),
+                }
+            }
+        }
+    }
+
+    ///
+    /// accumulate `lights` over the recorded G-buffer & compose the result into
+    /// `output` (the screen if None). `view` & `projection` are the matrices of
+    /// the geometry pass
+    ///
+    pub fn render(
+        &mut self,
+        driver: &mut DriverPtr,
+        view: &Mat4f,
+        projection: &Mat4f,
+        lights: &[Light],
+        output: Option<&FrameBufferPtr>,
+    ) {
+        if lights.len() > self.instance_capacity {
+            self.instance_capacity = lights.len().next_power_of_two();
+            self.instances = Self::create_instances(driver, self.instance_capacity);
+        }
+
+        let mut pass = Pass::new(
+            self.width,
+            self.height,
+            Some(self.gbuffer.light_frame_buffer.clone()),
+            [
+                ColorPassAction::ClearValue(ClearValue::Float4(Vec4f::new(0.0, 0.0, 0.0, 0.0))),
+                ColorPassAction::DontCare,
+                ColorPassAction::DontCare,
+                ColorPassAction::DontCare,
+            ],
+            DepthPassAction::DontCare,
+        )
+        .with_label("deferred: lights");
+
+        if !lights.is_empty() {
+            let instances: Vec<LightInstance> = lights
+                .iter()
+                .map(|l| Self::light_instance(l, view))
+                .collect();
+            pass.queue
+                .update_device_buffer(&self.instances, 0, Arc::new(instances));
+
+            let bindings = Bindings {
+                vertex_buffers: vec![self.volume_vb.clone(), self.instances.clone()],
+                index_buffer: Some(self.volume_ib.clone()),
+
+                vertex_images: Vec::new(),
+                pixel_images: vec![
+                    self.gbuffer.albedo.clone(),
+                    self.gbuffer.normal.clone(),
+                    self.gbuffer.material.clone(),
+                    self.gbuffer.depth.clone(),
+                ],
+            };
+            let uniforms = LightUniforms {
+                projection: *projection,
+                inv_projection: projection.inverse(),
+            };
+            pass.queue.draw(
+                &self.light_pipeline,
+                &bindings,
+                Arc::new(GenPayload::from(uniforms)),
+                20,
+                lights.len() as u32,
+            );
+        }
+        driver.render_pass(&mut pass);
+
+        let mut pass = Pass::new(
+            self.width,
+            self.height,
+            output.cloned(),
+            [
+                ColorPassAction::DontCare,
+                ColorPassAction::DontCare,
+                ColorPassAction::DontCare,
+                ColorPassAction::DontCare,
+            ],
+            DepthPassAction::DontCare,
+        )
+        .with_label("deferred: composite");
+        self.composite.render(
+            &mut pass,
+            CompositeUniforms {
+                ambient: Vec4f::new(self.ambient.x, self.ambient.y, self.ambient.z, 1.0),
+                background: self.background,
+            },
+            &[
+                self.gbuffer.albedo.clone(),
+                self.gbuffer.material.clone(),
+                self.gbuffer.light.clone(),
+                self.gbuffer.depth.clone(),
+            ],
+        );
+        driver.render_pass(&mut pass);
+    }
+}
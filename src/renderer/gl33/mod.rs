@@ -0,0 +1,172 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+//
+// Desktop OpenGL 3.3 core backend
+//
+// The driver sources are shared with the GLES 3 backend, only the GL bindings
+// and the shader translation differ. Desktop GL entry points are loaded at
+// runtime: libGL can't be linked along with libGLESv2.
+//
+#[allow(dead_code, unused_imports, non_snake_case, clippy::all)]
+mod gl {
+    mod bindings {
+        include!(concat!(env!("OUT_DIR"), "/gl33_bindings.rs"));
+    }
+
+    pub use self::bindings::*;
+    use self::types::*;
+
+    ////////////////////////////////////////////////////////////////////////////
+    // GLES only entry points
+    ////////////////////////////////////////////////////////////////////////////
+    pub unsafe fn ClearDepthf(depth: GLfloat) {
+        ClearDepth(depth as GLdouble)
+    }
+
+    /// invalidation is only a hint & needs GL 4.3: ignore it
+    pub unsafe fn InvalidateFramebuffer(
+        _target: GLenum,
+        _num_attachments: GLsizei,
+        _attachments: *const GLenum,
+    ) {
+    }
+
+    /// ARB_ES2_compatibility is core in GL 4.1, fallback to IEEE 754 otherwise
+    pub unsafe fn GetShaderPrecisionFormat(
+        shader_type: GLenum,
+        precision_type: GLenum,
+        range: *mut GLint,
+        precision: *mut GLint,
+    ) {
+        if bindings::GetShaderPrecisionFormat::is_loaded() {
+            return bindings::GetShaderPrecisionFormat(
+                shader_type,
+                precision_type,
+                range,
+                precision,
+            );
+        }
+
+        let (r, p) = match precision_type {
+            LOW_FLOAT | MEDIUM_FLOAT | HIGH_FLOAT => ([127, 127], 23),
+            _ => ([31, 30], 0),
+        };
+        *range = r[0];
+        *range.add(1) = r[1];
+        *precision = p;
+    }
+}
+
+mod backend {
+    use super::gl;
+    use super::gl::types::*;
+    use std::borrow::Cow;
+
+    /// desktop GL exposes the KHR_debug entry points without suffix
+    pub const KHR_SUFFIX: &str = "";
+
+    ///
+    /// the fixed index restart needs GL 4.3 or ARB_ES3_compatibility, the
+    /// restart index is set explicitly instead
+    ///
+    pub const PRIMITIVE_RESTART: GLenum = gl::PRIMITIVE_RESTART;
+
+    pub unsafe fn primitive_restart_index(index: GLuint) {
+        gl::PrimitiveRestartIndex(index)
+    }
+
+    /// float surfaces are renderable & blendable since GL 3.0
+    pub fn float_render_targets() -> (bool, bool) {
+        (true, true)
+    }
+
+    ///
+    /// translate GLSL ES 3.00 to GLSL 3.30 core: replace the version directive
+    /// and drop the default precision statements. Lines are kept in place so
+    /// the compilation logs still point to the original source lines
+    ///
+    pub fn translate_shader(src: &str) -> Cow<'_, str> {
+        let mut out = String::with_capacity(src.len());
+        for line in src.lines() {
+            let l = line.trim();
+            if l.starts_with("#version") {
+                out.push_str("#version 330 core");
+            } else if !(l.starts_with("precision ") && l.ends_with(';')) {
+                out.push_str(line);
+            }
+            out.push('\n');
+        }
+        Cow::Owned(out)
+    }
+}
+
+#[path = "../gles3/debug.rs"]
+mod debug;
+#[path = "../gles3/readback.rs"]
+#[allow(dead_code)]
+mod readback;
+#[path = "../gles3/renderer.rs"]
+#[allow(dead_code)]
+mod renderer;
+#[path = "../gles3/state.rs"]
+mod state;
+
+use super::DriverPtr;
+use std::ffi::c_void;
+
+///
+/// load the GL 3.3 core entry points through `loader` & create the driver.
+/// A GL 3.3 core context must be current on the calling thread
+///
+pub fn get_driver_with_loader<F: FnMut(&str) -> *const c_void>(mut loader: F) -> DriverPtr {
+    gl::load_with(|name| loader(name));
+    readback::get_driver_with_loader(loader)
+}
+
+#[cfg(all(test, feature = "scene"))]
+mod tests {
+    use crate::scene::utility_mesh::LINE_PIXEL_SHADER;
+
+    #[test]
+    fn translate_shader_keeps_lines() {
+        let src: Vec<&str> = LINE_PIXEL_SHADER.lines().collect();
+        assert_eq!(
+            &src[0..3],
+            ["", "#version 300 es", "precision highp float;"]
+        );
+
+        let translated = super::backend::translate_shader(LINE_PIXEL_SHADER);
+        let out: Vec<&str> = translated.lines().collect();
+        assert_eq!(out.len(), src.len());
+        assert_eq!(&out[0..3], ["", "#version 330 core", ""]);
+        assert_eq!(&out[3..], &src[3..]);
+        assert!(out.contains(&"in lowp     vec4        v_color;"));
+    }
+}
@@ -0,0 +1,213 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+use super::super::*;
+use super::backend;
+use super::gl;
+use super::gl::types::*;
+
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+
+////////////////////////////////////////////////////////////////////////////////
+// KHR_debug
+//
+// The KHR entry points are not exported by libGLESv2, they have to be fetched
+// from the context with a proc address loader (eglGetProcAddress & friends).
+// Desktop GL exposes the same entry points without the KHR suffix
+////////////////////////////////////////////////////////////////////////////////
+
+type ObjectLabelFn = extern "system" fn(GLenum, GLuint, GLsizei, *const GLchar);
+type PushDebugGroupFn = extern "system" fn(GLenum, GLuint, GLsizei, *const GLchar);
+type PopDebugGroupFn = extern "system" fn();
+type DebugMessageCallbackFn = extern "system" fn(GLDEBUGPROCKHR, *const c_void);
+
+pub(crate) struct KhrDebug {
+    object_label: ObjectLabelFn,
+    push_debug_group: PushDebugGroupFn,
+    pop_debug_group: PopDebugGroupFn,
+    debug_message_callback: DebugMessageCallbackFn,
+    max_label_length: usize,
+}
+
+pub(super) fn has_extension(name: &str) -> bool {
+    unsafe {
+        let mut count: GLint = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+        (0..count as GLuint).any(|i| {
+            let ext = gl::GetStringi(gl::EXTENSIONS, i);
+            !ext.is_null() && CStr::from_ptr(ext as *const c_char).to_bytes() == name.as_bytes()
+        })
+    }
+}
+
+impl KhrDebug {
+    ///
+    /// returns None if the context does not expose KHR_debug
+    ///
+    pub(crate) fn load(loader: &mut dyn FnMut(&str) -> *const c_void) -> Option<Self> {
+        if !has_extension("GL_KHR_debug") {
+            return None;
+        }
+
+        let object_label = loader(&format!("glObjectLabel{}", backend::KHR_SUFFIX));
+        let push_debug_group = loader(&format!("glPushDebugGroup{}", backend::KHR_SUFFIX));
+        let pop_debug_group = loader(&format!("glPopDebugGroup{}", backend::KHR_SUFFIX));
+        let debug_message_callback =
+            loader(&format!("glDebugMessageCallback{}", backend::KHR_SUFFIX));
+
+        if object_label.is_null()
+            || push_debug_group.is_null()
+            || pop_debug_group.is_null()
+            || debug_message_callback.is_null()
+        {
+            return None;
+        }
+
+        let mut max_label_length: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_LABEL_LENGTH_KHR, &mut max_label_length);
+            Some(Self {
+                object_label: std::mem::transmute::<*const c_void, ObjectLabelFn>(object_label),
+                push_debug_group: std::mem::transmute::<*const c_void, PushDebugGroupFn>(
+                    push_debug_group,
+                ),
+                pop_debug_group: std::mem::transmute::<*const c_void, PopDebugGroupFn>(
+                    pop_debug_group,
+                ),
+                debug_message_callback: std::mem::transmute::<*const c_void, DebugMessageCallbackFn>(
+                    debug_message_callback,
+                ),
+                max_label_length: max_label_length as usize,
+            })
+        }
+    }
+
+    fn clamp<'a>(&self, label: &'a str) -> &'a str {
+        let mut len = usize::min(label.len(), self.max_label_length.saturating_sub(1));
+        while !label.is_char_boundary(len) {
+            len -= 1;
+        }
+        &label[..len]
+    }
+
+    pub(crate) fn object_label(&self, identifier: GLenum, name: GLuint, label: &str) {
+        let label = self.clamp(label);
+        (self.object_label)(
+            identifier,
+            name,
+            label.len() as GLsizei,
+            label.as_ptr() as *const GLchar,
+        )
+    }
+
+    pub(crate) fn push_group(&self, label: &str) {
+        let label = self.clamp(label);
+        (self.push_debug_group)(
+            gl::DEBUG_SOURCE_APPLICATION_KHR,
+            0,
+            label.len() as GLsizei,
+            label.as_ptr() as *const GLchar,
+        )
+    }
+
+    pub(crate) fn pop_group(&self) {
+        (self.pop_debug_group)()
+    }
+
+    ///
+    /// `callback` must stay at the same address until the output is disabled
+    ///
+    pub(crate) fn set_callback(&self, callback: Option<*mut DebugCallback>) {
+        unsafe {
+            match callback {
+                Some(cb) => {
+                    gl::Enable(gl::DEBUG_OUTPUT_KHR);
+                    gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS_KHR);
+                    (self.debug_message_callback)(Some(debug_output), cb as *const c_void);
+                }
+                None => {
+                    (self.debug_message_callback)(None, std::ptr::null());
+                    gl::Disable(gl::DEBUG_OUTPUT_SYNCHRONOUS_KHR);
+                    gl::Disable(gl::DEBUG_OUTPUT_KHR);
+                }
+            }
+        }
+    }
+}
+
+extern "system" fn debug_output(
+    _source: GLenum,
+    gltype: GLenum,
+    id: GLuint,
+    severity: GLenum,
+    length: GLsizei,
+    message: *const GLchar,
+    user_param: *mut c_void,
+) {
+    let msg_type = match gltype {
+        gl::DEBUG_TYPE_ERROR_KHR => DebugMessageType::Error,
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR_KHR => DebugMessageType::DeprecatedBehavior,
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR_KHR => DebugMessageType::UndefinedBehavior,
+        gl::DEBUG_TYPE_PORTABILITY_KHR => DebugMessageType::Portability,
+        gl::DEBUG_TYPE_PERFORMANCE_KHR => DebugMessageType::Performance,
+        gl::DEBUG_TYPE_MARKER_KHR => DebugMessageType::Marker,
+        gl::DEBUG_TYPE_PUSH_GROUP_KHR | gl::DEBUG_TYPE_POP_GROUP_KHR => DebugMessageType::Group,
+        _ => DebugMessageType::Other,
+    };
+
+    let severity = match severity {
+        gl::DEBUG_SEVERITY_HIGH_KHR => DebugSeverity::High,
+        gl::DEBUG_SEVERITY_MEDIUM_KHR => DebugSeverity::Medium,
+        gl::DEBUG_SEVERITY_LOW_KHR => DebugSeverity::Low,
+        _ => DebugSeverity::Notification,
+    };
+
+    let message = unsafe {
+        if length < 0 {
+            CStr::from_ptr(message as *const c_char)
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            let bytes = std::slice::from_raw_parts(message as *const u8, length as usize);
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+    };
+
+    let callback = unsafe { &mut *(user_param as *mut DebugCallback) };
+    let msg = DebugMessage {
+        id,
+        msg_type,
+        severity,
+        message,
+    };
+
+    // a panic must not unwind into the GL implementation, the panic hook already reported it
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| callback(&msg)));
+}
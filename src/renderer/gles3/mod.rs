@@ -27,7 +27,42 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 //
+use super::gl;
+
+mod backend {
+    use super::gl;
+    use super::gl::types::*;
+    use std::borrow::Cow;
+
+    /// suffix of the KHR_debug entry points
+    pub const KHR_SUFFIX: &str = "KHR";
+
+    /// GLES 3 restarts at the maximum value of the index type
+    pub const PRIMITIVE_RESTART: GLenum = gl::PRIMITIVE_RESTART_FIXED_INDEX;
+
+    pub unsafe fn primitive_restart_index(_index: GLuint) {}
+
+    ///
+    /// rendering & blending to float surfaces are extensions in GLES 3,
+    /// returns (float_render_targets, float_blending)
+    ///
+    pub fn float_render_targets() -> (bool, bool) {
+        let render = super::debug::has_extension("GL_EXT_color_buffer_float");
+        (
+            render,
+            render && super::debug::has_extension("GL_EXT_float_blend"),
+        )
+    }
+
+    /// shaders are written in GLSL ES 3.00: nothing to translate
+    pub fn translate_shader(src: &str) -> Cow<'_, str> {
+        Cow::Borrowed(src)
+    }
+}
+
+mod debug;
 mod readback;
 mod renderer;
+mod state;
 
-pub use readback::get_driver;
+pub use readback::{get_driver, get_driver_with_loader};
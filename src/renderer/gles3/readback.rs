@@ -27,11 +27,13 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 //
-use super::super::gl::types::*;
 use super::super::*;
-use crate::renderer::gles3::*;
+use super::gl;
+use super::gl::types::*;
+use super::*;
 use crate::rs_math3d::*;
 
+use super::debug::*;
 use super::renderer::*;
 use std::ffi::c_void;
 use std::sync::*;
@@ -62,8 +64,9 @@ pub(crate) struct ReadbackDriver {
     f_pipeline: PipelinePtr, // floating point pipeline
     vb: DeviceBufferPtr,
     ib: DeviceBufferPtr,
+    leak_report: bool,
 
-    gles_driver: DriverPtrInternal,
+    gles_driver: Arc<Mutex<Gles3Driver>>,
 }
 
 static COPY_VERTEX_SHADER: &'static str = "
@@ -110,7 +113,7 @@ void main() {
 }";
 
 impl ReadbackDriver {
-    pub fn new(driver: &mut DriverPtrInternal) -> Self {
+    pub fn new(driver: &Arc<Mutex<Gles3Driver>>) -> Self {
         let orig = driver.clone();
         let mut drv_lock = driver.lock();
         let driver = drv_lock.as_deref_mut().unwrap();
@@ -136,10 +139,12 @@ impl ReadbackDriver {
 
         let quad_index: Vec<u32> = vec![0, 1, 2, 2, 3, 0];
 
-        let vb_desc = DeviceBufferDesc::Vertex(Usage::Static(Arc::new(quad_verts)));
+        let vb_desc = DeviceBufferDesc::vertex(Usage::Static(Arc::new(quad_verts)))
+            .with_label("readback quad vertices");
         let vb = driver.create_device_buffer(vb_desc).unwrap();
 
-        let ib_desc = DeviceBufferDesc::Index(Usage::Static(Arc::new(quad_index)));
+        let ib_desc = DeviceBufferDesc::index(Usage::Static(Arc::new(quad_index)))
+            .with_label("readback quad indices");
         let ib = driver.create_device_buffer(ib_desc).unwrap();
 
         Self {
@@ -149,11 +154,46 @@ impl ReadbackDriver {
             f_fb: None, // Self::create_fb(driver, OrigSurfaceType::Float),
             u_pipeline: Self::create_copy_pipeline(driver, OrigSurfaceType::UInt),
             f_pipeline: Self::create_copy_pipeline(driver, OrigSurfaceType::Float),
+            leak_report: false,
 
             gles_driver: orig,
         }
     }
 
+    ///
+    /// resources created by the readback driver itself, excluded from the leak report
+    ///
+    fn own_resources(&self) -> Vec<(ResourceType, usize)> {
+        let mut res = vec![
+            (ResourceType::DeviceBuffer, self.vb.res_id()),
+            (ResourceType::DeviceBuffer, self.ib.res_id()),
+        ];
+
+        for pipe in [&self.u_pipeline, &self.f_pipeline].iter() {
+            res.push((ResourceType::Pipeline, pipe.res_id()));
+            res.push((ResourceType::Shader, pipe.desc().shader.res_id()));
+        }
+
+        for fb in [&self.u_fb, &self.f_fb].iter().filter_map(|fb| fb.as_ref()) {
+            res.push((ResourceType::FrameBuffer, fb.res_id()));
+            let desc = fb.desc();
+            let attachments = desc
+                .color_attachements
+                .iter()
+                .filter_map(|a| a.as_ref())
+                .chain(std::iter::once(&desc.depth_stencil_attachement));
+            for attachment in attachments {
+                match attachment {
+                    SurfaceAttachment::Texture(t) => res.push((ResourceType::Texture, t.res_id())),
+                    SurfaceAttachment::RenderTarget(rt) => {
+                        res.push((ResourceType::RenderTarget, rt.res_id()))
+                    }
+                }
+            }
+        }
+        res
+    }
+
     fn create_copy_shader(
         driver: &mut dyn Driver,
         orig_surface_type: OrigSurfaceType,
@@ -171,6 +211,7 @@ impl ReadbackDriver {
 
             pixel_uniforms: Vec::new(),
             pixel_surfaces: Vec::from([String::from("uTexture")]),
+            label: Some(String::from("readback copy")),
         };
 
         driver.create_shader(shader_desc).unwrap()
@@ -199,6 +240,8 @@ impl ReadbackDriver {
             depth_test: true,
             blend: BlendOp::None,
             polygon_offset: PolygonOffset::None,
+            primitive_restart: false,
+            label: Some(String::from("readback copy")),
         };
 
         driver.create_pipeline(model_pipeline_desc).unwrap()
@@ -224,6 +267,7 @@ impl ReadbackDriver {
         let color_buffer_desc = TextureDesc {
             sampler_desc: color_tex_desc,
             payload: None,
+            label: Some(String::from("readback color")),
         };
         let color_buffer = driver.create_texture(color_buffer_desc).unwrap();
 
@@ -232,6 +276,7 @@ impl ReadbackDriver {
         let depth_buffer_desc = RenderTargetDesc {
             sampler_desc: depth_tex_desc,
             sample_count: 0,
+            label: Some(String::from("readback depth")),
         };
         let depth_buffer = driver.create_render_target(depth_buffer_desc).unwrap();
 
@@ -243,6 +288,7 @@ impl ReadbackDriver {
                 None,
             ],
             depth_stencil_attachement: SurfaceAttachment::RenderTarget(depth_buffer),
+            label: Some(String::from("readback")),
         };
 
         driver.create_frame_buffer(fb_desc).unwrap()
@@ -433,8 +479,7 @@ impl ReadbackDriver {
     ) -> Option<ReadbackPayload> {
         unsafe {
             let mut l = self.gles_driver.lock();
-            let me2 = l.as_deref_mut().unwrap();
-            let driver = &mut *(me2 as *mut dyn Driver as *mut Gles3Driver);
+            let driver = l.as_deref_mut().unwrap();
 
             match self.f_fb {
                 Some(_) => (),
@@ -473,6 +518,8 @@ impl ReadbackDriver {
 
                     let flags = gl::DEPTH_BUFFER_BIT | gl::COLOR_BUFFER_BIT;
                     gl::ClearDepthf(1.0);
+                    // the last pipeline may have left depth writes off
+                    driver.state().depth_mask(true);
 
                     let draw_buffer: [GLenum; 4] =
                         [gl::COLOR_ATTACHMENT0, gl::NONE, gl::NONE, gl::NONE];
@@ -569,12 +616,24 @@ impl Driver for ReadbackDriver {
         driver.create_frame_buffer(desc)
     }
 
+    fn create_query(&mut self, desc: QueryDesc) -> Option<QueryPtr> {
+        let mut lock = self.gles_driver.lock();
+        let driver = lock.as_mut().unwrap();
+        driver.create_query(desc)
+    }
+
     fn delete_resource(&mut self, resource_type: &ResourceType, res_id: usize) {
         let mut lock = self.gles_driver.lock();
         let driver = lock.as_mut().unwrap();
         driver.delete_resource(resource_type, res_id)
     }
 
+    fn set_label(&mut self, resource_type: &ResourceType, res_id: usize, label: &str) {
+        let mut lock = self.gles_driver.lock();
+        let driver = lock.as_mut().unwrap();
+        driver.set_label(resource_type, res_id, label)
+    }
+
     fn render_pass(&mut self, pass: &mut Pass) {
         let mut lock = self.gles_driver.lock();
         let driver = lock.as_mut().unwrap();
@@ -591,15 +650,76 @@ impl Driver for ReadbackDriver {
     ) -> Option<ReadbackPayload> {
         self.read_surface(surface, x, y, w, h)
     }
+
+    fn query_result(&mut self, query: &QueryPtr) -> Option<bool> {
+        let mut lock = self.gles_driver.lock();
+        let driver = lock.as_mut().unwrap();
+        driver.query_result(query)
+    }
+
+    fn stats(&self) -> DriverStats {
+        let lock = self.gles_driver.lock();
+        let driver = lock.unwrap();
+        driver.stats()
+    }
+
+    fn end_frame(&mut self) {
+        let mut lock = self.gles_driver.lock();
+        let driver = lock.as_mut().unwrap();
+        driver.end_frame()
+    }
+
+    fn live_resources(&self) -> Vec<LiveResource> {
+        let own = self.own_resources();
+        let lock = self.gles_driver.lock();
+        let driver = lock.unwrap();
+        driver
+            .live_resources()
+            .into_iter()
+            .filter(|r| !own.contains(&(r.res_type, r.res_id)))
+            .collect()
+    }
+
+    fn set_leak_report(&mut self, enabled: bool) {
+        self.leak_report = enabled
+    }
+
+    fn set_debug_callback(&mut self, callback: Option<DebugCallback>) {
+        let mut lock = self.gles_driver.lock();
+        let driver = lock.as_mut().unwrap();
+        driver.set_debug_callback(callback)
+    }
 }
 
 impl Drop for ReadbackDriver {
     fn drop(&mut self) {
+        if self.leak_report {
+            let live = self.live_resources();
+            if !live.is_empty() {
+                let mut lock = self.gles_driver.lock();
+                let driver = lock.as_deref_mut().unwrap();
+                driver.report_leaks("ReadBackDriver", &live);
+                return;
+            }
+        }
         println!("ReadBackDriver dropped - All is good!")
     }
 }
 
 pub fn get_driver() -> DriverPtr {
+    create_driver(None)
+}
+
+///
+/// same as `get_driver`, with the KHR_debug entry points fetched through
+/// `loader` (labels, debug groups & debug output)
+///
+pub fn get_driver_with_loader<F: FnMut(&str) -> *const c_void>(mut loader: F) -> DriverPtr {
+    let debug = KhrDebug::load(&mut loader);
+    create_driver(debug)
+}
+
+fn create_driver(debug: Option<KhrDebug>) -> DriverPtr {
     unsafe {
         let mut range: [GLint; 2] = [0, 0];
         let mut precision = 0;
@@ -655,6 +775,6 @@ pub fn get_driver() -> DriverPtr {
         );
         println!("lowp int range: {:?} - prselfecision: {}", range, precision);
     }
-    let mut drv = renderer::Gles3Driver::new();
-    DriverPtr::from(Arc::new(Mutex::new(ReadbackDriver::new(&mut drv))))
+    let drv = renderer::Gles3Driver::new(debug);
+    DriverPtr::from(Arc::new(Mutex::new(ReadbackDriver::new(&drv))))
 }
@@ -27,14 +27,17 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 //
-use super::super::gl::types::*;
 use super::super::*;
+use super::backend;
+use super::debug::*;
+use super::gl;
+use super::gl::types::*;
+use super::state::*;
 use crate::rs_math3d::*;
 
-use core::ops::Index;
+use core::ops::{Index, IndexMut};
 use std::collections::VecDeque;
 use std::ffi::c_void;
-use std::ops::DerefMut;
 use std::sync::*;
 
 fn color4b_to_color4f(col: Color4b) -> Vec4f {
@@ -279,6 +282,7 @@ fn setup_uniforms(
 struct GLDeviceBuffer {
     gl_id: GLuint,
     desc: DeviceBufferDesc,
+    label: Option<String>,
 }
 
 impl Drop for GLDeviceBuffer {
@@ -289,6 +293,8 @@ impl Drop for GLDeviceBuffer {
 
 struct GLTexture {
     gl_id: GLuint,
+    bytes: usize,
+    label: Option<String>,
 }
 
 impl Drop for GLTexture {
@@ -301,6 +307,8 @@ impl Drop for GLTexture {
 
 struct GLRenderTarget {
     gl_id: GLuint,
+    bytes: usize,
+    label: Option<String>,
 }
 
 impl Drop for GLRenderTarget {
@@ -392,27 +400,7 @@ impl GLPixelFormat for PixelFormat {
     }
 
     fn gl_pixel_size(&self) -> usize {
-        match &self {
-            PixelFormat::RGB8U => 3,
-            PixelFormat::RGBA8U => 4,
-            PixelFormat::R8U => 1,
-            PixelFormat::RGB32U => 3 * 4,
-            PixelFormat::RGBA32U => 4 * 4,
-            PixelFormat::R32U => 4,
-
-            PixelFormat::RGB32F => 3 * 4,
-            PixelFormat::RGBA32F => 4 * 4,
-            PixelFormat::R32F => 4,
-
-            PixelFormat::D16 => 2,
-            PixelFormat::D32 => 4,
-            PixelFormat::D24S8 => 4,
-            PixelFormat::D32S8 => 5,
-
-            PixelFormat::RGB8(_) => 3,
-            PixelFormat::RGBA8(_) => 4,
-            PixelFormat::R8(_) => 1,
-        }
+        self.pixel_size()
     }
 }
 
@@ -426,6 +414,8 @@ struct GLShader {
 
     pixel_uniforms: Vec<(String, GLuint)>,
     pixel_surfaces: Vec<(String, GLuint)>,
+
+    label: Option<String>,
 }
 
 impl Drop for GLShader {
@@ -436,11 +426,13 @@ impl Drop for GLShader {
 
 struct GLPipeline {
     desc: PipelineDesc,
+    label: Option<String>,
 }
 
 struct GLFrameBuffer {
     gl_id: GLuint,
     desc: FrameBufferDesc,
+    label: Option<String>,
 }
 
 impl Drop for GLFrameBuffer {
@@ -449,6 +441,33 @@ impl Drop for GLFrameBuffer {
     }
 }
 
+struct GLQuery {
+    gl_id: GLuint,
+    desc: QueryDesc,
+    // a result can only be fetched once the query ran
+    issued: bool,
+    label: Option<String>,
+}
+
+impl Drop for GLQuery {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteQueries(1, &self.gl_id as *const GLuint) }
+    }
+}
+
+trait GLQueryType {
+    fn gl_target(&self) -> GLenum;
+}
+
+impl GLQueryType for QueryType {
+    fn gl_target(&self) -> GLenum {
+        match self {
+            QueryType::AnySamplesPassed => gl::ANY_SAMPLES_PASSED,
+            QueryType::AnySamplesPassedConservative => gl::ANY_SAMPLES_PASSED_CONSERVATIVE,
+        }
+    }
+}
+
 trait GLBlendFactor {
     fn gl_blend_factor(&self) -> GLenum;
 }
@@ -475,7 +494,7 @@ impl GLBlendFactor for BlendFactor {
     }
 }
 ////////////////////////////////////////////////////////////////////////////////
-/// Resource Container
+// Resource Container
 ////////////////////////////////////////////////////////////////////////////////
 
 struct ResourceContainer<T> {
@@ -515,6 +534,13 @@ impl<T> ResourceContainer<T> {
             None => panic!("Deleting an already deleted object"),
         }
     }
+
+    fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.res
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, r)| r.as_ref().map(|r| (idx, r)))
+    }
 }
 
 impl<T> Index<usize> for ResourceContainer<T> {
@@ -528,6 +554,15 @@ impl<T> Index<usize> for ResourceContainer<T> {
     }
 }
 
+impl<T> IndexMut<usize> for ResourceContainer<T> {
+    fn index_mut(&mut self, idx: usize) -> &mut Self::Output {
+        match &mut self.res[idx] {
+            Some(t) => t,
+            None => panic!("Accessing invalid object index"),
+        }
+    }
+}
+
 pub struct NullPayload {
     size: usize,
 }
@@ -546,8 +581,14 @@ impl Drop for NullPayload {
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-/// Driver
+// Driver
 ////////////////////////////////////////////////////////////////////////////////
+// GL calls requested on other threads, replayed on the GL thread
+enum PendingCall {
+    Delete(ResourceType, usize),
+    Label(ResourceType, usize, String),
+}
+
 pub(crate) struct Gles3Driver {
     device_buffers: ResourceContainer<GLDeviceBuffer>,
     textures: ResourceContainer<GLTexture>,
@@ -555,14 +596,29 @@ pub(crate) struct Gles3Driver {
     shaders: ResourceContainer<GLShader>,
     pipelines: ResourceContainer<GLPipeline>,
     framebuffers: ResourceContainer<GLFrameBuffer>,
+    queries: ResourceContainer<GLQuery>,
 
     caps: DriverCaps,
+    stats: DriverStats,
+    last_pipeline: Option<usize>,
+
+    debug: Option<KhrDebug>,
+    debug_callback: Option<Box<DebugCallback>>,
+
+    // read & draw frame buffers used to copy textures
+    copy_frame_buffers: Option<(GLuint, GLuint)>,
+
+    state: GLStateCache,
+
+    // resources dropped or labeled on other threads are handled on the GL thread
+    gl_thread: std::thread::ThreadId,
+    pending_calls: Vec<PendingCall>,
 
     self_ptr: Option<Weak<Mutex<dyn Driver>>>,
 }
 
 impl Gles3Driver {
-    pub(crate) fn new() -> DriverPtrInternal {
+    pub(crate) fn new(debug: Option<KhrDebug>) -> Arc<Mutex<Gles3Driver>> {
         let mut max_rt_size = 0;
         let mut max_tex_size = 0;
 
@@ -572,7 +628,9 @@ impl Gles3Driver {
         }
 
         let min_surface_size = std::cmp::min(4096, std::cmp::min(max_rt_size, max_tex_size));
-        let me: DriverPtrInternal = Arc::new_cyclic(|_| {
+        let (float_render_targets, float_blending) = backend::float_render_targets();
+        let me = Arc::new_cyclic(|me: &Weak<Mutex<Gles3Driver>>| {
+            let self_ptr: Weak<Mutex<dyn Driver>> = me.clone();
             let s = Self {
                 device_buffers: ResourceContainer::new(),
                 textures: ResourceContainer::new(),
@@ -580,25 +638,32 @@ impl Gles3Driver {
                 shaders: ResourceContainer::new(),
                 pipelines: ResourceContainer::new(),
                 framebuffers: ResourceContainer::new(),
+                queries: ResourceContainer::new(),
 
                 caps: DriverCaps {
                     max_2d_surface_dimension: Dimensioni::new(min_surface_size, min_surface_size),
+                    float_render_targets,
+                    float_blending,
                 },
+                stats: DriverStats::default(),
+                last_pipeline: None,
+
+                debug,
+                debug_callback: None,
+
+                copy_frame_buffers: None,
+
+                state: GLStateCache::new(),
 
-                self_ptr: None,
+                gl_thread: std::thread::current().id(),
+                pending_calls: Vec::new(),
+
+                self_ptr: Some(self_ptr),
             };
             Mutex::new(s)
         });
 
-        let me_clone = me.clone();
-        let mut l = me_clone.lock();
-        let me2 = l.as_mut().unwrap();
-        unsafe {
-            let me3 =
-                &mut *(me2.deref_mut() as &mut dyn Driver as *mut dyn Driver as *mut Gles3Driver);
-            me3.self_ptr = Some(Arc::downgrade(&me));
-            me3.initialize();
-        }
+        me.lock().unwrap().initialize();
         me
     }
 
@@ -606,6 +671,46 @@ impl Gles3Driver {
         self.framebuffers[fb_id].gl_id
     }
 
+    ///
+    /// the cached GL state, for GL calls made outside of the render passes
+    ///
+    pub(crate) fn state(&mut self) -> &mut GLStateCache {
+        &mut self.state
+    }
+
+    ///
+    /// send a driver message to the debug callback, or to stderr when there is none
+    ///
+    pub(crate) fn report(&mut self, severity: DebugSeverity, message: String) {
+        match &mut self.debug_callback {
+            Some(callback) => callback(&DebugMessage {
+                id: 0,
+                msg_type: DebugMessageType::Other,
+                severity,
+                message,
+            }),
+            None => eprintln!("{}", message),
+        }
+    }
+
+    pub(crate) fn report_leaks(&mut self, owner: &str, live: &[LiveResource]) {
+        if live.is_empty() {
+            return;
+        }
+
+        let mut message = format!("{} dropped with {} live resource(s):", owner, live.len());
+        for r in live {
+            message += &format!(
+                "\n    {:?} #{} \"{}\" - {} bytes",
+                r.res_type,
+                r.res_id,
+                r.label.as_deref().unwrap_or(""),
+                r.bytes
+            );
+        }
+        self.report(DebugSeverity::High, message);
+    }
+
     fn initialize(&mut self) {
         //self.read_back_state    = Some(ReadbackState::new(self));
         unsafe {
@@ -616,21 +721,15 @@ impl Gles3Driver {
     }
 
     fn buffer_type_to_gl(bt: &DeviceBufferDesc) -> GLenum {
-        match bt {
-            DeviceBufferDesc::Vertex(_) => gl::ARRAY_BUFFER,
-            DeviceBufferDesc::Index(_) => gl::ELEMENT_ARRAY_BUFFER,
-            DeviceBufferDesc::Pixel(_) => gl::PIXEL_UNPACK_BUFFER,
+        match bt.buffer_type {
+            DeviceBufferType::Vertex => gl::ARRAY_BUFFER,
+            DeviceBufferType::Index => gl::ELEMENT_ARRAY_BUFFER,
+            DeviceBufferType::Pixel => gl::PIXEL_UNPACK_BUFFER,
         }
     }
 
     fn buffer_usage_to_gl(bt: &DeviceBufferDesc) -> GLenum {
-        let usage = match bt {
-            DeviceBufferDesc::Vertex(u)
-            | DeviceBufferDesc::Index(u)
-            | DeviceBufferDesc::Pixel(u) => u,
-        };
-
-        match usage {
+        match bt.usage {
             Usage::Static(_) => gl::STATIC_DRAW,
             Usage::Streamed(_) => gl::STREAM_DRAW,
             Usage::Dynamic(_) => gl::DYNAMIC_DRAW,
@@ -638,13 +737,7 @@ impl Gles3Driver {
     }
 
     fn buffer_data(bt: &DeviceBufferDesc) -> Option<*const u8> {
-        let usage = match bt {
-            DeviceBufferDesc::Vertex(u)
-            | DeviceBufferDesc::Index(u)
-            | DeviceBufferDesc::Pixel(u) => u,
-        };
-
-        match usage {
+        match &bt.usage {
             Usage::Static(b) => Some(b.ptr()),
             Usage::Streamed(_) => None,
             Usage::Dynamic(_) => None,
@@ -652,22 +745,16 @@ impl Gles3Driver {
     }
 
     fn erase_buffer_data(bt: &DeviceBufferDesc) -> DeviceBufferDesc {
-        let usage = match bt {
-            DeviceBufferDesc::Vertex(u)
-            | DeviceBufferDesc::Index(u)
-            | DeviceBufferDesc::Pixel(u) => u,
-        };
-
-        let usage = match usage {
+        let usage = match &bt.usage {
             Usage::Static(p) => Usage::Static(Arc::new(NullPayload { size: p.size() })),
             Usage::Streamed(s) => Usage::Streamed(*s),
             Usage::Dynamic(s) => Usage::Dynamic(*s),
         };
 
-        match bt {
-            DeviceBufferDesc::Vertex(_) => DeviceBufferDesc::Vertex(usage),
-            DeviceBufferDesc::Index(_) => DeviceBufferDesc::Index(usage),
-            DeviceBufferDesc::Pixel(_) => DeviceBufferDesc::Pixel(usage),
+        DeviceBufferDesc {
+            buffer_type: bt.buffer_type,
+            usage,
+            label: bt.label.clone(),
         }
     }
 
@@ -675,6 +762,7 @@ impl Gles3Driver {
         TextureDesc {
             sampler_desc: desc.sampler_desc.clone(),
             payload: None,
+            label: desc.label.clone(),
         }
     }
 
@@ -746,6 +834,16 @@ impl Gles3Driver {
                             );
                         }
                     }
+
+                    if desc.mip_maps > 1 {
+                        gl::TexParameteri(
+                            gl::TEXTURE_2D,
+                            gl::TEXTURE_MAX_LEVEL,
+                            (desc.mip_maps - 1) as GLint,
+                        );
+                        gl::GenerateMipmap(gl::TEXTURE_2D);
+                        Self::check_gl_error();
+                    }
                 }
             }
         }
@@ -759,19 +857,30 @@ impl Gles3Driver {
         }
     }
 
-    fn create_render_target(desc: &SamplerDesc, _sample_size: usize) -> GLuint {
+    fn create_render_target(desc: &SamplerDesc, sample_count: usize) -> GLuint {
         unsafe {
             let mut res: GLuint = 0;
             gl::GenRenderbuffers(1, &mut res);
             match &desc.image_type {
                 SamplerType::Sampler2D(pch_x, pch_y) => {
                     gl::BindRenderbuffer(gl::RENDERBUFFER, res);
-                    gl::RenderbufferStorage(
-                        gl::RENDERBUFFER,
-                        desc.pixel_format.gl_internal_format(),
-                        pch_x.size as GLsizei,
-                        pch_y.size as GLsizei,
-                    );
+                    if sample_count > 1 {
+                        // multisampled, resolved with a blit
+                        gl::RenderbufferStorageMultisample(
+                            gl::RENDERBUFFER,
+                            sample_count as GLsizei,
+                            desc.pixel_format.gl_internal_format(),
+                            pch_x.size as GLsizei,
+                            pch_y.size as GLsizei,
+                        );
+                    } else {
+                        gl::RenderbufferStorage(
+                            gl::RENDERBUFFER,
+                            desc.pixel_format.gl_internal_format(),
+                            pch_x.size as GLsizei,
+                            pch_y.size as GLsizei,
+                        );
+                    }
                     if gl::GetError() != gl::NO_ERROR {
                         panic!("Error creating render target");
                     }
@@ -808,6 +917,7 @@ impl Gles3Driver {
                 return None;
             }
 
+            let src = backend::translate_shader(src);
             let c_str = std::ffi::CString::new(src.as_bytes()).unwrap();
             gl::ShaderSource(shader, 1, &(c_str.as_ptr() as *const i8), core::ptr::null());
             gl::CompileShader(shader);
@@ -843,11 +953,57 @@ impl Gles3Driver {
         }
     }
 
+    ///
+    /// estimated size of a surface, including its mip chain
+    ///
+    fn sampler_bytes(desc: &SamplerDesc, sample_count: usize) -> usize {
+        let (mut w, mut h) = match &desc.image_type {
+            SamplerType::Sampler2D(pch_x, pch_y) => (pch_x.size, pch_y.size),
+        };
+        let mut bytes = 0;
+        for _ in 0..usize::max(desc.mip_maps, 1) {
+            bytes += w * h * desc.pixel_format.gl_pixel_size();
+            w = usize::max(w / 2, 1);
+            h = usize::max(h / 2, 1);
+        }
+        bytes * usize::max(sample_count, 1)
+    }
+
+    fn resource_label(&self, res_type: ResourceType, res_id: usize) -> Option<String> {
+        match res_type {
+            ResourceType::DeviceBuffer => self.device_buffers[res_id].label.clone(),
+            ResourceType::Texture => self.textures[res_id].label.clone(),
+            ResourceType::RenderTarget => self.render_targets[res_id].label.clone(),
+            ResourceType::Shader => self.shaders[res_id].label.clone(),
+            ResourceType::Pipeline => self.pipelines[res_id].label.clone(),
+            ResourceType::FrameBuffer => self.framebuffers[res_id].label.clone(),
+            ResourceType::Query => self.queries[res_id].label.clone(),
+        }
+    }
+
+    fn label_object(&self, identifier: GLenum, gl_id: GLuint, label: &Option<String>) {
+        match (&self.debug, label) {
+            (Some(debug), Some(label)) => debug.object_label(identifier, gl_id, label),
+            _ => (),
+        }
+    }
+
+    fn resource_bytes(&self, res_type: ResourceType, res_id: usize) -> usize {
+        match res_type {
+            ResourceType::DeviceBuffer => self.device_buffers[res_id].desc.size(),
+            ResourceType::Texture => self.textures[res_id].bytes,
+            ResourceType::RenderTarget => self.render_targets[res_id].bytes,
+            _ => 0,
+        }
+    }
+
     fn delete_device_buffer(&mut self, buff: usize) {
+        self.state.device_buffer_deleted(buff);
         self.device_buffers.remove(buff)
     }
 
     fn delete_texture(&mut self, surf: usize) {
+        self.state.texture_deleted(self.textures[surf].gl_id);
         self.textures.remove(surf)
     }
 
@@ -856,10 +1012,12 @@ impl Gles3Driver {
     }
 
     fn delete_shader(&mut self, shader: usize) {
+        self.state.program_deleted(self.shaders[shader].gl_id);
         self.shaders.remove(shader)
     }
 
     fn delete_pipeline(&mut self, pipe: usize) {
+        self.state.pipeline_deleted(pipe);
         self.pipelines.remove(pipe)
     }
 
@@ -867,6 +1025,273 @@ impl Gles3Driver {
         self.framebuffers.remove(pass)
     }
 
+    ///
+    /// invalidate the selected attachments of the bound frame buffer
+    ///
+    fn invalidate_attachments(fb_desc: Option<&FrameBufferDesc>, colors: [bool; 4], depth: bool) {
+        let mut attachments: Vec<GLenum> = Vec::new();
+        match fb_desc {
+            Some(desc) => {
+                for idx in 0..4 {
+                    if colors[idx] && desc.color_attachements[idx].is_some() {
+                        attachments.push(gl::COLOR_ATTACHMENT0 + idx as GLenum);
+                    }
+                }
+                if depth {
+                    attachments.push(Self::depth_attachment_point(
+                        &desc.depth_stencil_attachement.pixel_format(),
+                    ));
+                }
+            }
+            None => {
+                if colors[0] {
+                    attachments.push(gl::COLOR);
+                }
+                if depth {
+                    attachments.push(gl::DEPTH);
+                    attachments.push(gl::STENCIL);
+                }
+            }
+        }
+
+        if !attachments.is_empty() {
+            unsafe {
+                gl::InvalidateFramebuffer(
+                    gl::FRAMEBUFFER,
+                    attachments.len() as GLsizei,
+                    attachments.as_ptr(),
+                );
+            }
+            Self::check_gl_error();
+        }
+    }
+
+    fn depth_attachment_point(format: &PixelFormat) -> GLenum {
+        if format.has_stencil() {
+            gl::DEPTH_STENCIL_ATTACHMENT
+        } else {
+            gl::DEPTH_ATTACHMENT
+        }
+    }
+
+    fn flush_pending_calls(&mut self) {
+        if std::thread::current().id() == self.gl_thread {
+            let pending = std::mem::replace(&mut self.pending_calls, Vec::new());
+            for call in pending {
+                match call {
+                    PendingCall::Delete(resource_type, res_id) => {
+                        self.delete_resource(&resource_type, res_id)
+                    }
+                    PendingCall::Label(resource_type, res_id, label) => {
+                        self.set_label(&resource_type, res_id, &label)
+                    }
+                }
+            }
+        }
+    }
+
+    fn begin_query(&mut self, query: &QueryPtr) {
+        let gl_query = &mut self.queries[query.res_id()];
+        let first_use = !gl_query.issued;
+        gl_query.issued = true;
+
+        let gl_id = gl_query.gl_id;
+        unsafe { gl::BeginQuery(gl_query.desc.query_type.gl_target(), gl_id) };
+
+        // the query object is only created by its first use
+        if first_use {
+            self.label_object(gl::QUERY_KHR, gl_id, &self.queries[query.res_id()].label);
+        }
+    }
+
+    fn frame_buffer_gl_id(&self, fb: &Option<FrameBufferPtr>) -> GLuint {
+        match fb {
+            Some(fb) => self.framebuffers[fb.res_id()].gl_id,
+            None => 0,
+        }
+    }
+
+    fn blit(&mut self, cmd: &BlitCommand, pass_fb: GLuint) {
+        let src_fb = self.frame_buffer_gl_id(&cmd.src);
+        let dst_fb = self.frame_buffer_gl_id(&cmd.dst);
+        let default_read_buffer = match cmd.src {
+            Some(_) => gl::COLOR_ATTACHMENT0,
+            None => gl::BACK,
+        };
+
+        unsafe {
+            // blits are scissored
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, src_fb);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, dst_fb);
+
+            let mut mask = 0;
+            if cmd.color {
+                match cmd.src {
+                    Some(_) => gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + cmd.src_attachment as GLenum),
+                    None => gl::ReadBuffer(gl::BACK),
+                }
+                mask |= gl::COLOR_BUFFER_BIT;
+            }
+
+            if cmd.depth {
+                mask |= gl::DEPTH_BUFFER_BIT;
+                match &cmd.src {
+                    Some(fb)
+                        if fb
+                            .desc()
+                            .depth_stencil_attachement
+                            .pixel_format()
+                            .has_stencil() =>
+                    {
+                        mask |= gl::STENCIL_BUFFER_BIT
+                    }
+                    _ => (),
+                }
+            }
+
+            let filter = match cmd.filter {
+                BlitFilter::Nearest => gl::NEAREST,
+                BlitFilter::Linear => gl::LINEAR,
+            };
+
+            let (s, d) = (&cmd.src_rect, &cmd.dst_rect);
+            gl::BlitFramebuffer(
+                s.x,
+                s.y,
+                s.x + s.width,
+                s.y + s.height,
+                d.x,
+                d.y,
+                d.x + d.width,
+                d.y + d.height,
+                mask,
+                filter,
+            );
+            Self::check_gl_error();
+
+            gl::ReadBuffer(default_read_buffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, pass_fb);
+            gl::Enable(gl::SCISSOR_TEST);
+        }
+    }
+
+    fn copy_texture(&mut self, cmd: &CopyTextureCommand, pass_fb: GLuint) {
+        unsafe {
+            let (read_fb, draw_fb) = match self.copy_frame_buffers {
+                Some(fbs) => fbs,
+                None => {
+                    let mut fbs: [GLuint; 2] = [0; 2];
+                    gl::GenFramebuffers(2, fbs.as_mut_ptr());
+                    self.copy_frame_buffers = Some((fbs[0], fbs[1]));
+                    (fbs[0], fbs[1])
+                }
+            };
+
+            let format = &cmd.src.desc().sampler_desc.pixel_format;
+            let (attachment, mask) = if format.is_depth() {
+                (Self::depth_attachment_point(format), gl::DEPTH_BUFFER_BIT)
+            } else {
+                (gl::COLOR_ATTACHMENT0, gl::COLOR_BUFFER_BIT)
+            };
+
+            let src_tex = self.textures[cmd.src.res_id()].gl_id;
+            let dst_tex = self.textures[cmd.dst.res_id()].gl_id;
+
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, read_fb);
+            gl::FramebufferTexture2D(gl::READ_FRAMEBUFFER, attachment, gl::TEXTURE_2D, src_tex, 0);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, draw_fb);
+            gl::FramebufferTexture2D(gl::DRAW_FRAMEBUFFER, attachment, gl::TEXTURE_2D, dst_tex, 0);
+
+            let r = &cmd.src_rect;
+            gl::BlitFramebuffer(
+                r.x,
+                r.y,
+                r.x + r.width,
+                r.y + r.height,
+                cmd.dst_x,
+                cmd.dst_y,
+                cmd.dst_x + r.width,
+                cmd.dst_y + r.height,
+                mask,
+                gl::NEAREST,
+            );
+            Self::check_gl_error();
+
+            // don't keep the textures alive through the copy frame buffers
+            gl::FramebufferTexture2D(gl::READ_FRAMEBUFFER, attachment, gl::TEXTURE_2D, 0, 0);
+            gl::FramebufferTexture2D(gl::DRAW_FRAMEBUFFER, attachment, gl::TEXTURE_2D, 0, 0);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, pass_fb);
+            gl::Enable(gl::SCISSOR_TEST);
+        }
+    }
+
+    unsafe fn clear_color_attachment(
+        idx: usize,
+        action: &ColorPassAction,
+        surf: Option<&SurfaceAttachment>,
+    ) {
+        match action {
+            ColorPassAction::Clear(col) => {
+                let col = *col;
+                match surf {
+                    Some(surf_ref) => match surf_ref.pixel_format().to_orig_surface_type() {
+                        OrigSurfaceType::UInt => {
+                            let i_cols: [GLuint; 4] = [
+                                col.x as GLuint,
+                                col.y as GLuint,
+                                col.z as GLuint,
+                                col.w as GLuint,
+                            ];
+                            gl::ClearBufferuiv(
+                                gl::COLOR as GLenum,
+                                idx as GLint,
+                                i_cols.as_ptr() as *const GLuint,
+                            );
+                        }
+                        OrigSurfaceType::Float => {
+                            let float_col = color4b_to_color4f(col);
+                            let f_cols: [GLfloat; 4] =
+                                [float_col.x, float_col.y, float_col.z, float_col.w];
+                            gl::ClearBufferfv(
+                                gl::COLOR as GLenum,
+                                idx as GLint,
+                                f_cols.as_ptr() as *const GLfloat,
+                            );
+                        }
+                    },
+                    _ => {
+                        // assume that it's the default fixed point frame buffer
+                        let float_col = color4b_to_color4f(col);
+                        let f_cols: [GLfloat; 4] =
+                            [float_col.x, float_col.y, float_col.z, float_col.w];
+                        gl::ClearBufferfv(
+                            gl::COLOR as GLenum,
+                            idx as GLint,
+                            f_cols.as_ptr() as *const GLfloat,
+                        );
+                    }
+                }
+            }
+            ColorPassAction::ClearValue(ClearValue::Float4(col)) => {
+                let f_cols: [GLfloat; 4] = [col.x, col.y, col.z, col.w];
+                gl::ClearBufferfv(gl::COLOR as GLenum, idx as GLint, f_cols.as_ptr());
+            }
+            ColorPassAction::ClearValue(ClearValue::UInt4(col)) => {
+                let u_cols: [GLuint; 4] = [col.x, col.y, col.z, col.w];
+                gl::ClearBufferuiv(gl::COLOR as GLenum, idx as GLint, u_cols.as_ptr());
+            }
+            ColorPassAction::ClearValue(ClearValue::Int4(col)) => {
+                let i_cols: [GLint; 4] = [col.x, col.y, col.z, col.w];
+                gl::ClearBufferiv(gl::COLOR as GLenum, idx as GLint, i_cols.as_ptr());
+            }
+            ColorPassAction::Previous | ColorPassAction::DontCare => return,
+        }
+        Self::check_gl_error();
+    }
+
     pub fn check_gl_error() {
         unsafe {
             let error = gl::GetError();
@@ -885,113 +1310,141 @@ impl Gles3Driver {
         instance_count: u32,
     ) {
         unsafe {
+            self.stats.current_frame.draw_calls += 1;
+            if self.last_pipeline != Some(pipe.res_id()) {
+                self.stats.current_frame.pipeline_changes += 1;
+                self.last_pipeline = Some(pipe.res_id());
+            }
+
             let gl_pipe = &self.pipelines[pipe.res_id()];
             let gl_prog = &self.shaders[gl_pipe.desc.shader.res_id()];
+            let state = &mut self.state;
 
             // blend
             match &gl_pipe.desc.blend {
                 BlendOp::Add(blend) | BlendOp::Subtract(blend) => {
-                    gl::Enable(gl::BLEND);
-                    gl::BlendFuncSeparate(
+                    state.blend(true);
+                    state.blend_func(
                         blend.src_factor_rgb.gl_blend_factor(),
                         blend.dst_factor_rgb.gl_blend_factor(),
                         blend.src_factor_alpha.gl_blend_factor(),
                         blend.dst_factor_alpha.gl_blend_factor(),
                     );
                 }
-                _ => gl::Disable(gl::BLEND),
+                _ => state.blend(false),
             }
 
             match &gl_pipe.desc.blend {
-                BlendOp::Add(_) => gl::BlendEquationSeparate(gl::FUNC_ADD, gl::FUNC_ADD),
-                BlendOp::Subtract(_) => {
-                    gl::BlendEquationSeparate(gl::FUNC_SUBTRACT, gl::FUNC_SUBTRACT)
-                }
+                BlendOp::Add(_) => state.blend_equation(gl::FUNC_ADD, gl::FUNC_ADD),
+                BlendOp::Subtract(_) => state.blend_equation(gl::FUNC_SUBTRACT, gl::FUNC_SUBTRACT),
                 BlendOp::ReverseSubtract(_) => {
-                    gl::BlendEquationSeparate(gl::FUNC_REVERSE_SUBTRACT, gl::FUNC_REVERSE_SUBTRACT)
+                    state.blend_equation(gl::FUNC_REVERSE_SUBTRACT, gl::FUNC_REVERSE_SUBTRACT)
                 }
                 _ => (),
             }
 
-            let (gl_prim, gl_elem_count) = match gl_pipe.desc.primitive_type {
-                PrimitiveType::Lines => (gl::LINES, 2 * prim_count),
-                PrimitiveType::LineStrip => (gl::LINE_STRIP, 1 + prim_count),
-                PrimitiveType::Points => (gl::POINTS, prim_count),
-                PrimitiveType::Triangles => (gl::TRIANGLES, 3 * prim_count),
-                PrimitiveType::TriangleStrip => (gl::TRIANGLE_STRIP, 2 + prim_count),
+            let gl_prim = match gl_pipe.desc.primitive_type {
+                PrimitiveType::Lines => gl::LINES,
+                PrimitiveType::LineStrip => gl::LINE_STRIP,
+                PrimitiveType::LineLoop => gl::LINE_LOOP,
+                PrimitiveType::Points => gl::POINTS,
+                PrimitiveType::Triangles => gl::TRIANGLES,
+                PrimitiveType::TriangleStrip => gl::TRIANGLE_STRIP,
+                PrimitiveType::TriangleFan => gl::TRIANGLE_FAN,
             };
+            let gl_elem_count = gl_pipe.desc.element_count(prim_count);
+            let restart_index = match (gl_pipe.desc.primitive_restart, &gl_pipe.desc.index_type) {
+                (true, IndexType::UInt16) => Some(0xFFFF),
+                (true, IndexType::UInt32) => Some(0xFFFF_FFFF),
+                _ => None,
+            };
+            state.primitive_restart(restart_index);
 
             match gl_pipe.desc.cull_mode {
-                CullMode::None => gl::Disable(gl::CULL_FACE),
-                CullMode::Winding => gl::Enable(gl::CULL_FACE),
+                CullMode::None => state.cull_face(false),
+                CullMode::Winding => state.cull_face(true),
             }
 
             match gl_pipe.desc.face_winding {
-                FaceWinding::CCW => gl::CullFace(gl::BACK),
-                FaceWinding::CW => gl::CullFace(gl::FRONT),
+                FaceWinding::CCW => state.cull_face_mode(gl::BACK),
+                FaceWinding::CW => state.cull_face_mode(gl::FRONT),
             }
 
-            if gl_pipe.desc.depth_test {
-                gl::Enable(gl::DEPTH_TEST)
-            } else {
-                gl::Disable(gl::DEPTH_TEST)
-            }
-
-            gl::DepthMask(if gl_pipe.desc.depth_write {
-                gl::TRUE
-            } else {
-                gl::FALSE
-            } as GLboolean);
+            state.depth_test(gl_pipe.desc.depth_test);
+            state.depth_mask(gl_pipe.desc.depth_write);
 
             match gl_pipe.desc.polygon_offset {
-                PolygonOffset::None => {
-                    gl::Disable(gl::POLYGON_OFFSET_FILL);
-                    gl::PolygonOffset(0.0, 0.0)
-                }
+                PolygonOffset::None => state.polygon_offset(None),
                 PolygonOffset::FactorUnits(factor, units) => {
-                    gl::Enable(gl::POLYGON_OFFSET_FILL);
-                    gl::PolygonOffset(factor, units)
+                    state.polygon_offset(Some((factor, units)))
                 }
             }
 
-            gl::UseProgram(gl_prog.gl_id);
-            for (l, layout) in gl_pipe.desc.buffer_layouts.iter().enumerate() {
-                let gl_vb =
-                    &self.device_buffers[bindings.vertex_buffers[layout.buffer_id].res_id()];
-                gl::BindBuffer(gl::ARRAY_BUFFER, gl_vb.gl_id);
-                for (i, a) in layout.vertex_attributes.iter().enumerate() {
-                    let aidx = &gl_prog.vertex_attributes[l][i];
-                    gl::EnableVertexAttribArray(aidx.1);
-                    match a.format() {
-                        VertexFormat::Int
-                        | VertexFormat::Int2
-                        | VertexFormat::Int3
-                        | VertexFormat::Int4
-                        | VertexFormat::UInt
-                        | VertexFormat::UInt2
-                        | VertexFormat::UInt3
-                        | VertexFormat::UInt4 => {
-                            gl::VertexAttribIPointer(
-                                aidx.1,
-                                a.format().gl_elem_count() as GLint,
-                                a.format().gl_elem_type(),
-                                layout.stride as GLint,
-                                a.offset() as *const c_void,
-                            );
-                        }
-                        _ => {
-                            gl::VertexAttribPointer(
-                                aidx.1,
-                                a.format().gl_elem_count() as GLint,
-                                a.format().gl_elem_type(),
-                                a.format().gl_is_normalized(),
-                                layout.stride as GLint,
-                                a.offset() as *const c_void,
-                            );
+            state.use_program(gl_prog.gl_id);
+
+            let device_buffers = &self.device_buffers;
+            let key = VertexArrayKey {
+                pipeline: pipe.res_id(),
+                vertex_buffers: bindings.vertex_buffers.iter().map(|b| b.res_id()).collect(),
+                index_buffer: bindings.index_buffer.as_ref().map(|b| b.res_id()),
+            };
+            let cached = state.vertex_array(key, || {
+                for (l, layout) in gl_pipe.desc.buffer_layouts.iter().enumerate() {
+                    let gl_vb = &device_buffers[bindings.vertex_buffers[layout.buffer_id].res_id()];
+                    gl::BindBuffer(gl::ARRAY_BUFFER, gl_vb.gl_id);
+                    for (i, a) in layout.vertex_attributes.iter().enumerate() {
+                        let aidx = &gl_prog.vertex_attributes[l][i];
+                        gl::EnableVertexAttribArray(aidx.1);
+                        match a.format() {
+                            VertexFormat::Int
+                            | VertexFormat::Int2
+                            | VertexFormat::Int3
+                            | VertexFormat::Int4
+                            | VertexFormat::UInt
+                            | VertexFormat::UInt2
+                            | VertexFormat::UInt3
+                            | VertexFormat::UInt4 => {
+                                gl::VertexAttribIPointer(
+                                    aidx.1,
+                                    a.format().gl_elem_count() as GLint,
+                                    a.format().gl_elem_type(),
+                                    layout.stride as GLint,
+                                    a.offset() as *const c_void,
+                                );
+                            }
+                            _ => {
+                                gl::VertexAttribPointer(
+                                    aidx.1,
+                                    a.format().gl_elem_count() as GLint,
+                                    a.format().gl_elem_type(),
+                                    a.format().gl_is_normalized(),
+                                    layout.stride as GLint,
+                                    a.offset() as *const c_void,
+                                );
+                            }
                         }
+                        gl::VertexAttribDivisor(aidx.1, layout.divisor as GLuint);
                     }
-                    gl::VertexAttribDivisor(aidx.1, layout.divisor as GLuint);
                 }
+
+                match &bindings.index_buffer {
+                    Some(ib) => {
+                        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, device_buffers[ib.res_id()].gl_id)
+                    }
+                    None => (),
+                }
+            });
+
+            if cached {
+                // buffer binds, attribute enables, pointers & divisors
+                let attributes: usize = gl_pipe
+                    .desc
+                    .buffer_layouts
+                    .iter()
+                    .map(|l| 1 + 3 * l.vertex_attributes.len())
+                    .sum();
+                let index_buffer = bindings.index_buffer.is_some() as usize;
+                state.add_saved_calls(attributes + index_buffer);
             }
 
             let mut vertex_pixel_uniforms = gl_prog.vertex_uniforms.clone();
@@ -1004,27 +1457,23 @@ impl Gles3Driver {
 
             for (i, t) in bindings.vertex_images.iter().enumerate() {
                 let location = gl_prog.vertex_surfaces[i].1;
-                gl::ActiveTexture(((gl::TEXTURE0 as usize) + i) as GLenum);
-                gl::BindTexture(gl::TEXTURE_2D, self.textures[t.res_id()].gl_id as GLuint);
-                gl::Uniform1i(location as GLint, i as GLint);
+                state.bind_texture(i, self.textures[t.res_id()].gl_id as GLuint);
+                state.sampler_unit(location as GLint, i as GLint);
             }
 
             let pixel_sampler_offset = bindings.vertex_images.len();
 
             for (i, t) in bindings.pixel_images.iter().enumerate() {
                 let location = gl_prog.pixel_surfaces[i].1;
-                gl::ActiveTexture(((gl::TEXTURE0 as usize) + i + pixel_sampler_offset) as GLenum);
-                gl::BindTexture(gl::TEXTURE_2D, self.textures[t.res_id()].gl_id as GLuint);
-                gl::Uniform1i(location as GLint, (i + pixel_sampler_offset) as GLint);
+                state.bind_texture(
+                    i + pixel_sampler_offset,
+                    self.textures[t.res_id()].gl_id as GLuint,
+                );
+                state.sampler_unit(location as GLint, (i + pixel_sampler_offset) as GLint);
             }
 
             match &bindings.index_buffer {
-                Some(ib) => {
-                    gl::BindBuffer(
-                        gl::ELEMENT_ARRAY_BUFFER,
-                        self.device_buffers[ib.res_id()].gl_id,
-                    );
-
+                Some(_) => {
                     let itype = match gl_pipe.desc.index_type {
                         IndexType::None => panic!(
                             "attempt to bind an index buffer to a pipeline that doesn't support it"
@@ -1054,19 +1503,7 @@ impl Gles3Driver {
                 }
             }
 
-            for l in &gl_prog.vertex_attributes {
-                for v in l {
-                    gl::DisableVertexAttribArray(v.1);
-                }
-            }
-
-            match gl_pipe.desc.polygon_offset {
-                PolygonOffset::None => (),
-                PolygonOffset::FactorUnits(_, _) => {
-                    gl::Disable(gl::POLYGON_OFFSET_FILL);
-                    gl::PolygonOffset(0.0, 0.0)
-                }
-            }
+            self.stats.current_frame.saved_calls += state.take_saved_calls();
         }
     }
 
@@ -1085,10 +1522,8 @@ impl Gles3Driver {
         pl: Arc<dyn Payload>,
     ) {
         unsafe {
-            match self.device_buffers[dev_buf.res_id()].desc {
-                DeviceBufferDesc::Vertex(Usage::Static(_))
-                | DeviceBufferDesc::Index(Usage::Static(_))
-                | DeviceBufferDesc::Pixel(Usage::Static(_)) => {
+            match self.device_buffers[dev_buf.res_id()].desc.usage {
+                Usage::Static(_) => {
                     //return None
                     panic!("trying to update static buffer")
                 }
@@ -1104,11 +1539,9 @@ impl Gles3Driver {
                 )
             }
 
-            let target = match self.device_buffers[dev_buf.res_id()].desc {
-                DeviceBufferDesc::Vertex(_) => gl::ARRAY_BUFFER,
-                DeviceBufferDesc::Index(_) => gl::ELEMENT_ARRAY_BUFFER,
-                DeviceBufferDesc::Pixel(_) => gl::PIXEL_UNPACK_BUFFER,
-            };
+            let target = Self::buffer_type_to_gl(&self.device_buffers[dev_buf.res_id()].desc);
+            // the element array binding belongs to the vertex array
+            self.state.bind_vertex_array(0);
             gl::BindBuffer(
                 target,
                 self.device_buffers[dev_buf.res_id()].gl_id as GLuint,
@@ -1122,6 +1555,7 @@ impl Gles3Driver {
             Self::check_gl_error();
 
             std::ptr::copy_nonoverlapping(pl.ptr() as *mut u8, ptr, pl.size());
+            self.stats.current_frame.uploaded_bytes += pl.size();
 
             assert_eq!(gl::UnmapBuffer(target), gl::TRUE as GLboolean);
             Self::check_gl_error();
@@ -1132,7 +1566,9 @@ impl Gles3Driver {
         // TODO: check payload size and format
         let res_id = dev_buf.res_id();
         let gl_id = self.textures[res_id].gl_id;
+        self.stats.current_frame.uploaded_bytes += pl.size();
         Self::upload_texture(gl_id, &dev_buf.desc().sampler_desc, Some(pl));
+        self.state.texture_bound(gl_id);
     }
 }
 
@@ -1146,6 +1582,8 @@ impl Driver for Gles3Driver {
             let data = Self::buffer_data(&desc);
             let mut buff = 0;
             gl::GenBuffers(1, &mut buff);
+            // the element array binding belongs to the vertex array
+            self.state.bind_vertex_array(0);
             gl::BindBuffer(Self::buffer_type_to_gl(&desc), buff);
             let buff_data = match data {
                 Some(d) => d,
@@ -1159,11 +1597,17 @@ impl Driver for Gles3Driver {
                 Self::buffer_usage_to_gl(&desc),
             );
 
+            self.label_object(gl::BUFFER_KHR, buff, &desc.label);
             let gl_buff = GLDeviceBuffer {
                 gl_id: buff,
                 desc: Self::erase_buffer_data(&desc),
+                label: desc.label.clone(),
             };
             let idx = self.device_buffers.add(gl_buff);
+            self.stats.device_buffers.add(desc.size());
+            if data.is_some() {
+                self.stats.current_frame.uploaded_bytes += desc.size();
+            }
 
             let iptr = self.self_ptr.clone().unwrap().upgrade().unwrap();
 
@@ -1178,9 +1622,20 @@ impl Driver for Gles3Driver {
 
     fn create_texture(&mut self, desc: TextureDesc) -> Option<TexturePtr> {
         let new_desc = Self::erase_texture_data(&desc);
+        let bytes = Self::sampler_bytes(&desc.sampler_desc, 1);
+        if let Some(payload) = &desc.payload {
+            self.stats.current_frame.uploaded_bytes += payload.size();
+        }
         let idx = Self::create_texture(&desc.sampler_desc, desc.payload);
-        let img = GLTexture { gl_id: idx };
+        self.state.texture_bound(idx);
+        self.label_object(gl::TEXTURE, idx, &desc.label);
+        let img = GLTexture {
+            gl_id: idx,
+            bytes,
+            label: desc.label.clone(),
+        };
         let idx = self.textures.add(img);
+        self.stats.textures.add(bytes);
 
         let iptr = self.self_ptr.clone().unwrap().upgrade().unwrap();
 
@@ -1194,8 +1649,15 @@ impl Driver for Gles3Driver {
 
     fn create_render_target(&mut self, desc: RenderTargetDesc) -> Option<RenderTargetPtr> {
         let idx = Self::create_render_target(&desc.sampler_desc, desc.sample_count);
-        let img = GLRenderTarget { gl_id: idx };
+        let bytes = Self::sampler_bytes(&desc.sampler_desc, desc.sample_count);
+        self.label_object(gl::RENDERBUFFER, idx, &desc.label);
+        let img = GLRenderTarget {
+            gl_id: idx,
+            bytes,
+            label: desc.label.clone(),
+        };
         let idx = self.render_targets.add(img);
+        self.stats.render_targets.add(bytes);
 
         let iptr = self.self_ptr.clone().unwrap().upgrade().unwrap();
 
@@ -1351,9 +1813,13 @@ impl Driver for Gles3Driver {
 
                 pixel_uniforms: pixel_uniforms,
                 pixel_surfaces: pixel_surfaces,
+
+                label: desc.label.clone(),
             };
 
+            self.label_object(gl::PROGRAM_KHR, program_object, &desc.label);
             let idx = self.shaders.add(gl_shader);
+            self.stats.shaders.add(0);
 
             let iptr = self.self_ptr.clone().unwrap().upgrade().unwrap();
 
@@ -1367,7 +1833,11 @@ impl Driver for Gles3Driver {
     }
 
     fn create_pipeline(&mut self, desc: PipelineDesc) -> Option<PipelinePtr> {
-        let idx = self.pipelines.add(GLPipeline { desc: desc.clone() });
+        let idx = self.pipelines.add(GLPipeline {
+            desc: desc.clone(),
+            label: desc.label.clone(),
+        });
+        self.stats.pipelines.add(0);
 
         let iptr = self.self_ptr.clone().unwrap().upgrade().unwrap();
 
@@ -1413,12 +1883,14 @@ impl Driver for Gles3Driver {
                 }
             }
 
+            let depth_point =
+                Self::depth_attachment_point(&desc.depth_stencil_attachement.pixel_format());
             match &desc.depth_stencil_attachement {
                 SurfaceAttachment::RenderTarget(ca) => {
                     let gl_id = self.render_targets[ca.res_id()].gl_id;
                     gl::FramebufferRenderbuffer(
                         gl::FRAMEBUFFER,
-                        gl::DEPTH_ATTACHMENT,
+                        depth_point,
                         gl::RENDERBUFFER,
                         gl_id,
                     );
@@ -1427,7 +1899,7 @@ impl Driver for Gles3Driver {
                     let gl_id = self.textures[ca.res_id()].gl_id;
                     gl::FramebufferTexture2D(
                         gl::FRAMEBUFFER,
-                        gl::DEPTH_ATTACHMENT,
+                        depth_point,
                         gl::TEXTURE_2D,
                         gl_id,
                         0,
@@ -1453,10 +1925,13 @@ impl Driver for Gles3Driver {
 
             Self::check_gl_error();
 
+            self.label_object(gl::FRAMEBUFFER, res, &desc.label);
             let idx = self.framebuffers.add(GLFrameBuffer {
                 desc: desc.clone(),
                 gl_id: res,
+                label: desc.label.clone(),
             });
+            self.stats.frame_buffers.add(0);
 
             let iptr = self.self_ptr.clone().unwrap().upgrade().unwrap();
 
@@ -1471,7 +1946,43 @@ impl Driver for Gles3Driver {
         }
     }
 
+    fn create_query(&mut self, desc: QueryDesc) -> Option<QueryPtr> {
+        let mut gl_id: GLuint = 0;
+        unsafe { gl::GenQueries(1, &mut gl_id) };
+
+        let idx = self.queries.add(GLQuery {
+            gl_id,
+            desc: desc.clone(),
+            issued: false,
+            label: desc.label.clone(),
+        });
+        self.stats.queries.add(0);
+
+        let iptr = self.self_ptr.clone().unwrap().upgrade().unwrap();
+        Some(QueryPtr::new(Query::new(
+            ResourceType::Query,
+            idx,
+            desc,
+            Some(iptr),
+        )))
+    }
+
     fn delete_resource(&mut self, resource_type: &ResourceType, res_id: usize) {
+        if std::thread::current().id() != self.gl_thread {
+            // no GL context on this thread
+            self.pending_calls
+                .push(PendingCall::Delete(*resource_type, res_id));
+            return;
+        }
+
+        // the id can be reused, labels of the deleted resource are dropped
+        self.pending_calls.retain(|call| match call {
+            PendingCall::Label(t, i, _) => !(t == resource_type && *i == res_id),
+            PendingCall::Delete(..) => true,
+        });
+
+        let bytes = self.resource_bytes(*resource_type, res_id);
+        self.stats.resource_mut(*resource_type).remove(bytes);
         match resource_type {
             ResourceType::DeviceBuffer => self.delete_device_buffer(res_id),
             ResourceType::Texture => self.delete_texture(res_id),
@@ -1479,10 +1990,62 @@ impl Driver for Gles3Driver {
             ResourceType::Shader => self.delete_shader(res_id),
             ResourceType::Pipeline => self.delete_pipeline(res_id),
             ResourceType::FrameBuffer => self.delete_frame_buffer(res_id),
+            ResourceType::Query => self.queries.remove(res_id),
+        }
+    }
+
+    fn set_label(&mut self, resource_type: &ResourceType, res_id: usize, label: &str) {
+        if std::thread::current().id() != self.gl_thread {
+            // no GL context on this thread
+            self.pending_calls.push(PendingCall::Label(
+                *resource_type,
+                res_id,
+                String::from(label),
+            ));
+            return;
+        }
+
+        let label = Some(String::from(label));
+        match resource_type {
+            ResourceType::DeviceBuffer => {
+                self.label_object(gl::BUFFER_KHR, self.device_buffers[res_id].gl_id, &label);
+                self.device_buffers[res_id].label = label;
+            }
+            ResourceType::Texture => {
+                self.label_object(gl::TEXTURE, self.textures[res_id].gl_id, &label);
+                self.textures[res_id].label = label;
+            }
+            ResourceType::RenderTarget => {
+                self.label_object(gl::RENDERBUFFER, self.render_targets[res_id].gl_id, &label);
+                self.render_targets[res_id].label = label;
+            }
+            ResourceType::Shader => {
+                self.label_object(gl::PROGRAM_KHR, self.shaders[res_id].gl_id, &label);
+                self.shaders[res_id].label = label;
+            }
+            ResourceType::Pipeline => self.pipelines[res_id].label = label,
+            ResourceType::FrameBuffer => {
+                self.label_object(gl::FRAMEBUFFER, self.framebuffers[res_id].gl_id, &label);
+                self.framebuffers[res_id].label = label;
+            }
+            ResourceType::Query => {
+                // labeled on its first use otherwise
+                if self.queries[res_id].issued {
+                    self.label_object(gl::QUERY_KHR, self.queries[res_id].gl_id, &label);
+                }
+                self.queries[res_id].label = label;
+            }
         }
     }
 
     fn render_pass(&mut self, pass: &mut Pass) {
+        self.flush_pending_calls();
+        self.stats.current_frame.passes += 1;
+        self.last_pipeline = None;
+        match (&self.debug, &pass.label) {
+            (Some(debug), Some(label)) => debug.push_group(label),
+            _ => (),
+        }
         unsafe {
             gl::Flush();
             gl::Viewport(0, 0, pass.width as i32, pass.height as i32);
@@ -1500,12 +2063,29 @@ impl Driver for Gles3Driver {
                 }
             }
 
+            // discard the attachments we don't care about
+            let fb_desc = pass
+                .frame_buffer
+                .as_ref()
+                .map(|fb| &self.framebuffers[fb.res_id()].desc);
+            let mut load_colors = [false; 4];
+            for idx in 0..4 {
+                load_colors[idx] = match pass.color_actions[idx] {
+                    ColorPassAction::DontCare => true,
+                    _ => false,
+                };
+            }
+            let load_depth = match pass.depth_action {
+                DepthPassAction::DontCare => true,
+                _ => false,
+            };
+            Self::invalidate_attachments(fb_desc, load_colors, load_depth);
+
             // set the draw buffers
-            match &pass.frame_buffer {
-                Some(fb) => {
-                    let fb_ref = &self.framebuffers[fb.res_id()];
+            match fb_desc {
+                Some(fb_desc) => {
                     let mut draw_buffer: [GLenum; 4] = [gl::NONE, gl::NONE, gl::NONE, gl::NONE];
-                    for (idx, attach) in fb_ref.desc.color_attachements.iter().enumerate() {
+                    for (idx, attach) in fb_desc.color_attachements.iter().enumerate() {
                         match attach {
                             Some(_) => {
                                 draw_buffer[idx] = gl::COLOR_ATTACHMENT0 + (idx as GLenum);
@@ -1518,62 +2098,17 @@ impl Driver for Gles3Driver {
                     Self::check_gl_error();
 
                     for idx in 0..4 {
-                        match pass.color_actions[idx] {
-                            ColorPassAction::Clear(col) => {
-                                let surf = &fb_ref.desc.color_attachements[idx];
-                                match surf.as_ref() {
-                                    Some(surf_ref) => {
-                                        match surf_ref.pixel_format().to_orig_surface_type() {
-                                            OrigSurfaceType::UInt => {
-                                                let i_cols: [GLuint; 4] = [
-                                                    col.x as GLuint,
-                                                    col.y as GLuint,
-                                                    col.z as GLuint,
-                                                    col.w as GLuint,
-                                                ];
-                                                gl::ClearBufferuiv(
-                                                    gl::COLOR as GLenum,
-                                                    idx as GLint,
-                                                    i_cols.as_ptr() as *const GLuint,
-                                                );
-                                            }
-                                            OrigSurfaceType::Float => {
-                                                let float_col = color4b_to_color4f(col);
-                                                let f_cols: [GLfloat; 4] = [
-                                                    float_col.x,
-                                                    float_col.y,
-                                                    float_col.z,
-                                                    float_col.w,
-                                                ];
-                                                gl::ClearBufferfv(
-                                                    gl::COLOR as GLenum,
-                                                    idx as GLint,
-                                                    f_cols.as_ptr() as *const GLfloat,
-                                                );
-                                            }
-                                        }
-                                    }
-                                    _ => {
-                                        // assume that it's the default fixed point frame buffer
-                                        let float_col = color4b_to_color4f(col);
-                                        let f_cols: [GLfloat; 4] =
-                                            [float_col.x, float_col.y, float_col.z, float_col.w];
-                                        gl::ClearBufferfv(
-                                            gl::COLOR as GLenum,
-                                            idx as GLint,
-                                            f_cols.as_ptr() as *const GLfloat,
-                                        );
-                                    }
-                                }
-                                Self::check_gl_error();
-                            }
-                            _ => (),
-                        }
+                        Self::clear_color_attachment(
+                            idx,
+                            &pass.color_actions[idx],
+                            fb_desc.color_attachements[idx].as_ref(),
+                        );
                     }
 
-                    // clear the depth
+                    // clear the depth & stencil
                     match pass.depth_action {
                         DepthPassAction::Clear(f) => {
+                            self.state.depth_mask(true);
                             gl::ClearBufferfv(
                                 gl::DEPTH as GLenum,
                                 0,
@@ -1581,6 +2116,12 @@ impl Driver for Gles3Driver {
                             );
                             Self::check_gl_error();
                         }
+                        DepthPassAction::ClearDepthStencil(depth, stencil) => {
+                            self.state.depth_mask(true);
+                            gl::StencilMask(0xFF);
+                            gl::ClearBufferfi(gl::DEPTH_STENCIL, 0, depth, stencil as GLint);
+                            Self::check_gl_error();
+                        }
                         _ => (),
                     }
                 }
@@ -1588,24 +2129,41 @@ impl Driver for Gles3Driver {
                     // TODO: does glClearBufferfv works here?
                     let mut bits = 0;
                     for idx in 0..4 {
-                        match pass.color_actions[idx] {
+                        let f_cols: [GLfloat; 4] = match pass.color_actions[idx] {
                             ColorPassAction::Clear(col) => {
                                 let float_col = color4b_to_color4f(col);
-                                let f_cols: [GLfloat; 4] =
-                                    [float_col.x, float_col.y, float_col.z, float_col.w];
-                                gl::ClearColor(f_cols[0], f_cols[1], f_cols[2], f_cols[3]);
-
-                                bits |= gl::COLOR_BUFFER_BIT;
+                                [float_col.x, float_col.y, float_col.z, float_col.w]
                             }
-                            _ => (),
-                        }
+                            ColorPassAction::ClearValue(ClearValue::Float4(col)) => {
+                                [col.x, col.y, col.z, col.w]
+                            }
+                            ColorPassAction::ClearValue(_) => {
+                                // rejected by ValidatingDriver, the clear is skipped here
+                                self.report(
+                                    DebugSeverity::High,
+                                    String::from("integer clear value on the default frame buffer"),
+                                );
+                                continue;
+                            }
+                            _ => continue,
+                        };
+                        gl::ClearColor(f_cols[0], f_cols[1], f_cols[2], f_cols[3]);
+                        bits |= gl::COLOR_BUFFER_BIT;
                     }
 
                     match pass.depth_action {
                         DepthPassAction::Clear(depth) => {
+                            self.state.depth_mask(true);
                             gl::ClearDepthf(depth);
                             bits |= gl::DEPTH_BUFFER_BIT;
                         }
+                        DepthPassAction::ClearDepthStencil(depth, stencil) => {
+                            self.state.depth_mask(true);
+                            gl::StencilMask(0xFF);
+                            gl::ClearDepthf(depth);
+                            gl::ClearStencil(stencil as GLint);
+                            bits |= gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT;
+                        }
                         _ => (),
                     }
                     gl::Clear(bits);
@@ -1613,6 +2171,7 @@ impl Driver for Gles3Driver {
                 }
             }
 
+            let pass_fb = self.frame_buffer_gl_id(&pass.frame_buffer);
             for mut cmd in &mut pass.queue.commands {
                 match &mut cmd {
                     RenderPassCommand::Viewport(x, y, w, h) => self.set_viewport(*x, *y, *w, *h),
@@ -1630,10 +2189,35 @@ impl Driver for Gles3Driver {
                     RenderPassCommand::UpdateTexture(cmd) => {
                         self.update_texture(&mut cmd.tex, cmd.payload.clone())
                     }
+                    RenderPassCommand::Blit(cmd) => self.blit(cmd, pass_fb),
+                    RenderPassCommand::CopyTexture(cmd) => self.copy_texture(cmd, pass_fb),
+                    RenderPassCommand::BeginQuery(query) => self.begin_query(query),
+                    RenderPassCommand::EndQuery(query) => {
+                        let target = self.queries[query.res_id()].desc.query_type.gl_target();
+                        gl::EndQuery(target)
+                    }
                 }
             }
 
             pass.queue.drain();
+
+            // discard what is not needed after the pass
+            let mut store_colors = [false; 4];
+            for idx in 0..4 {
+                store_colors[idx] = pass.color_store_actions[idx] == StoreAction::DontCare;
+            }
+            let store_depth = pass.depth_store_action == StoreAction::DontCare;
+            Self::invalidate_attachments(
+                pass.frame_buffer
+                    .as_ref()
+                    .map(|fb| &self.framebuffers[fb.res_id()].desc),
+                store_colors,
+                store_depth,
+            );
+        }
+        match (&self.debug, &pass.label) {
+            (Some(debug), Some(_)) => debug.pop_group(),
+            _ => (),
         }
     }
 
@@ -1647,10 +2231,125 @@ impl Driver for Gles3Driver {
     ) -> Option<ReadbackPayload> {
         panic!("unsupported: Use ReadBackDriver instead!")
     }
+
+    fn query_result(&mut self, query: &QueryPtr) -> Option<bool> {
+        let gl_query = &self.queries[query.res_id()];
+        if !gl_query.issued {
+            return None;
+        }
+
+        unsafe {
+            let mut available: GLuint = 0;
+            gl::GetQueryObjectuiv(gl_query.gl_id, gl::QUERY_RESULT_AVAILABLE, &mut available);
+            if available == gl::FALSE as GLuint {
+                return None;
+            }
+
+            let mut result: GLuint = 0;
+            gl::GetQueryObjectuiv(gl_query.gl_id, gl::QUERY_RESULT, &mut result);
+            Some(result != 0)
+        }
+    }
+
+    fn stats(&self) -> DriverStats {
+        self.stats
+    }
+
+    fn end_frame(&mut self) {
+        self.flush_pending_calls();
+        self.stats.end_frame()
+    }
+
+    fn live_resources(&self) -> Vec<LiveResource> {
+        let mut live = Vec::new();
+        let ids: [(ResourceType, Vec<usize>); 7] = [
+            (
+                ResourceType::DeviceBuffer,
+                self.device_buffers.iter().map(|(i, _)| i).collect(),
+            ),
+            (
+                ResourceType::Texture,
+                self.textures.iter().map(|(i, _)| i).collect(),
+            ),
+            (
+                ResourceType::RenderTarget,
+                self.render_targets.iter().map(|(i, _)| i).collect(),
+            ),
+            (
+                ResourceType::Shader,
+                self.shaders.iter().map(|(i, _)| i).collect(),
+            ),
+            (
+                ResourceType::Pipeline,
+                self.pipelines.iter().map(|(i, _)| i).collect(),
+            ),
+            (
+                ResourceType::FrameBuffer,
+                self.framebuffers.iter().map(|(i, _)| i).collect(),
+            ),
+            (
+                ResourceType::Query,
+                self.queries.iter().map(|(i, _)| i).collect(),
+            ),
+        ];
+
+        for (res_type, res_ids) in ids.iter() {
+            for res_id in res_ids {
+                live.push(LiveResource {
+                    res_type: *res_type,
+                    res_id: *res_id,
+                    bytes: self.resource_bytes(*res_type, *res_id),
+                    label: self.resource_label(*res_type, *res_id),
+                });
+            }
+        }
+        live
+    }
+
+    ///
+    /// the resources keep the driver alive: it is only dropped once they are all
+    /// gone, the wrapping `ReadbackDriver` reports the leaks instead
+    ///
+    fn set_leak_report(&mut self, _enabled: bool) {}
+
+    fn set_debug_callback(&mut self, callback: Option<DebugCallback>) {
+        match &self.debug {
+            Some(debug) => {
+                // detach the previous callback before releasing it
+                debug.set_callback(None);
+                self.debug_callback = callback.map(Box::new);
+                match &mut self.debug_callback {
+                    Some(cb) => debug.set_callback(Some(cb.as_mut() as *mut DebugCallback)),
+                    None => (),
+                }
+            }
+            None => (),
+        }
+    }
 }
 
 impl Drop for Gles3Driver {
     fn drop(&mut self) {
+        if std::thread::current().id() == self.gl_thread {
+            self.flush_pending_calls();
+        } else if !self.pending_calls.is_empty() {
+            let message = format!(
+                "Gles3Driver dropped off the GL thread with {} pending call(s)",
+                self.pending_calls.len()
+            );
+            self.report(DebugSeverity::High, message);
+        }
+        match (&self.debug, &self.debug_callback) {
+            (Some(debug), Some(_)) => debug.set_callback(None),
+            _ => (),
+        }
+        match self.copy_frame_buffers {
+            Some((read_fb, draw_fb)) => unsafe {
+                let fbs = [read_fb, draw_fb];
+                gl::DeleteFramebuffers(2, fbs.as_ptr());
+            },
+            None => (),
+        }
         println!("Gles3Driver dropped - All is good!")
     }
 }
@@ -0,0 +1,325 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+use super::backend;
+use super::gl;
+use super::gl::types::*;
+
+use std::collections::HashMap;
+
+////////////////////////////////////////////////////////////////////////////////
+// GL state cache
+//
+// Shadow copy of the GL state set by the draws, None stands for an unknown
+// state. Every call matching the shadow copy is skipped and counted.
+////////////////////////////////////////////////////////////////////////////////
+
+const MAX_TEXTURE_UNITS: usize = 32;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct VertexArrayKey {
+    pub pipeline: usize,
+    pub vertex_buffers: Vec<usize>,
+    pub index_buffer: Option<usize>,
+}
+
+pub(crate) struct GLStateCache {
+    blend: Option<bool>,
+    blend_func: Option<[GLenum; 4]>,
+    blend_equation: Option<(GLenum, GLenum)>,
+    cull_face: Option<bool>,
+    cull_face_mode: Option<GLenum>,
+    depth_test: Option<bool>,
+    depth_mask: Option<bool>,
+    polygon_offset_fill: Option<bool>,
+    polygon_offset: Option<(f32, f32)>,
+    primitive_restart: Option<bool>,
+    restart_index: Option<GLuint>,
+
+    program: Option<GLuint>,
+    vertex_array: Option<GLuint>,
+    active_texture: Option<usize>,
+    textures: [Option<GLuint>; MAX_TEXTURE_UNITS],
+    // sampler uniform values are per program
+    sampler_units: HashMap<(GLuint, GLint), GLint>,
+
+    vertex_arrays: HashMap<VertexArrayKey, GLuint>,
+
+    saved_calls: usize,
+}
+
+impl GLStateCache {
+    pub fn new() -> Self {
+        Self {
+            blend: None,
+            blend_func: None,
+            blend_equation: None,
+            cull_face: None,
+            cull_face_mode: None,
+            depth_test: None,
+            depth_mask: None,
+            polygon_offset_fill: None,
+            polygon_offset: None,
+            primitive_restart: None,
+            restart_index: None,
+
+            program: None,
+            vertex_array: None,
+            active_texture: None,
+            textures: [None; MAX_TEXTURE_UNITS],
+            sampler_units: HashMap::new(),
+
+            vertex_arrays: HashMap::new(),
+
+            saved_calls: 0,
+        }
+    }
+
+    ///
+    /// returns true if the call has to be issued, false if it's redundant
+    ///
+    fn update<T: PartialEq>(cached: &mut Option<T>, value: T, saved_calls: &mut usize) -> bool {
+        if cached.as_ref() == Some(&value) {
+            *saved_calls += 1;
+            false
+        } else {
+            *cached = Some(value);
+            true
+        }
+    }
+
+    fn capability(&mut self, cap: GLenum, enabled: bool) {
+        let cached = match cap {
+            gl::BLEND => &mut self.blend,
+            gl::CULL_FACE => &mut self.cull_face,
+            gl::DEPTH_TEST => &mut self.depth_test,
+            gl::POLYGON_OFFSET_FILL => &mut self.polygon_offset_fill,
+            backend::PRIMITIVE_RESTART => &mut self.primitive_restart,
+            _ => panic!("capability {:#X} is not cached", cap),
+        };
+        if Self::update(cached, enabled, &mut self.saved_calls) {
+            unsafe {
+                if enabled {
+                    gl::Enable(cap)
+                } else {
+                    gl::Disable(cap)
+                }
+            }
+        }
+    }
+
+    pub fn blend(&mut self, enabled: bool) {
+        self.capability(gl::BLEND, enabled)
+    }
+
+    pub fn blend_func(&mut self, src_rgb: GLenum, dst_rgb: GLenum, src_a: GLenum, dst_a: GLenum) {
+        let value = [src_rgb, dst_rgb, src_a, dst_a];
+        if Self::update(&mut self.blend_func, value, &mut self.saved_calls) {
+            unsafe { gl::BlendFuncSeparate(src_rgb, dst_rgb, src_a, dst_a) }
+        }
+    }
+
+    pub fn blend_equation(&mut self, rgb: GLenum, alpha: GLenum) {
+        if Self::update(
+            &mut self.blend_equation,
+            (rgb, alpha),
+            &mut self.saved_calls,
+        ) {
+            unsafe { gl::BlendEquationSeparate(rgb, alpha) }
+        }
+    }
+
+    pub fn cull_face(&mut self, enabled: bool) {
+        self.capability(gl::CULL_FACE, enabled)
+    }
+
+    pub fn cull_face_mode(&mut self, mode: GLenum) {
+        if Self::update(&mut self.cull_face_mode, mode, &mut self.saved_calls) {
+            unsafe { gl::CullFace(mode) }
+        }
+    }
+
+    pub fn depth_test(&mut self, enabled: bool) {
+        self.capability(gl::DEPTH_TEST, enabled)
+    }
+
+    pub fn depth_mask(&mut self, enabled: bool) {
+        if Self::update(&mut self.depth_mask, enabled, &mut self.saved_calls) {
+            unsafe { gl::DepthMask(if enabled { gl::TRUE } else { gl::FALSE } as GLboolean) }
+        }
+    }
+
+    pub fn polygon_offset(&mut self, offset: Option<(f32, f32)>) {
+        self.capability(gl::POLYGON_OFFSET_FILL, offset.is_some());
+        // the offset is ignored while disabled
+        if let Some((factor, units)) = offset {
+            if Self::update(
+                &mut self.polygon_offset,
+                (factor, units),
+                &mut self.saved_calls,
+            ) {
+                unsafe { gl::PolygonOffset(factor, units) }
+            }
+        }
+    }
+
+    ///
+    /// restart at `restart_index`, the maximum value of the index type, None disables it
+    ///
+    pub fn primitive_restart(&mut self, restart_index: Option<GLuint>) {
+        self.capability(backend::PRIMITIVE_RESTART, restart_index.is_some());
+        if let Some(index) = restart_index {
+            if Self::update(&mut self.restart_index, index, &mut self.saved_calls) {
+                unsafe { backend::primitive_restart_index(index) }
+            }
+        }
+    }
+
+    pub fn use_program(&mut self, program: GLuint) {
+        if Self::update(&mut self.program, program, &mut self.saved_calls) {
+            unsafe { gl::UseProgram(program) }
+        }
+    }
+
+    pub fn bind_vertex_array(&mut self, vao: GLuint) {
+        if Self::update(&mut self.vertex_array, vao, &mut self.saved_calls) {
+            unsafe { gl::BindVertexArray(vao) }
+        }
+    }
+
+    pub fn bind_texture(&mut self, unit: usize, texture: GLuint) {
+        if self.textures[unit] == Some(texture) {
+            self.saved_calls += 2;
+            return;
+        }
+        if Self::update(&mut self.active_texture, unit, &mut self.saved_calls) {
+            unsafe { gl::ActiveTexture(gl::TEXTURE0 + unit as GLenum) }
+        }
+        self.textures[unit] = Some(texture);
+        unsafe { gl::BindTexture(gl::TEXTURE_2D, texture) }
+    }
+
+    ///
+    /// a texture was bound to the active unit outside of the cache (uploads)
+    ///
+    pub fn texture_bound(&mut self, texture: GLuint) {
+        match self.active_texture {
+            Some(unit) => self.textures[unit] = Some(texture),
+            None => self.textures = [None; MAX_TEXTURE_UNITS],
+        }
+    }
+
+    ///
+    /// set a sampler uniform of the program in use
+    ///
+    pub fn sampler_unit(&mut self, location: GLint, unit: GLint) {
+        let program = self.program.expect("no program in use");
+        match self.sampler_units.insert((program, location), unit) {
+            Some(prev) if prev == unit => self.saved_calls += 1,
+            _ => unsafe { gl::Uniform1i(location, unit) },
+        }
+    }
+
+    ///
+    /// vertex array of a (pipeline, bindings) pair, the setup function is only called when
+    /// the vertex array is created. Returns whether the vertex array was found in the cache
+    ///
+    pub fn vertex_array<F: FnOnce()>(&mut self, key: VertexArrayKey, setup: F) -> bool {
+        match self.vertex_arrays.get(&key) {
+            Some(vao) => {
+                let vao = *vao;
+                self.bind_vertex_array(vao);
+                true
+            }
+            None => {
+                let mut vao: GLuint = 0;
+                unsafe { gl::GenVertexArrays(1, &mut vao) };
+                self.bind_vertex_array(vao);
+                setup();
+                self.vertex_arrays.insert(key, vao);
+                false
+            }
+        }
+    }
+
+    pub fn add_saved_calls(&mut self, count: usize) {
+        self.saved_calls += count;
+    }
+
+    pub fn take_saved_calls(&mut self) -> usize {
+        std::mem::replace(&mut self.saved_calls, 0)
+    }
+
+    fn delete_vertex_arrays<F: Fn(&VertexArrayKey) -> bool>(&mut self, filter: F) {
+        let keys: Vec<VertexArrayKey> = self
+            .vertex_arrays
+            .keys()
+            .filter(|k| filter(k))
+            .cloned()
+            .collect();
+        for key in keys {
+            let vao = self.vertex_arrays.remove(&key).unwrap();
+            if self.vertex_array == Some(vao) {
+                self.bind_vertex_array(0);
+            }
+            unsafe { gl::DeleteVertexArrays(1, &vao) };
+        }
+    }
+
+    pub fn device_buffer_deleted(&mut self, res_id: usize) {
+        self.delete_vertex_arrays(|k| {
+            k.vertex_buffers.contains(&res_id) || k.index_buffer == Some(res_id)
+        })
+    }
+
+    pub fn pipeline_deleted(&mut self, res_id: usize) {
+        self.delete_vertex_arrays(|k| k.pipeline == res_id)
+    }
+
+    pub fn program_deleted(&mut self, program: GLuint) {
+        if self.program == Some(program) {
+            self.program = None;
+        }
+        self.sampler_units.retain(|(p, _), _| *p != program);
+    }
+
+    pub fn texture_deleted(&mut self, texture: GLuint) {
+        for t in self.textures.iter_mut() {
+            if *t == Some(texture) {
+                *t = None;
+            }
+        }
+    }
+}
+
+impl Drop for GLStateCache {
+    fn drop(&mut self) {
+        self.delete_vertex_arrays(|_| true)
+    }
+}
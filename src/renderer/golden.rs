@@ -0,0 +1,314 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+use super::*;
+use std::path::{Path, PathBuf};
+
+////////////////////////////////////////////////////////////////////////////////
+// Golden image testing
+//
+// A rendered image is compared with a stored reference PNG. Pixels are
+// compared with a per channel tolerance & a perceptual (YIQ) threshold, on
+// failure the actual, expected & diff images are written next to the
+// reference (or to the output directory): `<name>.actual.png`,
+// `<name>.expected.png` & `<name>.diff.png`.
+//
+// Setting `NEOCOGI_UPDATE_GOLDEN` in the environment (re)writes the
+// references instead of comparing.
+////////////////////////////////////////////////////////////////////////////////
+
+// maximum YIQ distance between two colors
+const MAX_YIQ_DELTA: f32 = 35215.0;
+
+#[derive(Clone, Debug)]
+pub struct GoldenOptions {
+    /// maximum absolute difference per channel ([0, 255]) to consider pixels equal
+    pub channel_tolerance: u8,
+    /// [0, 1], pixels above the tolerance but perceptually closer are still equal
+    pub perceptual_threshold: f32,
+    /// number of differing pixels allowed before failing
+    pub max_failing_pixels: usize,
+    /// where failure images go, next to the reference if None
+    pub output_dir: Option<PathBuf>,
+    /// write the reference instead of comparing
+    pub update: bool,
+}
+
+impl GoldenOptions {
+    pub fn default() -> Self {
+        Self {
+            channel_tolerance: 2,
+            perceptual_threshold: 0.1,
+            max_failing_pixels: 0,
+            output_dir: None,
+            update: std::env::var_os("NEOCOGI_UPDATE_GOLDEN").is_some(),
+        }
+    }
+
+    pub fn with_channel_tolerance(mut self, tolerance: u8) -> Self {
+        self.channel_tolerance = tolerance;
+        self
+    }
+
+    pub fn with_perceptual_threshold(mut self, threshold: f32) -> Self {
+        self.perceptual_threshold = threshold;
+        self
+    }
+
+    pub fn with_max_failing_pixels(mut self, count: usize) -> Self {
+        self.max_failing_pixels = count;
+        self
+    }
+
+    pub fn with_output_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.output_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn with_update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GoldenReport {
+    pub width: usize,
+    pub height: usize,
+    /// pixels failing both the channel tolerance & the perceptual threshold
+    pub failing_pixels: usize,
+    pub max_channel_diff: u8,
+    /// [0, 1]
+    pub max_perceptual_diff: f32,
+}
+
+impl GoldenReport {
+    pub fn passed(&self, options: &GoldenOptions) -> bool {
+        self.failing_pixels <= options.max_failing_pixels
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Image(ImageError),
+    /// the pass has no texture to read back from its first color attachment
+    NoColorTexture,
+    MissingReference(PathBuf),
+    SizeMismatch {
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    /// the report & the directory of the failure images
+    Mismatch(GoldenReport, PathBuf),
+}
+
+impl From<ImageError> for GoldenError {
+    fn from(e: ImageError) -> Self {
+        GoldenError::Image(e)
+    }
+}
+
+fn rgba(image: &Image, index: usize) -> [u8; 4] {
+    let p = &image.pixels[index * image.channels..(index + 1) * image.channels];
+    match image.channels {
+        1 => [p[0], p[0], p[0], 255],
+        3 => [p[0], p[1], p[2], 255],
+        _ => [p[0], p[1], p[2], p[3]],
+    }
+}
+
+fn yiq(c: [u8; 4]) -> (f32, f32, f32) {
+    // blend with white, transparent pixels compare equal whatever their color
+    let a = c[3] as f32 / 255.0;
+    let blend = |v: u8| 255.0 + (v as f32 - 255.0) * a;
+    let (r, g, b) = (blend(c[0]), blend(c[1]), blend(c[2]));
+    (
+        r * 0.29889531 + g * 0.58662247 + b * 0.11448223,
+        r * 0.59597799 - g * 0.27417610 - b * 0.32180189,
+        r * 0.21147017 - g * 0.52261711 + b * 0.31114694,
+    )
+}
+
+///
+/// perceptual distance in [0, 1] between two colors
+///
+fn perceptual_diff(a: [u8; 4], b: [u8; 4]) -> f32 {
+    let (ya, ia, qa) = yiq(a);
+    let (yb, ib, qb) = yiq(b);
+    let (dy, di, dq) = (ya - yb, ia - ib, qa - qb);
+    let delta = 0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq;
+    (delta / MAX_YIQ_DELTA).sqrt()
+}
+
+///
+/// compare two images of the same size: returns the report & the diff image.
+/// The diff image is the faded expected image with the failing pixels in red
+/// & the tolerated differences in yellow
+///
+pub fn compare_images(
+    actual: &Image,
+    expected: &Image,
+    options: &GoldenOptions,
+) -> Result<(GoldenReport, Image), GoldenError> {
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return Err(GoldenError::SizeMismatch {
+            expected: (expected.width, expected.height),
+            actual: (actual.width, actual.height),
+        });
+    }
+
+    let mut report = GoldenReport {
+        width: actual.width,
+        height: actual.height,
+        ..Default::default()
+    };
+    let mut diff = Vec::with_capacity(actual.width * actual.height * 4);
+
+    for i in 0..actual.width * actual.height {
+        let a = rgba(actual, i);
+        let e = rgba(expected, i);
+
+        let channel_diff = (0..4)
+            .map(|c| (a[c] as i32 - e[c] as i32).abs() as u8)
+            .max()
+            .unwrap();
+        let perceptual = perceptual_diff(a, e);
+        report.max_channel_diff = report.max_channel_diff.max(channel_diff);
+        report.max_perceptual_diff = report.max_perceptual_diff.max(perceptual);
+
+        if channel_diff <= options.channel_tolerance {
+            let (y, _, _) = yiq(e);
+            let gray = (255.0 + (y - 255.0) * 0.1) as u8;
+            diff.extend_from_slice(&[gray, gray, gray, 255]);
+        } else if perceptual <= options.perceptual_threshold {
+            diff.extend_from_slice(&[255, 255, 0, 255]);
+        } else {
+            report.failing_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        }
+    }
+
+    let diff = Image {
+        width: actual.width,
+        height: actual.height,
+        channels: 4,
+        pixels: diff,
+    };
+    Ok((report, diff))
+}
+
+fn failure_path(reference: &Path, options: &GoldenOptions, suffix: &str) -> PathBuf {
+    let name = reference
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("golden"));
+    let dir = match &options.output_dir {
+        Some(dir) => dir.clone(),
+        None => reference
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default(),
+    };
+    dir.join(format!("{}.{}.png", name, suffix))
+}
+
+///
+/// compare an image with the reference PNG at `reference`
+///
+pub fn check_golden_image<P: AsRef<Path>>(
+    actual: &Image,
+    reference: P,
+    options: &GoldenOptions,
+) -> Result<GoldenReport, GoldenError> {
+    let reference = reference.as_ref();
+    if options.update {
+        if let Some(dir) = reference.parent() {
+            std::fs::create_dir_all(dir).map_err(ImageError::from)?;
+        }
+        write_png(reference, actual)?;
+        return Ok(GoldenReport {
+            width: actual.width,
+            height: actual.height,
+            ..Default::default()
+        });
+    }
+
+    if !reference.exists() {
+        return Err(GoldenError::MissingReference(reference.to_path_buf()));
+    }
+    let expected = Image::load(reference)?;
+    let (report, diff) = compare_images(actual, &expected, options)?;
+    if report.passed(options) {
+        return Ok(report);
+    }
+
+    let diff_path = failure_path(reference, options, "diff");
+    if let Some(dir) = diff_path.parent() {
+        std::fs::create_dir_all(dir).map_err(ImageError::from)?;
+    }
+    write_png(failure_path(reference, options, "actual"), actual)?;
+    write_png(failure_path(reference, options, "expected"), &expected)?;
+    write_png(&diff_path, &diff)?;
+
+    let dir = diff_path
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_default();
+    Err(GoldenError::Mismatch(report, dir))
+}
+
+///
+/// render `pass`, read back its first color attachment & compare it with the
+/// reference PNG at `reference`. The pass needs an offscreen frame buffer with
+/// a texture color attachment
+///
+pub fn check_golden_pass<P: AsRef<Path>>(
+    driver: &mut DriverPtr,
+    pass: &mut Pass,
+    reference: P,
+    options: &GoldenOptions,
+) -> Result<GoldenReport, GoldenError> {
+    let tex = match &pass.frame_buffer {
+        Some(fb) => match &fb.desc().color_attachements[0] {
+            Some(SurfaceAttachment::Texture(tex)) => tex.clone(),
+            _ => return Err(GoldenError::NoColorTexture),
+        },
+        None => return Err(GoldenError::NoColorTexture),
+    };
+
+    driver.render_pass(pass);
+    let actual = match read_back_texture(driver, &tex, None)? {
+        ExportImage::Ldr(image) => image,
+        ExportImage::Hdr(image) => image.to_image(),
+        ExportImage::UInt(_) => {
+            return Err(ImageError::Unsupported("golden test of 32 bits integer surfaces").into())
+        }
+    };
+    check_golden_image(&actual, reference, options)
+}
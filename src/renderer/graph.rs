@@ -0,0 +1,896 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+use super::*;
+use std::fmt;
+use std::sync::*;
+
+////////////////////////////////////////////////////////////////////////////////
+// Graph textures
+////////////////////////////////////////////////////////////////////////////////
+
+///
+/// size of a transient texture, relative sizes follow the graph size on resize
+///
+#[derive(Clone, Copy, Debug)]
+pub enum GraphSize {
+    Relative(f32),
+    Absolute(usize, usize),
+}
+
+impl GraphSize {
+    fn resolve(&self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            GraphSize::Relative(scale) => (
+                ((width as f32 * scale) as usize).max(1),
+                ((height as f32 * scale) as usize).max(1),
+            ),
+            GraphSize::Absolute(w, h) => (*w, *h),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GraphTextureDesc {
+    pub pixel_format: PixelFormat,
+    pub size: GraphSize,
+    pub label: Option<String>,
+}
+
+impl GraphTextureDesc {
+    pub fn new(pixel_format: PixelFormat, size: GraphSize) -> Self {
+        Self {
+            pixel_format,
+            size,
+            label: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(String::from(label));
+        self
+    }
+}
+
+///
+/// handle to a texture of a render graph
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GraphTexture(usize);
+
+enum GraphTextureSource {
+    Transient(GraphTextureDesc),
+    Imported(TexturePtr),
+}
+
+///
+/// textures allocated for the graph, handed to the passes while recording
+///
+pub struct GraphResources<'a> {
+    textures: &'a [Option<TexturePtr>],
+}
+
+impl<'a> GraphResources<'a> {
+    pub fn texture(&self, tex: GraphTexture) -> &TexturePtr {
+        match &self.textures[tex.0] {
+            Some(t) => t,
+            None => panic!("graph texture {} is not used by any executed pass", tex.0),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Graph passes
+////////////////////////////////////////////////////////////////////////////////
+
+pub type GraphPassFn = Box<dyn FnMut(&mut Pass, &GraphResources) + Send>;
+
+///
+/// a pass of the graph: the textures it reads, the ones it renders to (or the screen)
+/// and the function recording its commands
+///
+pub struct GraphPass {
+    label: String,
+    inputs: Vec<GraphTexture>,
+    color_outputs: Vec<(GraphTexture, ColorPassAction)>,
+    depth_output: Option<(GraphTexture, DepthPassAction)>,
+    screen_output: Option<(ColorPassAction, DepthPassAction)>,
+    record: GraphPassFn,
+}
+
+impl GraphPass {
+    pub fn new<F: FnMut(&mut Pass, &GraphResources) + Send + 'static>(
+        label: &str,
+        record: F,
+    ) -> Self {
+        Self {
+            label: String::from(label),
+            inputs: Vec::new(),
+            color_outputs: Vec::new(),
+            depth_output: None,
+            screen_output: None,
+            record: Box::new(record),
+        }
+    }
+
+    pub fn with_input(mut self, tex: GraphTexture) -> Self {
+        self.inputs.push(tex);
+        self
+    }
+
+    ///
+    /// color outputs are attached in the order they are declared
+    ///
+    pub fn with_color_output(mut self, tex: GraphTexture, action: ColorPassAction) -> Self {
+        self.color_outputs.push((tex, action));
+        self
+    }
+
+    pub fn with_depth_output(mut self, tex: GraphTexture, action: DepthPassAction) -> Self {
+        self.depth_output = Some((tex, action));
+        self
+    }
+
+    ///
+    /// render to the default frame buffer, such passes are never culled
+    ///
+    pub fn with_screen_output(mut self, color: ColorPassAction, depth: DepthPassAction) -> Self {
+        self.screen_output = Some((color, depth));
+        self
+    }
+
+    fn outputs(&self) -> Vec<GraphTexture> {
+        let mut outputs: Vec<GraphTexture> = self.color_outputs.iter().map(|(t, _)| *t).collect();
+        match &self.depth_output {
+            Some((t, _)) => outputs.push(*t),
+            None => (),
+        }
+        outputs
+    }
+
+    ///
+    /// outputs loaded with Previous depend on their former content
+    ///
+    fn dependencies(&self) -> Vec<GraphTexture> {
+        let mut deps = self.inputs.clone();
+        for (t, action) in &self.color_outputs {
+            match action {
+                ColorPassAction::Previous => deps.push(*t),
+                _ => (),
+            }
+        }
+        match &self.depth_output {
+            Some((t, DepthPassAction::Previous)) => deps.push(*t),
+            _ => (),
+        }
+        deps
+    }
+
+    fn uses(&self) -> Vec<GraphTexture> {
+        let mut uses = self.inputs.clone();
+        uses.extend(self.outputs());
+        uses
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Render graph
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug)]
+pub enum RenderGraphError {
+    UnwrittenInput { pass: String },
+    ReadsOwnOutput { pass: String },
+    Cycle { passes: Vec<String> },
+    NoOutput { pass: String },
+    ScreenAndTextureOutputs { pass: String },
+    TooManyColorOutputs { pass: String },
+    OutputSizeMismatch { pass: String },
+    Allocation { pass: String },
+}
+
+impl fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use RenderGraphError::*;
+        match self {
+            UnwrittenInput { pass } => {
+                write!(f, "pass \"{}\" reads a texture no pass writes", pass)
+            }
+            ReadsOwnOutput { pass } => {
+                write!(f, "pass \"{}\" reads and writes the same texture", pass)
+            }
+            Cycle { passes } => write!(f, "render graph cycle between passes {:?}", passes),
+            NoOutput { pass } => write!(f, "pass \"{}\" has no output", pass),
+            ScreenAndTextureOutputs { pass } => write!(
+                f,
+                "pass \"{}\" renders both to the screen and to textures",
+                pass
+            ),
+            TooManyColorOutputs { pass } => {
+                write!(f, "pass \"{}\" has more than 4 color outputs", pass)
+            }
+            OutputSizeMismatch { pass } => {
+                write!(f, "pass \"{}\" outputs have different sizes", pass)
+            }
+            Allocation { pass } => {
+                write!(f, "pass \"{}\" textures could not be allocated", pass)
+            }
+        }
+    }
+}
+
+struct CompiledPass {
+    pass: usize,
+    width: usize,
+    height: usize,
+    frame_buffer: Option<FrameBufferPtr>,
+    color_actions: [ColorPassAction; 4],
+    depth_action: DepthPassAction,
+    depth_store_action: StoreAction,
+}
+
+struct CompiledGraph {
+    passes: Vec<CompiledPass>,
+    textures: Vec<Option<TexturePtr>>,
+}
+
+///
+/// passes declare the textures they read & write. On execution, the graph drops the
+/// passes whose outputs are not used, orders the rest and renders them to frame buffers
+/// made of pooled textures. Transient textures with disjoint lifetimes share memory
+///
+pub struct RenderGraph {
+    width: usize,
+    height: usize,
+    textures: Vec<GraphTextureSource>,
+    passes: Vec<GraphPass>,
+    outputs: Vec<GraphTexture>,
+    compiled: Option<CompiledGraph>,
+    texture_pool: Vec<TexturePtr>,
+    depth_pool: Vec<RenderTargetPtr>,
+}
+
+fn same_size(desc: &SamplerDesc, width: usize, height: usize) -> bool {
+    desc.width() == width && desc.height() == height
+}
+
+impl RenderGraph {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            textures: Vec::new(),
+            passes: Vec::new(),
+            outputs: Vec::new(),
+            compiled: None,
+            texture_pool: Vec::new(),
+            depth_pool: Vec::new(),
+        }
+    }
+
+    pub fn create_texture(&mut self, desc: GraphTextureDesc) -> GraphTexture {
+        self.compiled = None;
+        self.textures.push(GraphTextureSource::Transient(desc));
+        GraphTexture(self.textures.len() - 1)
+    }
+
+    ///
+    /// external textures are never pooled and passes writing them are never culled
+    ///
+    pub fn import_texture(&mut self, tex: &TexturePtr) -> GraphTexture {
+        self.compiled = None;
+        self.textures
+            .push(GraphTextureSource::Imported(tex.clone()));
+        GraphTexture(self.textures.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, pass: GraphPass) {
+        self.compiled = None;
+        self.passes.push(pass);
+    }
+
+    ///
+    /// keep a transient texture alive after the graph execution (e.g. for readbacks)
+    ///
+    pub fn mark_output(&mut self, tex: GraphTexture) {
+        self.compiled = None;
+        self.outputs.push(tex);
+    }
+
+    ///
+    /// the relatively sized textures are reallocated on the next execution
+    ///
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if self.width != width || self.height != height {
+            self.width = width;
+            self.height = height;
+            self.compiled = None;
+        }
+    }
+
+    ///
+    /// texture allocated for a graph texture by the last execution
+    ///
+    pub fn texture(&self, tex: GraphTexture) -> Option<&TexturePtr> {
+        match &self.compiled {
+            Some(compiled) => compiled.textures[tex.0].as_ref(),
+            None => None,
+        }
+    }
+
+    ///
+    /// labels of the passes in execution order, culled passes excluded
+    ///
+    pub fn executed_passes(&self) -> Vec<&str> {
+        match &self.compiled {
+            Some(compiled) => compiled
+                .passes
+                .iter()
+                .map(|p| self.passes[p.pass].label.as_str())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    ///
+    /// compiles the graph if it changed, nothing is rendered when it's invalid
+    ///
+    pub fn execute(&mut self, driver: &mut DriverPtr) -> Result<(), RenderGraphError> {
+        if self.compiled.is_none() {
+            self.compiled = Some(self.compile(driver)?);
+        }
+
+        let compiled = self.compiled.as_ref().unwrap();
+        let resources = GraphResources {
+            textures: &compiled.textures,
+        };
+
+        for cp in &compiled.passes {
+            let gp = &mut self.passes[cp.pass];
+            let mut pass = Pass::new(
+                cp.width,
+                cp.height,
+                cp.frame_buffer.clone(),
+                cp.color_actions,
+                cp.depth_action,
+            )
+            .with_label(&gp.label)
+            .with_store_actions([StoreAction::Store; 4], cp.depth_store_action);
+
+            (gp.record)(&mut pass, &resources);
+            driver.render_pass(&mut pass);
+        }
+        Ok(())
+    }
+
+    fn is_imported(&self, tex: GraphTexture) -> bool {
+        match &self.textures[tex.0] {
+            GraphTextureSource::Imported(_) => true,
+            GraphTextureSource::Transient(_) => false,
+        }
+    }
+
+    fn writers(&self, tex: GraphTexture) -> Vec<usize> {
+        (0..self.passes.len())
+            .filter(|p| self.passes[*p].outputs().contains(&tex))
+            .collect()
+    }
+
+    ///
+    /// the writer whose content a pass reads: the last one declared before the pass. A
+    /// transient texture read before any declared write gets the last write
+    ///
+    fn source(&self, tex: GraphTexture, pass: usize) -> Option<usize> {
+        let writers = self.writers(tex);
+        match writers.iter().rev().find(|w| **w < pass) {
+            Some(w) => Some(*w),
+            None if self.is_imported(tex) || writers.contains(&pass) => None,
+            None => writers.last().cloned(),
+        }
+    }
+
+    fn cull(&self) -> Vec<bool> {
+        let mut alive = vec![false; self.passes.len()];
+        let mut stack: Vec<usize> = (0..self.passes.len())
+            .filter(|p| {
+                let pass = &self.passes[*p];
+                pass.screen_output.is_some()
+                    || pass
+                        .outputs()
+                        .iter()
+                        .any(|t| self.outputs.contains(t) || self.is_imported(*t))
+            })
+            .collect();
+
+        while let Some(p) = stack.pop() {
+            if alive[p] {
+                continue;
+            }
+            alive[p] = true;
+
+            for tex in self.passes[p].dependencies() {
+                match self.source(tex, p) {
+                    Some(w) if w != p && !alive[w] => stack.push(w),
+                    _ => (),
+                }
+            }
+        }
+        alive
+    }
+
+    ///
+    /// the passes using a texture keep their declaration order: writers run in sequence,
+    /// a reader runs after the write it reads (see `source`) and before the next one
+    ///
+    fn order(&self, alive: &[bool]) -> Result<Vec<usize>, RenderGraphError> {
+        let mut deps: Vec<Vec<usize>> = vec![Vec::new(); self.passes.len()];
+        for t in 0..self.textures.len() {
+            let tex = GraphTexture(t);
+            let writers: Vec<usize> = self
+                .writers(tex)
+                .into_iter()
+                .filter(|w| alive[*w])
+                .collect();
+
+            for w in writers.windows(2) {
+                deps[w[1]].push(w[0]);
+            }
+
+            for (r, pass) in self.passes.iter().enumerate() {
+                if !alive[r] || !pass.inputs.contains(&tex) {
+                    continue;
+                }
+                if writers.contains(&r) {
+                    return Err(RenderGraphError::ReadsOwnOutput {
+                        pass: pass.label.clone(),
+                    });
+                }
+                if writers.is_empty() && !self.is_imported(tex) {
+                    return Err(RenderGraphError::UnwrittenInput {
+                        pass: pass.label.clone(),
+                    });
+                }
+
+                // the source is alive since the reader is
+                let next = match self.source(tex, r) {
+                    Some(w) => {
+                        deps[r].push(w);
+                        writers.iter().find(|n| **n > w)
+                    }
+                    None => writers.first(),
+                };
+                // the next writer waits for the reads declared before it
+                match next {
+                    Some(n) if *n > r => deps[*n].push(r),
+                    _ => (),
+                }
+            }
+        }
+
+        let alive_count = alive.iter().filter(|a| **a).count();
+        let mut done = vec![false; self.passes.len()];
+        let mut order = Vec::new();
+        while order.len() < alive_count {
+            let next = (0..self.passes.len())
+                .find(|p| alive[*p] && !done[*p] && deps[*p].iter().all(|d| done[*d]));
+            match next {
+                Some(p) => {
+                    done[p] = true;
+                    order.push(p);
+                }
+                None => {
+                    let passes = (0..self.passes.len())
+                        .filter(|p| alive[*p] && !done[*p])
+                        .map(|p| self.passes[p].label.clone())
+                        .collect();
+                    return Err(RenderGraphError::Cycle { passes });
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    fn scratch_depth(
+        &mut self,
+        driver: &mut DriverPtr,
+        used: &mut Vec<RenderTargetPtr>,
+        width: usize,
+        height: usize,
+    ) -> Option<RenderTargetPtr> {
+        let found = self
+            .depth_pool
+            .iter()
+            .chain(used.iter())
+            .find(|rt| same_size(&rt.desc().sampler_desc, width, height))
+            .cloned();
+
+        let rt = match found {
+            Some(rt) => rt,
+            None => driver.create_render_target(RenderTargetDesc {
+                sampler_desc: SamplerDesc::default(width, height)
+                    .with_pixel_format(PixelFormat::D32),
+                sample_count: 0,
+                label: Some(String::from("render graph depth")),
+            })?,
+        };
+
+        if !used.iter().any(|u| Arc::ptr_eq(u, &rt)) {
+            used.push(rt.clone());
+        }
+        Some(rt)
+    }
+
+    fn compile(&mut self, driver: &mut DriverPtr) -> Result<CompiledGraph, RenderGraphError> {
+        let alive = self.cull();
+        let order = self.order(&alive)?;
+
+        for p in &order {
+            let pass = &self.passes[*p];
+            let label = pass.label.clone();
+            if pass.screen_output.is_some() && !pass.outputs().is_empty() {
+                return Err(RenderGraphError::ScreenAndTextureOutputs { pass: label });
+            }
+            if pass.screen_output.is_none() && pass.outputs().is_empty() {
+                return Err(RenderGraphError::NoOutput { pass: label });
+            }
+            if pass.color_outputs.len() > 4 {
+                return Err(RenderGraphError::TooManyColorOutputs { pass: label });
+            }
+        }
+
+        // lifetime of the textures in execution order, outputs live past the last pass
+        let mut first = vec![usize::MAX; self.textures.len()];
+        let mut last = vec![0; self.textures.len()];
+        for (pos, p) in order.iter().enumerate() {
+            for tex in self.passes[*p].uses() {
+                first[tex.0] = first[tex.0].min(pos);
+                last[tex.0] = last[tex.0].max(pos);
+            }
+        }
+        for tex in &self.outputs {
+            last[tex.0] = usize::MAX;
+        }
+
+        let mut textures: Vec<Option<TexturePtr>> = self
+            .textures
+            .iter()
+            .map(|t| match t {
+                GraphTextureSource::Imported(tex) => Some(tex.clone()),
+                GraphTextureSource::Transient(_) => None,
+            })
+            .collect();
+
+        // the pool textures not reused by this compilation are released
+        let mut free = std::mem::replace(&mut self.texture_pool, Vec::new());
+        let mut pool: Vec<TexturePtr> = Vec::new();
+        for (pos, p) in order.iter().enumerate() {
+            let uses = self.passes[*p].uses();
+            for tex in &uses {
+                let desc = match &self.textures[tex.0] {
+                    GraphTextureSource::Transient(desc) => desc,
+                    GraphTextureSource::Imported(_) => continue,
+                };
+                if first[tex.0] != pos || textures[tex.0].is_some() {
+                    continue;
+                }
+
+                let (w, h) = desc.size.resolve(self.width, self.height);
+                let matching = free.iter().position(|t| {
+                    let sampler_desc = &t.desc().sampler_desc;
+                    sampler_desc.pixel_format == desc.pixel_format && same_size(sampler_desc, w, h)
+                });
+                let texture = match matching {
+                    Some(idx) => free.swap_remove(idx),
+                    None => driver
+                        .create_texture(TextureDesc {
+                            sampler_desc: SamplerDesc::default(w, h)
+                                .with_pixel_format(desc.pixel_format.clone()),
+                            payload: None,
+                            label: desc.label.clone(),
+                        })
+                        .ok_or_else(|| RenderGraphError::Allocation {
+                            pass: self.passes[*p].label.clone(),
+                        })?,
+                };
+
+                if !pool.iter().any(|t| Arc::ptr_eq(t, &texture)) {
+                    pool.push(texture.clone());
+                }
+                textures[tex.0] = Some(texture);
+            }
+
+            for tex in &uses {
+                if !self.is_imported(*tex) && last[tex.0] == pos {
+                    free.push(textures[tex.0].clone().unwrap());
+                }
+            }
+        }
+        self.texture_pool = pool;
+
+        let mut depth_used = Vec::new();
+        let mut passes = Vec::new();
+        for p in order {
+            let compiled = match self.passes[p].screen_output {
+                Some((color, depth)) => CompiledPass {
+                    pass: p,
+                    width: self.width,
+                    height: self.height,
+                    frame_buffer: None,
+                    color_actions: [color; 4],
+                    depth_action: depth,
+                    depth_store_action: StoreAction::Store,
+                },
+                None => {
+                    let pass = &self.passes[p];
+                    let mut sizes = pass.outputs().into_iter().map(|t| {
+                        let desc = &textures[t.0].as_ref().unwrap().desc().sampler_desc;
+                        (desc.width(), desc.height())
+                    });
+                    // every pass has an output, checked above
+                    let (width, height) = sizes.next().unwrap();
+                    if sizes.any(|s| s != (width, height)) {
+                        return Err(RenderGraphError::OutputSizeMismatch {
+                            pass: pass.label.clone(),
+                        });
+                    }
+
+                    let mut color_attachements = [None, None, None, None];
+                    let mut color_actions = [ColorPassAction::Previous; 4];
+                    for (i, (t, action)) in pass.color_outputs.iter().enumerate() {
+                        let tex = textures[t.0].clone().unwrap();
+                        color_attachements[i] = Some(SurfaceAttachment::Texture(tex));
+                        color_actions[i] = *action;
+                    }
+
+                    let label = pass.label.clone();
+                    let allocation = || RenderGraphError::Allocation {
+                        pass: label.clone(),
+                    };
+                    let (depth_attachement, depth_action, depth_store_action) =
+                        match pass.depth_output {
+                            Some((t, action)) => (
+                                SurfaceAttachment::Texture(textures[t.0].clone().unwrap()),
+                                action,
+                                StoreAction::Store,
+                            ),
+                            // frame buffers need a depth attachment
+                            None => (
+                                SurfaceAttachment::RenderTarget(
+                                    self.scratch_depth(driver, &mut depth_used, width, height)
+                                        .ok_or_else(allocation)?,
+                                ),
+                                DepthPassAction::Clear(1.0),
+                                StoreAction::DontCare,
+                            ),
+                        };
+
+                    let frame_buffer = driver
+                        .create_frame_buffer(FrameBufferDesc {
+                            color_attachements,
+                            depth_stencil_attachement: depth_attachement,
+                            label: Some(label.clone()),
+                        })
+                        .ok_or_else(allocation)?;
+
+                    CompiledPass {
+                        pass: p,
+                        width,
+                        height,
+                        frame_buffer: Some(frame_buffer),
+                        color_actions,
+                        depth_action,
+                        depth_store_action,
+                    }
+                }
+            };
+            passes.push(compiled);
+        }
+        self.depth_pool = depth_used;
+
+        Ok(CompiledGraph { passes, textures })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::null_driver::NullDriver;
+    use super::*;
+    use crate::rs_math3d::*;
+
+    fn rgba8() -> GraphTextureDesc {
+        GraphTextureDesc::new(
+            PixelFormat::RGBA8(MinMagFilter::default()),
+            GraphSize::Relative(1.0),
+        )
+    }
+
+    fn pass(label: &str) -> GraphPass {
+        GraphPass::new(label, |_, _| ())
+    }
+
+    fn clear() -> ColorPassAction {
+        ColorPassAction::Clear(color4b(0, 0, 0, 0))
+    }
+
+    fn screen(label: &str) -> GraphPass {
+        pass(label).with_screen_output(clear(), DepthPassAction::Clear(1.0))
+    }
+
+    fn execute(graph: &mut RenderGraph) -> Result<Vec<String>, RenderGraphError> {
+        let mut driver = NullDriver::new(Vec::new());
+        graph.execute(&mut driver)?;
+        Ok(graph
+            .executed_passes()
+            .iter()
+            .map(|p| String::from(*p))
+            .collect())
+    }
+
+    #[test]
+    fn unread_output_is_culled() {
+        let mut graph = RenderGraph::new(8, 8);
+        let unused = graph.create_texture(rgba8());
+        let used = graph.create_texture(rgba8());
+        graph.add_pass(pass("unused").with_color_output(unused, clear()));
+        graph.add_pass(pass("used").with_color_output(used, clear()));
+        graph.add_pass(screen("screen").with_input(used));
+
+        assert_eq!(execute(&mut graph).unwrap(), ["used", "screen"]);
+    }
+
+    #[test]
+    fn imported_and_marked_outputs_are_kept() {
+        let mut driver = NullDriver::new(Vec::new());
+        let external = driver
+            .create_texture(TextureDesc {
+                sampler_desc: SamplerDesc::default(8, 8),
+                payload: None,
+                label: None,
+            })
+            .unwrap();
+
+        let mut graph = RenderGraph::new(8, 8);
+        let imported = graph.import_texture(&external);
+        let marked = graph.create_texture(rgba8());
+        let culled = graph.create_texture(rgba8());
+        graph.add_pass(pass("imported").with_color_output(imported, clear()));
+        graph.add_pass(pass("marked").with_color_output(marked, clear()));
+        graph.add_pass(pass("culled").with_color_output(culled, clear()));
+        graph.mark_output(marked);
+
+        assert_eq!(execute(&mut graph).unwrap(), ["imported", "marked"]);
+        assert!(graph.texture(marked).is_some());
+        assert!(graph.texture(culled).is_none());
+    }
+
+    #[test]
+    fn write_read_write_keeps_declaration_order() {
+        let mut graph = RenderGraph::new(8, 8);
+        let t = graph.create_texture(rgba8());
+        let u = graph.create_texture(rgba8());
+        graph.add_pass(pass("write t").with_color_output(t, clear()));
+        graph.add_pass(pass("read t").with_input(t).with_color_output(u, clear()));
+        graph.add_pass(pass("rewrite t").with_color_output(t, clear()));
+        graph.add_pass(screen("screen").with_input(t).with_input(u));
+
+        assert_eq!(
+            execute(&mut graph).unwrap(),
+            ["write t", "read t", "rewrite t", "screen"]
+        );
+    }
+
+    #[test]
+    fn read_before_declared_write() {
+        let mut graph = RenderGraph::new(8, 8);
+        let t = graph.create_texture(rgba8());
+        graph.add_pass(screen("screen").with_input(t));
+        graph.add_pass(pass("write t").with_color_output(t, clear()));
+
+        assert_eq!(execute(&mut graph).unwrap(), ["write t", "screen"]);
+    }
+
+    #[test]
+    fn previous_depends_on_former_writer() {
+        let build = |action: ColorPassAction| {
+            let mut graph = RenderGraph::new(8, 8);
+            let t = graph.create_texture(rgba8());
+            graph.add_pass(pass("base").with_color_output(t, clear()));
+            graph.add_pass(pass("overlay").with_color_output(t, action));
+            graph.add_pass(screen("screen").with_input(t));
+            graph
+        };
+
+        let mut previous = build(ColorPassAction::Previous);
+        assert_eq!(
+            execute(&mut previous).unwrap(),
+            ["base", "overlay", "screen"]
+        );
+
+        // a cleared output doesn't need the former content
+        let mut cleared = build(clear());
+        assert_eq!(execute(&mut cleared).unwrap(), ["overlay", "screen"]);
+    }
+
+    #[test]
+    fn reads_own_output() {
+        let mut graph = RenderGraph::new(8, 8);
+        let t = graph.create_texture(rgba8());
+        graph.add_pass(pass("feedback").with_input(t).with_color_output(t, clear()));
+        graph.mark_output(t);
+
+        match execute(&mut graph) {
+            Err(RenderGraphError::ReadsOwnOutput { pass }) => assert_eq!(pass, "feedback"),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn unwritten_input() {
+        let mut graph = RenderGraph::new(8, 8);
+        let t = graph.create_texture(rgba8());
+        graph.add_pass(screen("screen").with_input(t));
+
+        match execute(&mut graph) {
+            Err(RenderGraphError::UnwrittenInput { pass }) => assert_eq!(pass, "screen"),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn cycle() {
+        let mut graph = RenderGraph::new(8, 8);
+        let t = graph.create_texture(rgba8());
+        let u = graph.create_texture(rgba8());
+        graph.add_pass(pass("a").with_input(u).with_color_output(t, clear()));
+        graph.add_pass(pass("b").with_input(t).with_color_output(u, clear()));
+        graph.add_pass(screen("screen").with_input(t));
+
+        match execute(&mut graph) {
+            Err(RenderGraphError::Cycle { passes }) => assert_eq!(passes, ["a", "b", "screen"]),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn unsupported_format_is_an_error() {
+        let mut driver = NullDriver::new(vec![PixelFormat::RGBA32F]);
+        let mut graph = RenderGraph::new(8, 8);
+        let t = graph.create_texture(GraphTextureDesc::new(
+            PixelFormat::RGBA32F,
+            GraphSize::Relative(1.0),
+        ));
+        graph.add_pass(pass("hdr").with_color_output(t, clear()));
+        graph.add_pass(screen("screen").with_input(t));
+
+        match graph.execute(&mut driver) {
+            Err(RenderGraphError::Allocation { pass }) => assert_eq!(pass, "hdr"),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+}
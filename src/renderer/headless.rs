@@ -0,0 +1,311 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+//
+// Headless rendering context
+//
+// A surfaceless (EGL_MESA_platform_surfaceless) or pbuffer EGL context: no
+// window & no display server needed, Mesa llvmpipe included. Rendering goes to
+// an offscreen frame buffer.
+//
+use super::*;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+
+////////////////////////////////////////////////////////////////////////////////
+// EGL
+////////////////////////////////////////////////////////////////////////////////
+
+type EGLDisplay = *mut c_void;
+type EGLConfig = *mut c_void;
+type EGLContext = *mut c_void;
+type EGLSurface = *mut c_void;
+type EGLBoolean = u32;
+type EGLenum = u32;
+type EGLint = i32;
+
+const EGL_NONE: EGLint = 0x3038;
+const EGL_SURFACE_TYPE: EGLint = 0x3033;
+const EGL_PBUFFER_BIT: EGLint = 0x0001;
+const EGL_RENDERABLE_TYPE: EGLint = 0x3040;
+const EGL_OPENGL_ES3_BIT: EGLint = 0x0040;
+const EGL_WIDTH: EGLint = 0x3057;
+const EGL_HEIGHT: EGLint = 0x3056;
+const EGL_EXTENSIONS: EGLint = 0x3055;
+const EGL_CONTEXT_CLIENT_VERSION: EGLint = 0x3098;
+const EGL_OPENGL_ES_API: EGLenum = 0x30A0;
+const EGL_PLATFORM_SURFACELESS_MESA: EGLenum = 0x31DD;
+
+type GetPlatformDisplayFn = extern "C" fn(EGLenum, *mut c_void, *const EGLint) -> EGLDisplay;
+
+extern "C" {
+    fn eglGetProcAddress(name: *const c_char) -> *const c_void;
+    fn eglGetDisplay(display_id: *mut c_void) -> EGLDisplay;
+    fn eglInitialize(dpy: EGLDisplay, major: *mut EGLint, minor: *mut EGLint) -> EGLBoolean;
+    fn eglQueryString(dpy: EGLDisplay, name: EGLint) -> *const c_char;
+    fn eglBindAPI(api: EGLenum) -> EGLBoolean;
+    fn eglChooseConfig(
+        dpy: EGLDisplay,
+        attrib_list: *const EGLint,
+        configs: *mut EGLConfig,
+        config_size: EGLint,
+        num_config: *mut EGLint,
+    ) -> EGLBoolean;
+    fn eglCreateContext(
+        dpy: EGLDisplay,
+        config: EGLConfig,
+        share_context: EGLContext,
+        attrib_list: *const EGLint,
+    ) -> EGLContext;
+    fn eglCreatePbufferSurface(
+        dpy: EGLDisplay,
+        config: EGLConfig,
+        attrib_list: *const EGLint,
+    ) -> EGLSurface;
+    fn eglMakeCurrent(
+        dpy: EGLDisplay,
+        draw: EGLSurface,
+        read: EGLSurface,
+        ctx: EGLContext,
+    ) -> EGLBoolean;
+    fn eglDestroySurface(dpy: EGLDisplay, surface: EGLSurface) -> EGLBoolean;
+    fn eglDestroyContext(dpy: EGLDisplay, ctx: EGLContext) -> EGLBoolean;
+    fn eglReleaseThread() -> EGLBoolean;
+    fn eglGetError() -> EGLint;
+}
+
+fn has_extension(dpy: EGLDisplay, name: &str) -> bool {
+    unsafe {
+        let exts = eglQueryString(dpy, EGL_EXTENSIONS);
+        !exts.is_null()
+            && CStr::from_ptr(exts)
+                .to_bytes()
+                .split(|c| *c == b' ')
+                .any(|ext| ext == name.as_bytes())
+    }
+}
+
+fn get_proc_address(name: &str) -> *const c_void {
+    let name = CString::new(name).unwrap();
+    unsafe { eglGetProcAddress(name.as_ptr()) }
+}
+
+unsafe fn get_display() -> EGLDisplay {
+    // client extensions are queried on EGL_NO_DISPLAY
+    let no_display = std::ptr::null_mut();
+    if has_extension(no_display, "EGL_EXT_platform_base")
+        && has_extension(no_display, "EGL_MESA_platform_surfaceless")
+    {
+        let get_platform_display = get_proc_address("eglGetPlatformDisplayEXT");
+        if !get_platform_display.is_null() {
+            let get_platform_display: GetPlatformDisplayFn =
+                std::mem::transmute(get_platform_display);
+            let attribs = [EGL_NONE];
+            let dpy = get_platform_display(
+                EGL_PLATFORM_SURFACELESS_MESA,
+                std::ptr::null_mut(),
+                attribs.as_ptr(),
+            );
+            if !dpy.is_null() {
+                return dpy;
+            }
+        }
+    }
+    eglGetDisplay(std::ptr::null_mut())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Headless context
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeadlessError {
+    NoDisplay,
+    Initialize(i32),
+    NoConfig,
+    CreateContext(i32),
+    CreateSurface(i32),
+    MakeCurrent(i32),
+    CreateFrameBuffer,
+}
+
+///
+/// an offscreen GLES 3 context with its driver & a color/depth frame buffer.
+/// The context is current on the creating thread, which becomes the driver's
+/// GL thread: render & read back from there
+///
+pub struct HeadlessContext {
+    display: EGLDisplay,
+    context: EGLContext,
+    surface: EGLSurface,
+    width: usize,
+    height: usize,
+    frame_buffer: Option<FrameBufferPtr>,
+    driver: Option<DriverPtr>,
+}
+
+impl HeadlessContext {
+    pub fn new(width: usize, height: usize) -> Result<Self, HeadlessError> {
+        unsafe {
+            let display = get_display();
+            if display.is_null() {
+                return Err(HeadlessError::NoDisplay);
+            }
+
+            let (mut major, mut minor) = (0, 0);
+            if eglInitialize(display, &mut major, &mut minor) == 0 {
+                return Err(HeadlessError::Initialize(eglGetError()));
+            }
+            eglBindAPI(EGL_OPENGL_ES_API);
+
+            let config_attribs = [
+                EGL_SURFACE_TYPE,
+                EGL_PBUFFER_BIT,
+                EGL_RENDERABLE_TYPE,
+                EGL_OPENGL_ES3_BIT,
+                EGL_NONE,
+            ];
+            let mut config = std::ptr::null_mut();
+            let mut num_configs = 0;
+            if eglChooseConfig(
+                display,
+                config_attribs.as_ptr(),
+                &mut config,
+                1,
+                &mut num_configs,
+            ) == 0
+                || num_configs == 0
+            {
+                return Err(HeadlessError::NoConfig);
+            }
+
+            let context_attribs = [EGL_CONTEXT_CLIENT_VERSION, 3, EGL_NONE];
+            let context = eglCreateContext(
+                display,
+                config,
+                std::ptr::null_mut(),
+                context_attribs.as_ptr(),
+            );
+            if context.is_null() {
+                return Err(HeadlessError::CreateContext(eglGetError()));
+            }
+
+            // nothing is rendered to the default frame buffer, a 1x1 pbuffer
+            // is enough when surfaceless contexts are not supported
+            let surface = if has_extension(display, "EGL_KHR_surfaceless_context") {
+                std::ptr::null_mut()
+            } else {
+                let surface_attribs = [EGL_WIDTH, 1, EGL_HEIGHT, 1, EGL_NONE];
+                let surface = eglCreatePbufferSurface(display, config, surface_attribs.as_ptr());
+                if surface.is_null() {
+                    let err = eglGetError();
+                    eglDestroyContext(display, context);
+                    return Err(HeadlessError::CreateSurface(err));
+                }
+                surface
+            };
+
+            if eglMakeCurrent(display, surface, surface, context) == 0 {
+                let err = eglGetError();
+                if !surface.is_null() {
+                    eglDestroySurface(display, surface);
+                }
+                eglDestroyContext(display, context);
+                return Err(HeadlessError::MakeCurrent(err));
+            }
+
+            let mut ctx = Self {
+                display,
+                context,
+                surface,
+                width,
+                height,
+                frame_buffer: None,
+                driver: Some(get_driver_with_loader(get_proc_address)),
+            };
+
+            ctx.frame_buffer =
+                create_color_depth_frame_buffer(ctx.driver.as_mut().unwrap(), width, height);
+            if ctx.frame_buffer.is_none() {
+                return Err(HeadlessError::CreateFrameBuffer);
+            }
+            Ok(ctx)
+        }
+    }
+
+    pub fn driver(&mut self) -> &mut DriverPtr {
+        self.driver.as_mut().unwrap()
+    }
+
+    ///
+    /// the offscreen frame buffer: RGBA8 color texture & D32 depth
+    ///
+    pub fn frame_buffer(&self) -> &FrameBufferPtr {
+        self.frame_buffer.as_ref().unwrap()
+    }
+
+    ///
+    /// the color attachment of the frame buffer, for `Driver::read_back`
+    ///
+    pub fn color_texture(&self) -> TexturePtr {
+        match &self.frame_buffer().desc().color_attachements[0] {
+            Some(SurfaceAttachment::Texture(tex)) => tex.clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self) {
+        // the GL resources have to go while the context is still current
+        self.frame_buffer = None;
+        self.driver = None;
+        unsafe {
+            eglMakeCurrent(
+                self.display,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            if !self.surface.is_null() {
+                eglDestroySurface(self.display, self.surface);
+            }
+            eglDestroyContext(self.display, self.context);
+            eglReleaseThread();
+        }
+    }
+}
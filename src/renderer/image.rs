@@ -0,0 +1,655 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+use super::*;
+use std::path::Path;
+use std::sync::*;
+
+////////////////////////////////////////////////////////////////////////////////
+// Image decoding
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    Png(png::DecodingError),
+    PngEncoding(png::EncodingError),
+    Readback(ReadbackError),
+    UnknownFormat,
+    Unsupported(&'static str),
+    Malformed(&'static str),
+}
+
+impl From<std::io::Error> for ImageError {
+    fn from(e: std::io::Error) -> Self {
+        ImageError::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for ImageError {
+    fn from(e: png::DecodingError) -> Self {
+        ImageError::Png(e)
+    }
+}
+
+impl From<png::EncodingError> for ImageError {
+    fn from(e: png::EncodingError) -> Self {
+        ImageError::PngEncoding(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Tga,
+    Pnm,
+}
+
+impl ImageFormat {
+    ///
+    /// guess the format from the leading bytes, TGA has no signature so it's the fallback
+    ///
+    pub fn guess(bytes: &[u8]) -> Self {
+        const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        if bytes.len() >= 8 && bytes[0..8] == PNG_SIGNATURE {
+            ImageFormat::Png
+        } else if bytes.len() >= 2 && bytes[0] == b'P' && b"2356".contains(&bytes[1]) {
+            ImageFormat::Pnm
+        } else {
+            ImageFormat::Tga
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ImageLoadOptions {
+    pub flip_vertically: bool,
+    pub premultiply_alpha: bool,
+    pub generate_mips: bool,
+    pub filter: MinMagFilter,
+    pub wrap: WrapMode,
+}
+
+impl ImageLoadOptions {
+    pub fn default() -> Self {
+        Self {
+            flip_vertically: false,
+            premultiply_alpha: false,
+            generate_mips: false,
+            filter: MinMagFilter::default(),
+            wrap: WrapMode::Repeat,
+        }
+    }
+
+    pub fn with_flip_vertically(mut self, flip: bool) -> Self {
+        self.flip_vertically = flip;
+        self
+    }
+
+    pub fn with_premultiply_alpha(mut self, premultiply: bool) -> Self {
+        self.premultiply_alpha = premultiply;
+        self
+    }
+
+    ///
+    /// the minification filter is forced to `LinearMipmapLinear` when mips are generated
+    ///
+    pub fn with_mips(mut self, generate: bool) -> Self {
+        self.generate_mips = generate;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: MinMagFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap_mode(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+}
+
+///
+/// 8 bits per channel image, rows are stored top to bottom
+///
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn flip_vertically(&mut self) {
+        let stride = self.width * self.channels;
+        for y in 0..self.height / 2 {
+            let (top, bottom) = self.pixels.split_at_mut((self.height - 1 - y) * stride);
+            top[y * stride..(y + 1) * stride].swap_with_slice(&mut bottom[0..stride]);
+        }
+    }
+
+    pub fn premultiply_alpha(&mut self) {
+        if self.channels != 4 {
+            return;
+        }
+
+        for p in self.pixels.chunks_exact_mut(4) {
+            let a = p[3] as u32;
+            p[0] = ((p[0] as u32 * a + 127) / 255) as u8;
+            p[1] = ((p[1] as u32 * a + 127) / 255) as u8;
+            p[2] = ((p[2] as u32 * a + 127) / 255) as u8;
+        }
+    }
+
+    // expand gray + alpha to RGBA, GL has no matching 2 channels format here
+    fn expand_gray_alpha(&mut self) {
+        if self.channels != 2 {
+            return;
+        }
+
+        let mut pixels = Vec::with_capacity(self.pixels.len() * 2);
+        for p in self.pixels.chunks_exact(2) {
+            pixels.extend_from_slice(&[p[0], p[0], p[0], p[1]]);
+        }
+        self.pixels = pixels;
+        self.channels = 4;
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ImageError> {
+        Self::decode_with_format(bytes, ImageFormat::guess(bytes))
+    }
+
+    pub fn decode_with_format(bytes: &[u8], format: ImageFormat) -> Result<Self, ImageError> {
+        let mut image = match format {
+            ImageFormat::Png => decode_png(bytes)?,
+            ImageFormat::Tga => decode_tga(bytes)?,
+            ImageFormat::Pnm => decode_pnm(bytes)?,
+        };
+        image.expand_gray_alpha();
+        Ok(image)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+        let bytes = std::fs::read(path)?;
+        Self::decode(&bytes)
+    }
+
+    pub fn to_texture_desc(mut self, options: &ImageLoadOptions) -> TextureDesc {
+        if options.flip_vertically {
+            self.flip_vertically();
+        }
+
+        if options.premultiply_alpha {
+            self.premultiply_alpha();
+        }
+
+        let mut filter = options.filter.clone();
+        let mip_maps = if options.generate_mips {
+            filter.min_filter = Filter::LinearMipmapLinear;
+            mip_level_count(self.width, self.height)
+        } else {
+            0
+        };
+
+        let pixel_format = match self.channels {
+            1 => PixelFormat::R8(filter),
+            3 => PixelFormat::RGB8(filter),
+            _ => PixelFormat::RGBA8(filter),
+        };
+
+        TextureDesc {
+            sampler_desc: SamplerDesc::default(self.width, self.height)
+                .with_pixel_format(pixel_format)
+                .with_wrap_mode(options.wrap)
+                .with_mip_maps(mip_maps),
+            payload: Some(Arc::new(self.pixels)),
+            label: None,
+        }
+    }
+}
+
+///
+/// number of levels of a full mip chain down to 1x1
+///
+pub fn mip_level_count(width: usize, height: usize) -> usize {
+    let mut size = usize::max(width, height);
+    let mut levels = 1;
+    while size > 1 {
+        size /= 2;
+        levels += 1;
+    }
+    levels
+}
+
+///
+/// decode a PNG, TGA or PPM/PGM image in memory into a texture descriptor
+///
+pub fn decode_texture_desc(
+    bytes: &[u8],
+    options: &ImageLoadOptions,
+) -> Result<TextureDesc, ImageError> {
+    Ok(Image::decode(bytes)?.to_texture_desc(options))
+}
+
+///
+/// load a PNG, TGA or PPM/PGM image file into a texture descriptor
+///
+pub fn load_texture_desc<P: AsRef<Path>>(
+    path: P,
+    options: &ImageLoadOptions,
+) -> Result<TextureDesc, ImageError> {
+    Ok(Image::load(path)?.to_texture_desc(options))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// PNG
+////////////////////////////////////////////////////////////////////////////////
+fn decode_png(bytes: &[u8]) -> Result<Image, ImageError> {
+    // the decoder limits the output buffer to 64MB by default
+    let mut decoder = png::Decoder::new(bytes);
+    // palette & low bit depths are expanded, 16 bits channels are stripped to 8 bits
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels)?;
+    pixels.truncate(info.buffer_size());
+
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return Err(ImageError::Unsupported("unexpanded palette")),
+    };
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+
+    // rows can be padded when the line size is larger than the packed pixel row
+    let stride = width * channels;
+    if info.line_size != stride {
+        let mut packed = Vec::with_capacity(stride * height);
+        for row in pixels.chunks(info.line_size).take(height) {
+            packed.extend_from_slice(&row[0..stride]);
+        }
+        pixels = packed;
+    }
+
+    Ok(Image {
+        width,
+        height,
+        channels,
+        pixels,
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TGA
+////////////////////////////////////////////////////////////////////////////////
+fn decode_tga(bytes: &[u8]) -> Result<Image, ImageError> {
+    if bytes.len() < 18 {
+        return Err(ImageError::Malformed("truncated TGA header"));
+    }
+
+    let id_len = bytes[0] as usize;
+    let color_map_type = bytes[1];
+    let image_type = bytes[2];
+    let width = u16::from_le_bytes([bytes[12], bytes[13]]) as usize;
+    let height = u16::from_le_bytes([bytes[14], bytes[15]]) as usize;
+    let bpp = bytes[16] as usize;
+    let descriptor = bytes[17];
+
+    if color_map_type != 0 {
+        return Err(ImageError::Unsupported("color mapped TGA"));
+    }
+
+    let rle = match image_type {
+        2 | 3 => false,
+        10 | 11 => true,
+        _ => return Err(ImageError::Unsupported("TGA image type")),
+    };
+
+    let channels = match (image_type, bpp) {
+        (2, 24) | (10, 24) => 3,
+        (2, 32) | (10, 32) => 4,
+        (3, 8) | (11, 8) => 1,
+        _ => return Err(ImageError::Unsupported("TGA pixel depth")),
+    };
+
+    let pixel_count = width
+        .checked_mul(height)
+        .ok_or(ImageError::Malformed("TGA size out of range"))?;
+    let mut data = &bytes[18 + id_len.min(bytes.len() - 18)..];
+
+    // reject sizes the data can't hold before allocating: a raw image stores every pixel,
+    // a RLE packet of at least 2 bytes expands to at most 128 pixels
+    let max_pixel_count = if rle {
+        (data.len() / 2).saturating_mul(128)
+    } else {
+        data.len() / channels
+    };
+    if pixel_count > max_pixel_count {
+        return Err(ImageError::Malformed("truncated TGA data"));
+    }
+
+    let mut pixels = Vec::with_capacity(pixel_count * channels);
+
+    if rle {
+        while pixels.len() < pixel_count * channels {
+            let (&header, rest) = data
+                .split_first()
+                .ok_or(ImageError::Malformed("truncated TGA data"))?;
+            let count = (header & 0x7F) as usize + 1;
+            if header & 0x80 != 0 {
+                if rest.len() < channels {
+                    return Err(ImageError::Malformed("truncated TGA data"));
+                }
+                for _ in 0..count {
+                    pixels.extend_from_slice(&rest[0..channels]);
+                }
+                data = &rest[channels..];
+            } else {
+                if rest.len() < count * channels {
+                    return Err(ImageError::Malformed("truncated TGA data"));
+                }
+                pixels.extend_from_slice(&rest[0..count * channels]);
+                data = &rest[count * channels..];
+            }
+        }
+        pixels.truncate(pixel_count * channels);
+    } else {
+        if data.len() < pixel_count * channels {
+            return Err(ImageError::Malformed("truncated TGA data"));
+        }
+        pixels.extend_from_slice(&data[0..pixel_count * channels]);
+    }
+
+    // BGR(A) to RGB(A)
+    if channels >= 3 {
+        for p in pixels.chunks_exact_mut(channels) {
+            p.swap(0, 2);
+        }
+    }
+
+    let mut image = Image {
+        width,
+        height,
+        channels,
+        pixels,
+    };
+
+    // bit 5 set means the origin is the top left corner, otherwise rows are bottom up
+    if descriptor & 0x20 == 0 {
+        image.flip_vertically();
+    }
+
+    // bit 4 set means the columns are stored right to left
+    if descriptor & 0x10 != 0 {
+        for row in image.pixels.chunks_exact_mut(width * channels) {
+            for x in 0..width / 2 {
+                for c in 0..channels {
+                    row.swap(x * channels + c, (width - 1 - x) * channels + c);
+                }
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// PPM/PGM
+////////////////////////////////////////////////////////////////////////////////
+struct PnmReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PnmReader<'a> {
+    fn skip_whitespace_and_comments(&mut self) {
+        while self.pos < self.bytes.len() {
+            match self.bytes[self.pos] {
+                b'#' => {
+                    while self.pos < self.bytes.len() && self.bytes[self.pos] != b'\n' {
+                        self.pos += 1;
+                    }
+                }
+                c if c.is_ascii_whitespace() => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn read_uint(&mut self) -> Result<usize, ImageError> {
+        self.skip_whitespace_and_comments();
+        let start = self.pos;
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_digit() {
+            self.pos += 1;
+        }
+
+        if start == self.pos {
+            return Err(ImageError::Malformed("expected a number in PNM data"));
+        }
+
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or(ImageError::Malformed("number out of range in PNM data"))
+    }
+}
+
+fn decode_pnm(bytes: &[u8]) -> Result<Image, ImageError> {
+    if bytes.len() < 2 || bytes[0] != b'P' {
+        return Err(ImageError::Malformed("missing PNM magic"));
+    }
+
+    let (channels, binary) = match bytes[1] {
+        b'2' => (1, false),
+        b'3' => (3, false),
+        b'5' => (1, true),
+        b'6' => (3, true),
+        _ => return Err(ImageError::Unsupported("PNM variant")),
+    };
+
+    let mut reader = PnmReader { bytes, pos: 2 };
+    let width = reader.read_uint()?;
+    let height = reader.read_uint()?;
+    let max_val = reader.read_uint()?;
+
+    if max_val == 0 || max_val > 65535 {
+        return Err(ImageError::Malformed("PNM max value out of range"));
+    }
+
+    let sample_count = width
+        .checked_mul(height)
+        .and_then(|c| c.checked_mul(channels))
+        .ok_or(ImageError::Malformed("PNM size out of range"))?;
+
+    // reject sizes the data can't hold before allocating: a binary sample takes 1 or 2 bytes,
+    // an ASCII sample at least one digit
+    let sample_size = if binary && max_val >= 256 { 2 } else { 1 };
+    let max_sample_count = (bytes.len() - reader.pos) / sample_size;
+    if sample_count > max_sample_count {
+        return Err(ImageError::Malformed("truncated PNM data"));
+    }
+
+    let mut samples = Vec::with_capacity(sample_count);
+
+    if binary {
+        // exactly one whitespace character separates the header from the raster
+        let start = reader.pos + 1;
+        let raster = bytes
+            .get(start..start + sample_count * sample_size)
+            .ok_or(ImageError::Malformed("truncated PNM data"))?;
+        if sample_size == 1 {
+            samples.extend(raster.iter().map(|s| *s as usize));
+        } else {
+            samples.extend(
+                raster
+                    .chunks_exact(2)
+                    .map(|s| u16::from_be_bytes([s[0], s[1]]) as usize),
+            );
+        }
+    } else {
+        for _ in 0..sample_count {
+            samples.push(reader.read_uint()?);
+        }
+    }
+
+    let pixels = samples
+        .into_iter()
+        .map(|s| ((usize::min(s, max_val) * 255 + max_val / 2) / max_val) as u8)
+        .collect();
+
+    Ok(Image {
+        width,
+        height,
+        channels,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tga_header(image_type: u8, width: u16, height: u16, bpp: u8, descriptor: u8) -> Vec<u8> {
+        let mut header = vec![0; 18];
+        header[2] = image_type;
+        header[12..14].copy_from_slice(&width.to_le_bytes());
+        header[14..16].copy_from_slice(&height.to_le_bytes());
+        header[16] = bpp;
+        header[17] = descriptor;
+        header
+    }
+
+    #[test]
+    fn png_rgba() {
+        let rgba = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 2);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&rgba).unwrap();
+        }
+
+        assert_eq!(ImageFormat::guess(&bytes), ImageFormat::Png);
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (2, 2, 4));
+        assert_eq!(image.pixels, rgba);
+    }
+
+    #[test]
+    fn png_gray_alpha_is_expanded() {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 1, 1);
+            encoder.set_color(png::ColorType::GrayscaleAlpha);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[7, 200]).unwrap();
+        }
+
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!(image.channels, 4);
+        assert_eq!(image.pixels, [7, 7, 7, 200]);
+    }
+
+    #[test]
+    fn tga_raw_bottom_up() {
+        // 1x2 BGR, bottom row first
+        let mut bytes = tga_header(2, 1, 2, 24, 0);
+        bytes.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+
+        let image = Image::decode_with_format(&bytes, ImageFormat::Tga).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (1, 2, 3));
+        assert_eq!(image.pixels, [6, 5, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn tga_rle_top_down() {
+        // 3x1 gray: a run of 2 then a raw packet of 1, origin bit set
+        let mut bytes = tga_header(11, 3, 1, 8, 0x20);
+        bytes.extend_from_slice(&[0x81, 9, 0x00, 7]);
+
+        let image = Image::decode_with_format(&bytes, ImageFormat::Tga).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (3, 1, 1));
+        assert_eq!(image.pixels, [9, 9, 7]);
+    }
+
+    #[test]
+    fn tga_oversized_header_is_rejected() {
+        let bytes = tga_header(2, 65535, 65535, 32, 0);
+        match Image::decode_with_format(&bytes, ImageFormat::Tga) {
+            Err(ImageError::Malformed(_)) => (),
+            _ => panic!("expected a malformed image error"),
+        }
+
+        let bytes = tga_header(10, 65535, 65535, 32, 0);
+        match Image::decode_with_format(&bytes, ImageFormat::Tga) {
+            Err(ImageError::Malformed(_)) => (),
+            _ => panic!("expected a malformed image error"),
+        }
+    }
+
+    #[test]
+    fn pgm_ascii() {
+        let bytes = b"P2\n# comment\n2 1\n4\n0 4\n";
+        let image = Image::decode(bytes).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (2, 1, 1));
+        assert_eq!(image.pixels, [0, 255]);
+    }
+
+    #[test]
+    fn ppm_binary_16_bits() {
+        let mut bytes = b"P6 1 1 65535\n".to_vec();
+        bytes.extend_from_slice(&[0xFF, 0xFF, 0x80, 0x00, 0x00, 0x00]);
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (1, 1, 3));
+        assert_eq!(image.pixels, [255, 128, 0]);
+    }
+
+    #[test]
+    fn pnm_oversized_header_is_rejected() {
+        for bytes in [
+            &b"P5 4294967296 4294967296 255\n"[..],
+            &b"P6 100000 100000 65535\n"[..],
+            &b"P3 100 100 255\n1 2 3"[..],
+        ] {
+            match Image::decode(bytes) {
+                Err(ImageError::Malformed(_)) => (),
+                _ => panic!("expected a malformed image error"),
+            }
+        }
+    }
+}
@@ -28,13 +28,62 @@
 // POSSIBILITY OF SUCH DAMAGE.
 //
 pub mod common;
+pub mod deferred;
+#[cfg(feature = "gl33")]
+mod gl33;
 mod gles3;
+#[cfg(feature = "image")]
+mod golden;
+mod graph;
+#[cfg(feature = "headless")]
+mod headless;
+#[cfg(feature = "image")]
+mod image;
+#[cfg(test)]
+mod null_driver;
+pub mod oit;
+pub mod post;
+#[cfg(feature = "image")]
+mod screenshot;
 pub mod utils;
+mod validation;
 
 pub use common::*;
-pub use gles3::get_driver;
+pub use gles3::{get_driver, get_driver_with_loader};
+pub use graph::*;
+#[cfg(feature = "headless")]
+pub use headless::*;
 pub use utils::*;
+pub use validation::*;
 
 mod gl {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
+
+///
+/// driver backend, selected at runtime with `get_driver_with_backend`
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Gles3,
+    /// desktop OpenGL 3.3 core profile
+    #[cfg(feature = "gl33")]
+    Gl33,
+}
+
+///
+/// create the driver for `backend`, the context current on the calling thread
+/// must match it. `loader` fetches the GL entry points (glfwGetProcAddress,
+/// eglGetProcAddress...). `get_driver` only covers GLES 3: it relies on the
+/// linked libGLESv2 while desktop GL can only be reached through a loader
+///
+pub fn get_driver_with_backend<F: FnMut(&str) -> *const std::ffi::c_void>(
+    backend: Backend,
+    loader: F,
+) -> DriverPtr {
+    match backend {
+        Backend::Gles3 => gles3::get_driver_with_loader(loader),
+        #[cfg(feature = "gl33")]
+        Backend::Gl33 => gl33::get_driver_with_loader(loader),
+    }
+}
@@ -0,0 +1,147 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+//
+// Driver without GL for the unit tests: resources are only ids & descriptors
+//
+use super::*;
+use crate::rs_math3d::*;
+use std::sync::*;
+
+pub(crate) struct NullDriver {
+    self_ptr: Weak<Mutex<dyn Driver>>,
+    next_id: usize,
+    /// textures & render targets of these formats can't be created
+    unsupported: Vec<PixelFormat>,
+}
+
+impl NullDriver {
+    pub(crate) fn new(unsupported: Vec<PixelFormat>) -> DriverPtr {
+        let driver: Arc<Mutex<dyn Driver>> = Arc::new_cyclic(|me: &Weak<Mutex<NullDriver>>| {
+            let self_ptr: Weak<Mutex<dyn Driver>> = me.clone();
+            Mutex::new(NullDriver {
+                self_ptr,
+                next_id: 0,
+                unsupported,
+            })
+        });
+        DriverPtr::from(driver)
+    }
+
+    fn resource<Desc>(&mut self, res_type: ResourceType, desc: Desc) -> Arc<Resource<Desc>> {
+        self.next_id += 1;
+        Arc::new(Resource::new(
+            res_type,
+            self.next_id,
+            desc,
+            self.self_ptr.upgrade(),
+        ))
+    }
+
+    fn supports(&self, pixel_format: &PixelFormat) -> bool {
+        !self.unsupported.contains(pixel_format)
+    }
+}
+
+impl Driver for NullDriver {
+    fn get_caps(&self) -> DriverCaps {
+        DriverCaps {
+            max_2d_surface_dimension: Dimensioni::new(4096, 4096),
+            float_render_targets: self.supports(&PixelFormat::RGBA32F),
+            float_blending: self.supports(&PixelFormat::RGBA32F),
+        }
+    }
+
+    fn create_device_buffer(&mut self, desc: DeviceBufferDesc) -> Option<DeviceBufferPtr> {
+        Some(self.resource(ResourceType::DeviceBuffer, desc))
+    }
+
+    fn create_texture(&mut self, desc: TextureDesc) -> Option<TexturePtr> {
+        if !self.supports(&desc.sampler_desc.pixel_format) {
+            return None;
+        }
+        Some(self.resource(ResourceType::Texture, desc))
+    }
+
+    fn create_render_target(&mut self, desc: RenderTargetDesc) -> Option<RenderTargetPtr> {
+        if !self.supports(&desc.sampler_desc.pixel_format) {
+            return None;
+        }
+        Some(self.resource(ResourceType::RenderTarget, desc))
+    }
+
+    fn create_shader(&mut self, desc: ShaderDesc) -> Option<ShaderPtr> {
+        Some(self.resource(ResourceType::Shader, desc))
+    }
+
+    fn create_pipeline(&mut self, desc: PipelineDesc) -> Option<PipelinePtr> {
+        Some(self.resource(ResourceType::Pipeline, desc))
+    }
+
+    fn create_frame_buffer(&mut self, desc: FrameBufferDesc) -> Option<FrameBufferPtr> {
+        Some(self.resource(ResourceType::FrameBuffer, desc))
+    }
+
+    fn create_query(&mut self, desc: QueryDesc) -> Option<QueryPtr> {
+        Some(self.resource(ResourceType::Query, desc))
+    }
+
+    fn delete_resource(&mut self, _resource_type: &ResourceType, _res_id: usize) {}
+    fn set_label(&mut self, _resource_type: &ResourceType, _res_id: usize, _label: &str) {}
+
+    fn render_pass(&mut self, _pass: &mut Pass) {}
+
+    fn read_back(
+        &mut self,
+        _surface: &TexturePtr,
+        _x: u32,
+        _y: u32,
+        _w: u32,
+        _h: u32,
+    ) -> Option<ReadbackPayload> {
+        None
+    }
+
+    fn query_result(&mut self, _query: &QueryPtr) -> Option<bool> {
+        None
+    }
+
+    fn stats(&self) -> DriverStats {
+        DriverStats::default()
+    }
+
+    fn end_frame(&mut self) {}
+
+    fn live_resources(&self) -> Vec<LiveResource> {
+        Vec::new()
+    }
+
+    fn set_leak_report(&mut self, _enabled: bool) {}
+    fn set_debug_callback(&mut self, _callback: Option<DebugCallback>) {}
+}
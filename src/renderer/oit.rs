@@ -0,0 +1,236 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+//
+// Weighted blended order independent transparency (McGuire & Bavoil 2013)
+//
+// Translucent surfaces are accumulated without sorting in two float targets:
+//  - 0: rgb = sum(color * alpha * weight), a = revealage = product(1 - alpha)
+//  - 1: r = sum(alpha * weight)
+// GLES 3 has no per attachment blend functions: the separate rgb & alpha blend
+// factors give the sums (One, One) & the product (Zero, OneMinusSrcAlpha) in a
+// single pass. The composite pass blends the weighted average color over the
+// opaque frame buffer.
+//
+// Blending float targets needs EXT_color_buffer_float & EXT_float_blend on GLES,
+// the accumulation targets can't be created without them.
+//
+use super::*;
+use crate::rs_math3d::*;
+
+///
+/// outputs & weight function of the translucent pixel shaders: call
+/// `oit_output(color)` with the straight alpha color
+///
+pub static OIT_OUTPUTS: &'static str = "
+layout(location = 0) out highp vec4 oit_accum;
+layout(location = 1) out highp vec4 oit_weight;
+
+void oit_output(vec4 color) {
+    float a = color.a;
+    float w = clamp(a * max(1e-2, 3e3 * pow(1.0 - gl_FragCoord.z, 3.0)), 1e-2, 3e3);
+    oit_accum = vec4(color.rgb * a * w, a);
+    oit_weight = vec4(a * w);
+}
+";
+
+static COMPOSITE_PIXEL_SHADER: &'static str = "
+#version 300 es
+precision highp float;
+
+in highp    vec2        vUV;
+
+uniform     sampler2D   uAccum;
+uniform     sampler2D   uWeight;
+
+out         vec4        fragColor;
+
+void main() {
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    vec4 accum = texelFetch(uAccum, p, 0);
+    float revealage = accum.a;
+    if (revealage == 1.0) {
+        discard;
+    }
+    float weight = texelFetch(uWeight, p, 0).r;
+    // blended with (OneMinusSrcAlpha, SrcAlpha)
+    fragColor = vec4(accum.rgb / max(weight, 1e-5), revealage);
+}";
+
+///
+/// blend state of the translucent pipelines
+///
+pub fn oit_blend() -> BlendOp {
+    BlendOp::Add(Blend {
+        src_factor_rgb: BlendFactor::One,
+        src_factor_alpha: BlendFactor::Zero,
+        dst_factor_rgb: BlendFactor::One,
+        dst_factor_alpha: BlendFactor::OneMinusSrcAlpha,
+    })
+}
+
+///
+/// the accumulation targets & the composite pass. The translucent pipelines
+/// use `oit_blend`, depth test without depth write & the `OIT_OUTPUTS`
+///
+pub struct WeightedBlendedOit {
+    width: usize,
+    height: usize,
+    frame_buffer: FrameBufferPtr,
+    accum: TexturePtr,
+    weight: TexturePtr,
+    composite: FullscreenEffect<NoUniforms>,
+}
+
+impl WeightedBlendedOit {
+    ///
+    /// `depth` is the depth attachment of the opaque frame buffer: translucent
+    /// surfaces behind the opaque ones are depth tested away. Returns None if the
+    /// driver can't render & blend to float targets
+    ///
+    pub fn new(
+        driver: &mut DriverPtr,
+        width: usize,
+        height: usize,
+        depth: SurfaceAttachment,
+    ) -> Option<Self> {
+        let caps = driver.get_caps();
+        if !caps.float_render_targets || !caps.float_blending {
+            return None;
+        }
+
+        let quad = ScreenQuad::new(driver);
+        let composite = FullscreenEffect::new(
+            driver,
+            &quad,
+            FullscreenEffectDesc::new(COMPOSITE_PIXEL_SHADER)
+                .with_input("uAccum")
+                .with_input("uWeight")
+                .with_blend(BlendOp::Add(Blend {
+                    src_factor_rgb: BlendFactor::OneMinusSrcAlpha,
+                    src_factor_alpha: BlendFactor::OneMinusSrcAlpha,
+                    dst_factor_rgb: BlendFactor::SrcAlpha,
+                    dst_factor_alpha: BlendFactor::SrcAlpha,
+                }))
+                .with_label("oit: composite"),
+        )?;
+
+        let (frame_buffer, accum, weight) = Self::create_targets(driver, width, height, depth)?;
+        Some(Self {
+            width,
+            height,
+            frame_buffer,
+            accum,
+            weight,
+            composite,
+        })
+    }
+
+    fn create_targets(
+        driver: &mut DriverPtr,
+        width: usize,
+        height: usize,
+        depth: SurfaceAttachment,
+    ) -> Option<(FrameBufferPtr, TexturePtr, TexturePtr)> {
+        let mut texture = |pixel_format: PixelFormat, label: &str| {
+            driver.create_texture(TextureDesc {
+                sampler_desc: SamplerDesc::default(width, height).with_pixel_format(pixel_format),
+                payload: None,
+                label: Some(String::from(label)),
+            })
+        };
+        let accum = texture(PixelFormat::RGBA32F, "oit: accum")?;
+        let weight = texture(PixelFormat::R32F, "oit: weight")?;
+
+        let frame_buffer = driver.create_frame_buffer(FrameBufferDesc {
+            color_attachements: [
+                Some(SurfaceAttachment::Texture(accum.clone())),
+                Some(SurfaceAttachment::Texture(weight.clone())),
+                None,
+                None,
+            ],
+            depth_stencil_attachement: depth,
+            label: Some(String::from("oit")),
+        })?;
+        Some((frame_buffer, accum, weight))
+    }
+
+    ///
+    /// reallocate the targets for a new size (& opaque depth attachment),
+    /// returns None & keeps the previous targets if the allocation failed
+    ///
+    pub fn resize(
+        &mut self,
+        driver: &mut DriverPtr,
+        width: usize,
+        height: usize,
+        depth: SurfaceAttachment,
+    ) -> Option<()> {
+        let (frame_buffer, accum, weight) = Self::create_targets(driver, width, height, depth)?;
+        self.width = width;
+        self.height = height;
+        self.frame_buffer = frame_buffer;
+        self.accum = accum;
+        self.weight = weight;
+        Some(())
+    }
+
+    pub fn frame_buffer(&self) -> &FrameBufferPtr {
+        &self.frame_buffer
+    }
+
+    ///
+    /// a pass clearing the accumulation targets & keeping the opaque depth, to
+    /// draw the translucent surfaces into
+    ///
+    pub fn accumulation_pass(&self) -> Pass {
+        Pass::new(
+            self.width,
+            self.height,
+            Some(self.frame_buffer.clone()),
+            [
+                ColorPassAction::ClearValue(ClearValue::Float4(Vec4f::new(0.0, 0.0, 0.0, 1.0))),
+                ColorPassAction::ClearValue(ClearValue::Float4(Vec4f::new(0.0, 0.0, 0.0, 0.0))),
+                ColorPassAction::DontCare,
+                ColorPassAction::DontCare,
+            ],
+            DepthPassAction::Previous,
+        )
+        .with_label("oit: accumulation")
+    }
+
+    ///
+    /// blend the translucent surfaces over `pass`: a pass on the opaque frame
+    /// buffer keeping its content
+    ///
+    pub fn composite(&self, pass: &mut Pass) {
+        self.composite
+            .render(pass, NoUniforms, &[self.accum.clone(), self.weight.clone()]);
+    }
+}
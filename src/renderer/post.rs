@@ -0,0 +1,604 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+//
+// Post processing stack
+//
+// The scene is rendered into an HDR (RGBA32F) frame buffer, then:
+//  - bloom: bright pass & downsample pyramid, upsampled back additively
+//  - composite: bloom, exposure, tonemapping (Reinhard/ACES), vignette & gamma
+//  - FXAA on the tonemapped image
+//
+// Float textures are not filtered (they are always sampled with nearest), the
+// bloom shaders do their own bilinear filtering with texelFetch.
+//
+// The bloom upsample blends additively into the float targets: on GLES 3 this
+// needs EXT_color_buffer_float & EXT_float_blend. Without them the scene and
+// bloom targets fall back to RGBA8 (LDR, bloom only from values up to 1.0).
+//
+use super::*;
+
+////////////////////////////////////////////////////////////////////////////////
+// Settings
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tonemap {
+    /// clamp
+    None,
+    Reinhard,
+    /// Narkowicz's ACES filmic curve fit
+    Aces,
+}
+
+///
+/// the stack settings, they can be changed between frames
+///
+#[derive(Clone, Debug)]
+pub struct PostSettings {
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+
+    pub bloom: bool,
+    /// brightness above which pixels bloom
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    /// number of pyramid levels, clamped to the allocated levels
+    pub bloom_levels: usize,
+
+    pub fxaa: bool,
+
+    pub vignette: bool,
+    /// [0, 1] darkening at the corners
+    pub vignette_intensity: f32,
+    /// [0, 1] distance from the center where the darkening starts
+    pub vignette_radius: f32,
+
+    pub gamma: bool,
+    pub gamma_value: f32,
+}
+
+impl PostSettings {
+    pub fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            tonemap: Tonemap::Aces,
+
+            bloom: true,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.5,
+            bloom_levels: MAX_BLOOM_LEVELS,
+
+            fxaa: true,
+
+            vignette: false,
+            vignette_intensity: 0.4,
+            vignette_radius: 0.5,
+
+            gamma: true,
+            gamma_value: 2.2,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Shaders
+////////////////////////////////////////////////////////////////////////////////
+
+const MAX_BLOOM_LEVELS: usize = 6;
+
+crate::render_data! {
+    uniforms DownsampleUniforms {
+        threshold   : f32,
+    }
+
+    uniforms CompositeUniforms {
+        exposure            : f32,
+        tonemap             : i32,
+        bloom_intensity     : f32,
+        vignette_intensity  : f32,
+        vignette_radius     : f32,
+        gamma               : f32,
+    }
+}
+
+// bilinear filtering of unfiltered (float) textures
+static FETCH_FUNCTIONS: &'static str = "
+vec3 fetch(sampler2D tex, ivec2 p) {
+    return texelFetch(tex, clamp(p, ivec2(0), textureSize(tex, 0) - 1), 0).rgb;
+}
+
+vec3 bilinear(sampler2D tex, vec2 uv) {
+    vec2 p = uv * vec2(textureSize(tex, 0)) - 0.5;
+    ivec2 i = ivec2(floor(p));
+    vec2 f = fract(p);
+    vec3 bottom = mix(fetch(tex, i), fetch(tex, i + ivec2(1, 0)), f.x);
+    vec3 top = mix(fetch(tex, i + ivec2(0, 1)), fetch(tex, i + ivec2(1, 1)), f.x);
+    return mix(bottom, top, f.y);
+}
+";
+
+static SHADER_HEADER: &'static str = "
+#version 300 es
+precision highp float;
+
+in highp    vec2        vUV;
+out         vec4        fragColor;
+";
+
+// 2x2 box downsample, pixels darker than threshold are dropped (soft knee)
+static DOWNSAMPLE_PIXEL_SHADER: &'static str = "
+uniform     sampler2D   uSource;
+uniform     float       threshold;
+
+void main() {
+    ivec2 p = ivec2(gl_FragCoord.xy) * 2;
+    vec3 c = (fetch(uSource, p) + fetch(uSource, p + ivec2(1, 0))
+            + fetch(uSource, p + ivec2(0, 1)) + fetch(uSource, p + ivec2(1, 1))) * 0.25;
+    float brightness = max(c.r, max(c.g, c.b));
+    c *= max(brightness - threshold, 0.0) / max(brightness, 1e-4);
+    fragColor = vec4(c, 1.0);
+}";
+
+// 3x3 tent filter, added to the next (larger) level
+static UPSAMPLE_PIXEL_SHADER: &'static str = "
+uniform     sampler2D   uSource;
+
+void main() {
+    vec2 t = 1.0 / vec2(textureSize(uSource, 0));
+    vec3 c = bilinear(uSource, vUV) * 4.0;
+    c += (bilinear(uSource, vUV + vec2(t.x, 0.0)) + bilinear(uSource, vUV - vec2(t.x, 0.0))
+        + bilinear(uSource, vUV + vec2(0.0, t.y)) + bilinear(uSource, vUV - vec2(0.0, t.y))) * 2.0;
+    c += bilinear(uSource, vUV + t) + bilinear(uSource, vUV - t)
+        + bilinear(uSource, vUV + vec2(t.x, -t.y)) + bilinear(uSource, vUV + vec2(-t.x, t.y));
+    fragColor = vec4(c / 16.0, 1.0);
+}";
+
+static COMPOSITE_PIXEL_SHADER: &'static str = "
+uniform     sampler2D   uScene;
+uniform     sampler2D   uBloom;
+uniform     float       exposure;
+uniform     int         tonemap;
+uniform     float       bloom_intensity;
+uniform     float       vignette_intensity;
+uniform     float       vignette_radius;
+uniform     float       gamma;
+
+vec3 aces(vec3 c) {
+    return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec3 c = fetch(uScene, ivec2(vUV * vec2(textureSize(uScene, 0))));
+    if (bloom_intensity > 0.0) {
+        c += bilinear(uBloom, vUV) * bloom_intensity;
+    }
+    c *= exposure;
+
+    if (tonemap == 1) {
+        c = c / (1.0 + c);
+    } else if (tonemap == 2) {
+        c = aces(c);
+    }
+    c = clamp(c, 0.0, 1.0);
+
+    float d = length(vUV - 0.5) * 1.41421356;
+    c *= 1.0 - vignette_intensity * smoothstep(vignette_radius, 1.0, d);
+
+    c = pow(c, vec3(1.0 / gamma));
+    fragColor = vec4(c, 1.0);
+}";
+
+// FXAA (Timothy Lottes' FXAA 3.11 console variant)
+static FXAA_PIXEL_SHADER: &'static str = "
+uniform     sampler2D   uSource;
+
+const float REDUCE_MIN = 1.0 / 128.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float SPAN_MAX = 8.0;
+
+float luma(vec3 c) {
+    return dot(c, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec2 t = 1.0 / vec2(textureSize(uSource, 0));
+    vec3 rgb_m = texture(uSource, vUV).rgb;
+    float nw = luma(texture(uSource, vUV + vec2(-t.x, t.y)).rgb);
+    float ne = luma(texture(uSource, vUV + t).rgb);
+    float sw = luma(texture(uSource, vUV - t).rgb);
+    float se = luma(texture(uSource, vUV + vec2(t.x, -t.y)).rgb);
+    float m = luma(rgb_m);
+
+    float luma_min = min(m, min(min(nw, ne), min(sw, se)));
+    float luma_max = max(m, max(max(nw, ne), max(sw, se)));
+
+    vec2 dir = vec2(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+    float dir_reduce = max((nw + ne + sw + se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * t;
+
+    vec3 rgb_a = 0.5 * (texture(uSource, vUV + dir * (1.0 / 3.0 - 0.5)).rgb
+                      + texture(uSource, vUV + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 rgb_b = rgb_a * 0.5 + 0.25 * (texture(uSource, vUV - dir * 0.5).rgb
+                                     + texture(uSource, vUV + dir * 0.5).rgb);
+    float luma_b = luma(rgb_b);
+    fragColor = vec4((luma_b < luma_min || luma_b > luma_max) ? rgb_a : rgb_b, 1.0);
+}";
+
+fn pixel_shader(body: &str, fetch: bool) -> String {
+    let mut src = String::from(SHADER_HEADER);
+    if fetch {
+        src.push_str(FETCH_FUNCTIONS);
+    }
+    src.push_str(body);
+    src
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Stack
+////////////////////////////////////////////////////////////////////////////////
+
+struct Target {
+    frame_buffer: FrameBufferPtr,
+    texture: TexturePtr,
+    width: usize,
+    height: usize,
+}
+
+impl Target {
+    fn new(
+        driver: &mut DriverPtr,
+        width: usize,
+        height: usize,
+        pixel_format: PixelFormat,
+        label: &str,
+    ) -> Self {
+        let texture = driver
+            .create_texture(TextureDesc {
+                sampler_desc: SamplerDesc::default(width, height)
+                    .with_pixel_format(pixel_format)
+                    .with_wrap_mode(WrapMode::ClampToEdge),
+                payload: None,
+                label: Some(String::from(label)),
+            })
+            .unwrap();
+        let depth = driver
+            .create_render_target(RenderTargetDesc {
+                sampler_desc: SamplerDesc::default(width, height)
+                    .with_pixel_format(PixelFormat::D32),
+                sample_count: 0,
+                label: Some(format!("{} depth", label)),
+            })
+            .unwrap();
+        let frame_buffer = driver
+            .create_frame_buffer(FrameBufferDesc {
+                color_attachements: [
+                    Some(SurfaceAttachment::Texture(texture.clone())),
+                    None,
+                    None,
+                    None,
+                ],
+                depth_stencil_attachement: SurfaceAttachment::RenderTarget(depth),
+                label: Some(String::from(label)),
+            })
+            .unwrap();
+
+        Self {
+            frame_buffer,
+            texture,
+            width,
+            height,
+        }
+    }
+}
+
+fn effect_pass(
+    frame_buffer: Option<FrameBufferPtr>,
+    width: usize,
+    height: usize,
+    color_action: ColorPassAction,
+    label: &str,
+) -> Pass {
+    Pass::new(
+        width,
+        height,
+        frame_buffer,
+        [
+            color_action,
+            ColorPassAction::DontCare,
+            ColorPassAction::DontCare,
+            ColorPassAction::DontCare,
+        ],
+        DepthPassAction::DontCare,
+    )
+    .with_label(label)
+}
+
+///
+/// render the scene into `scene_frame_buffer` & call `render` to post process
+/// it into the output
+///
+pub struct PostStack {
+    pub settings: PostSettings,
+
+    hdr_format: PixelFormat,
+    scene: Target,
+    bloom: Vec<Target>,
+    ldr: Target,
+
+    downsample: FullscreenEffect<DownsampleUniforms>,
+    upsample: FullscreenEffect<NoUniforms>,
+    composite: FullscreenEffect<CompositeUniforms>,
+    fxaa: FullscreenEffect<NoUniforms>,
+}
+
+impl PostStack {
+    pub fn new(driver: &mut DriverPtr, width: usize, height: usize) -> Self {
+        let quad = ScreenQuad::new(driver);
+
+        let downsample = FullscreenEffect::new(
+            driver,
+            &quad,
+            FullscreenEffectDesc::new(&pixel_shader(DOWNSAMPLE_PIXEL_SHADER, true))
+                .with_input("uSource")
+                .with_label("post: downsample"),
+        )
+        .unwrap();
+
+        let additive = Blend {
+            src_factor_rgb: BlendFactor::One,
+            src_factor_alpha: BlendFactor::One,
+            dst_factor_rgb: BlendFactor::One,
+            dst_factor_alpha: BlendFactor::One,
+        };
+        let upsample = FullscreenEffect::new(
+            driver,
+            &quad,
+            FullscreenEffectDesc::new(&pixel_shader(UPSAMPLE_PIXEL_SHADER, true))
+                .with_input("uSource")
+                .with_blend(BlendOp::Add(additive))
+                .with_label("post: upsample"),
+        )
+        .unwrap();
+
+        let composite = FullscreenEffect::new(
+            driver,
+            &quad,
+            FullscreenEffectDesc::new(&pixel_shader(COMPOSITE_PIXEL_SHADER, true))
+                .with_input("uScene")
+                .with_input("uBloom")
+                .with_label("post: composite"),
+        )
+        .unwrap();
+
+        let fxaa = FullscreenEffect::new(
+            driver,
+            &quad,
+            FullscreenEffectDesc::new(&pixel_shader(FXAA_PIXEL_SHADER, false))
+                .with_input("uSource")
+                .with_label("post: fxaa"),
+        )
+        .unwrap();
+
+        let caps = driver.get_caps();
+        let hdr_format = if caps.float_render_targets && caps.float_blending {
+            PixelFormat::RGBA32F
+        } else {
+            PixelFormat::RGBA8(MinMagFilter::default())
+        };
+
+        let (scene, bloom, ldr) = Self::create_targets(driver, hdr_format.clone(), width, height);
+        Self {
+            settings: PostSettings::default(),
+            hdr_format,
+            scene,
+            bloom,
+            ldr,
+            downsample,
+            upsample,
+            composite,
+            fxaa,
+        }
+    }
+
+    fn create_targets(
+        driver: &mut DriverPtr,
+        hdr_format: PixelFormat,
+        width: usize,
+        height: usize,
+    ) -> (Target, Vec<Target>, Target) {
+        let scene = Target::new(driver, width, height, hdr_format.clone(), "post: scene");
+
+        let mut bloom = Vec::new();
+        let (mut w, mut h) = (width / 2, height / 2);
+        while bloom.len() < MAX_BLOOM_LEVELS && w > 0 && h > 0 {
+            let label = format!("post: bloom {}", bloom.len());
+            bloom.push(Target::new(driver, w, h, hdr_format.clone(), &label));
+            w /= 2;
+            h /= 2;
+        }
+        // keep at least one level for the composite input
+        if bloom.is_empty() {
+            bloom.push(Target::new(driver, 1, 1, hdr_format, "post: bloom 0"));
+        }
+
+        let linear = MinMagFilter::default()
+            .with_min_filter(Filter::Linear)
+            .with_mag_filter(Filter::Linear);
+        let ldr = Target::new(
+            driver,
+            width,
+            height,
+            PixelFormat::RGBA8(linear),
+            "post: ldr",
+        );
+        (scene, bloom, ldr)
+    }
+
+    ///
+    /// reallocate the targets if the size changed
+    ///
+    pub fn resize(&mut self, driver: &mut DriverPtr, width: usize, height: usize) {
+        if self.scene.width == width && self.scene.height == height {
+            return;
+        }
+        let (scene, bloom, ldr) =
+            Self::create_targets(driver, self.hdr_format.clone(), width, height);
+        self.scene = scene;
+        self.bloom = bloom;
+        self.ldr = ldr;
+    }
+
+    ///
+    /// the HDR frame buffer to render the scene into (RGBA32F color, or RGBA8
+    /// without float render targets, & D32 depth)
+    ///
+    pub fn scene_frame_buffer(&self) -> &FrameBufferPtr {
+        &self.scene.frame_buffer
+    }
+
+    pub fn scene_texture(&self) -> &TexturePtr {
+        &self.scene.texture
+    }
+
+    pub fn width(&self) -> usize {
+        self.scene.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.scene.height
+    }
+
+    ///
+    /// post process the scene into `output` (the screen if None), the output
+    /// has the size of the stack
+    ///
+    pub fn render(&self, driver: &mut DriverPtr, output: Option<&FrameBufferPtr>) {
+        let settings = &self.settings;
+        let levels = settings.bloom_levels.max(1).min(self.bloom.len());
+
+        if settings.bloom {
+            let mut source = &self.scene;
+            for (i, level) in self.bloom[0..levels].iter().enumerate() {
+                let mut pass = effect_pass(
+                    Some(level.frame_buffer.clone()),
+                    level.width,
+                    level.height,
+                    ColorPassAction::DontCare,
+                    "post: bloom downsample",
+                );
+                let threshold = if i == 0 {
+                    settings.bloom_threshold
+                } else {
+                    0.0
+                };
+                self.downsample.render(
+                    &mut pass,
+                    DownsampleUniforms { threshold },
+                    &[source.texture.clone()],
+                );
+                driver.render_pass(&mut pass);
+                source = level;
+            }
+
+            for i in (1..levels).rev() {
+                let target = &self.bloom[i - 1];
+                let mut pass = effect_pass(
+                    Some(target.frame_buffer.clone()),
+                    target.width,
+                    target.height,
+                    ColorPassAction::Previous,
+                    "post: bloom upsample",
+                );
+                self.upsample
+                    .render(&mut pass, NoUniforms, &[self.bloom[i].texture.clone()]);
+                driver.render_pass(&mut pass);
+            }
+        }
+
+        let uniforms = CompositeUniforms {
+            exposure: settings.exposure,
+            tonemap: match settings.tonemap {
+                Tonemap::None => 0,
+                Tonemap::Reinhard => 1,
+                Tonemap::Aces => 2,
+            },
+            bloom_intensity: if settings.bloom {
+                settings.bloom_intensity
+            } else {
+                0.0
+            },
+            vignette_intensity: if settings.vignette {
+                settings.vignette_intensity
+            } else {
+                0.0
+            },
+            vignette_radius: settings.vignette_radius,
+            gamma: if settings.gamma {
+                settings.gamma_value
+            } else {
+                1.0
+            },
+        };
+
+        let (width, height) = (self.scene.width, self.scene.height);
+        let composite_target = if settings.fxaa {
+            Some(self.ldr.frame_buffer.clone())
+        } else {
+            output.cloned()
+        };
+        let mut pass = effect_pass(
+            composite_target,
+            width,
+            height,
+            ColorPassAction::DontCare,
+            "post: composite",
+        );
+        self.composite.render(
+            &mut pass,
+            uniforms,
+            &[self.scene.texture.clone(), self.bloom[0].texture.clone()],
+        );
+        driver.render_pass(&mut pass);
+
+        if settings.fxaa {
+            let mut pass = effect_pass(
+                output.cloned(),
+                width,
+                height,
+                ColorPassAction::DontCare,
+                "post: fxaa",
+            );
+            self.fxaa
+                .render(&mut pass, NoUniforms, &[self.ldr.texture.clone()]);
+            driver.render_pass(&mut pass);
+        }
+    }
+}
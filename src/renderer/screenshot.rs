@@ -0,0 +1,621 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+use super::*;
+use std::io::Write;
+use std::path::Path;
+
+////////////////////////////////////////////////////////////////////////////////
+// Screenshots & surface export
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Png,
+    Ppm,
+    Exr,
+    Pfm,
+}
+
+impl ExportFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(ExportFormat::Png),
+            "ppm" | "pgm" => Some(ExportFormat::Ppm),
+            "exr" => Some(ExportFormat::Exr),
+            "pfm" => Some(ExportFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        match self {
+            ExportFormat::Png | ExportFormat::Ppm => false,
+            ExportFormat::Exr | ExportFormat::Pfm => true,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ExportOptions {
+    /// (near, far) planes used to linearize & normalize depth, raw depth is written otherwise
+    pub depth_planes: Option<(f32, f32)>,
+    /// override the format deduced from the file extension
+    pub format: Option<ExportFormat>,
+}
+
+impl ExportOptions {
+    pub fn default() -> Self {
+        Self {
+            depth_planes: None,
+            format: None,
+        }
+    }
+
+    pub fn with_depth_planes(mut self, near: f32, far: f32) -> Self {
+        self.depth_planes = Some((near, far));
+        self
+    }
+
+    pub fn with_format(mut self, format: ExportFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+}
+
+///
+/// 32 bits float image, rows are stored top to bottom
+///
+pub struct FloatImage {
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    pub pixels: Vec<f32>,
+}
+
+impl FloatImage {
+    pub fn to_image(&self) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            channels: self.channels,
+            pixels: self
+                .pixels
+                .iter()
+                .map(|p| (p.clamp(0.0, 1.0) * 255.0 + 0.5) as u8)
+                .collect(),
+        }
+    }
+}
+
+impl Image {
+    pub fn to_float_image(&self) -> FloatImage {
+        FloatImage {
+            width: self.width,
+            height: self.height,
+            channels: self.channels,
+            pixels: self.pixels.iter().map(|p| *p as f32 / 255.0).collect(),
+        }
+    }
+}
+
+pub enum ExportImage {
+    Ldr(Image),
+    Hdr(FloatImage),
+    /// 32 bits unsigned integer surfaces (e.g. object ids): clamping them to 8 bits
+    /// would lose the values, they are only exported to float formats
+    UInt(FloatImage),
+}
+
+impl ExportImage {
+    ///
+    /// convert a readback payload (bottom up rows) of the given pixel format to an image
+    ///
+    pub fn from_readback(
+        payload: ReadbackPayload,
+        pixel_format: &PixelFormat,
+        width: usize,
+        height: usize,
+        depth_planes: Option<(f32, f32)>,
+    ) -> Self {
+        let (channels, pixels): (usize, Vec<f32>) = match payload {
+            ReadbackPayload::RGB32U(v) => (
+                3,
+                v.iter()
+                    .flat_map(|p| [p.x as f32, p.y as f32, p.z as f32])
+                    .collect(),
+            ),
+            ReadbackPayload::RGBA32U(v) => (
+                4,
+                v.iter()
+                    .flat_map(|p| [p.x as f32, p.y as f32, p.z as f32, p.w as f32])
+                    .collect(),
+            ),
+            ReadbackPayload::R32U(v) => (1, v.iter().map(|p| *p as f32).collect()),
+            ReadbackPayload::RGB32F(v) => (3, v.iter().flat_map(|p| [p.x, p.y, p.z]).collect()),
+            ReadbackPayload::RGBA32F(v) => {
+                (4, v.iter().flat_map(|p| [p.x, p.y, p.z, p.w]).collect())
+            }
+            ReadbackPayload::R32F(v) => (1, v),
+            ReadbackPayload::Depth(v) => match depth_planes {
+                Some((near, far)) => (
+                    1,
+                    v.iter().map(|d| linearize_depth(*d, near, far)).collect(),
+                ),
+                None => (1, v),
+            },
+        };
+
+        let mut image = FloatImage {
+            width,
+            height,
+            channels,
+            pixels,
+        };
+        flip_rows(&mut image.pixels, width * channels, height);
+
+        match pixel_format {
+            PixelFormat::RGB8(_) | PixelFormat::RGBA8(_) | PixelFormat::R8(_) => {
+                ExportImage::Ldr(image.to_image())
+            }
+            PixelFormat::RGB8U | PixelFormat::RGBA8U | PixelFormat::R8U => {
+                image.pixels.iter_mut().for_each(|p| *p /= 255.0);
+                ExportImage::Ldr(image.to_image())
+            }
+            PixelFormat::RGB32U | PixelFormat::RGBA32U | PixelFormat::R32U => {
+                ExportImage::UInt(image)
+            }
+            _ => ExportImage::Hdr(image),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: ExportFormat) -> Result<(), ImageError> {
+        match (self, format.is_float()) {
+            (ExportImage::Ldr(image), false) => write_ldr(path, image, format),
+            (ExportImage::Ldr(image), true) => write_hdr(path, &image.to_float_image(), format),
+            (ExportImage::Hdr(image), false) => write_ldr(path, &image.to_image(), format),
+            (ExportImage::Hdr(image), true) => write_hdr(path, image, format),
+            (ExportImage::UInt(_), false) => Err(ImageError::Unsupported(
+                "8 bits export of 32 bits integer surfaces",
+            )),
+            (ExportImage::UInt(image), true) => write_hdr(path, image, format),
+        }
+    }
+}
+
+///
+/// map a [0, 1] depth buffer value to [0, 1] linear depth between the near & far planes
+///
+pub fn linearize_depth(depth: f32, near: f32, far: f32) -> f32 {
+    let z_ndc = depth * 2.0 - 1.0;
+    let z_eye = 2.0 * near * far / (far + near - z_ndc * (far - near));
+    (z_eye - near) / (far - near)
+}
+
+fn flip_rows<T>(pixels: &mut [T], stride: usize, height: usize) {
+    for y in 0..height / 2 {
+        let (top, bottom) = pixels.split_at_mut((height - 1 - y) * stride);
+        top[y * stride..(y + 1) * stride].swap_with_slice(&mut bottom[0..stride]);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Readback
+////////////////////////////////////////////////////////////////////////////////
+
+///
+/// read back the whole texture into an image
+///
+pub fn read_back_texture(
+    driver: &mut DriverPtr,
+    tex: &TexturePtr,
+    depth_planes: Option<(f32, f32)>,
+) -> Result<ExportImage, ImageError> {
+    let sampler_desc = &tex.desc().sampler_desc;
+    let width = sampler_desc.width();
+    let height = sampler_desc.height();
+    let payload = driver
+        .read_back(tex, 0, 0, width as u32, height as u32)
+        .ok_or(ImageError::Unsupported("surface readback"))?;
+
+    Ok(ExportImage::from_readback(
+        payload,
+        &sampler_desc.pixel_format,
+        width,
+        height,
+        depth_planes,
+    ))
+}
+
+fn attachment_texture(attachment: &SurfaceAttachment) -> Result<&TexturePtr, ImageError> {
+    match attachment {
+        SurfaceAttachment::Texture(tex) => Ok(tex),
+        SurfaceAttachment::RenderTarget(_) => Err(ImageError::Readback(
+            ReadbackError::NoReadbackFromRenderTarget,
+        )),
+    }
+}
+
+fn export_format<P: AsRef<Path>>(
+    path: P,
+    options: &ExportOptions,
+) -> Result<ExportFormat, ImageError> {
+    options
+        .format
+        .or_else(|| ExportFormat::from_path(path))
+        .ok_or(ImageError::UnknownFormat)
+}
+
+///
+/// save a texture to an image file, the format is deduced from the extension
+///
+pub fn save_texture<P: AsRef<Path>>(
+    driver: &mut DriverPtr,
+    tex: &TexturePtr,
+    path: P,
+    options: &ExportOptions,
+) -> Result<(), ImageError> {
+    let format = export_format(&path, options)?;
+    read_back_texture(driver, tex, options.depth_planes)?.save(path, format)
+}
+
+///
+/// save a color attachment of a frame buffer to an image file
+///
+pub fn save_frame_buffer<P: AsRef<Path>>(
+    driver: &mut DriverPtr,
+    fb: &FrameBufferPtr,
+    attachment: usize,
+    path: P,
+    options: &ExportOptions,
+) -> Result<(), ImageError> {
+    let color = fb
+        .desc()
+        .color_attachements
+        .get(attachment)
+        .and_then(|a| a.as_ref())
+        .ok_or(ImageError::Unsupported("empty color attachment"))?;
+    save_texture(driver, attachment_texture(color)?, path, options)
+}
+
+///
+/// save the depth attachment of a frame buffer to an image file
+///
+pub fn save_frame_buffer_depth<P: AsRef<Path>>(
+    driver: &mut DriverPtr,
+    fb: &FrameBufferPtr,
+    path: P,
+    options: &ExportOptions,
+) -> Result<(), ImageError> {
+    let depth = attachment_texture(&fb.desc().depth_stencil_attachement)?;
+    save_texture(driver, depth, path, options)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Writers
+////////////////////////////////////////////////////////////////////////////////
+fn write_ldr<P: AsRef<Path>>(
+    path: P,
+    image: &Image,
+    format: ExportFormat,
+) -> Result<(), ImageError> {
+    match format {
+        ExportFormat::Png => write_png(path, image),
+        _ => write_ppm(path, image),
+    }
+}
+
+fn write_hdr<P: AsRef<Path>>(
+    path: P,
+    image: &FloatImage,
+    format: ExportFormat,
+) -> Result<(), ImageError> {
+    match format {
+        ExportFormat::Exr => write_exr(path, image),
+        _ => write_pfm(path, image),
+    }
+}
+
+///
+/// keep the first `channels` channels of each pixel
+///
+fn select_channels<T: Copy>(pixels: &[T], src_channels: usize, channels: usize) -> Vec<T> {
+    if src_channels == channels {
+        return pixels.to_vec();
+    }
+
+    pixels
+        .chunks_exact(src_channels)
+        .flat_map(|p| p[0..channels].iter().copied())
+        .collect()
+}
+
+pub fn write_png<P: AsRef<Path>>(path: P, image: &Image) -> Result<(), ImageError> {
+    let color_type = match image.channels {
+        1 => png::ColorType::Grayscale,
+        2 => png::ColorType::GrayscaleAlpha,
+        3 => png::ColorType::Rgb,
+        4 => png::ColorType::Rgba,
+        _ => return Err(ImageError::Unsupported("PNG channel count")),
+    };
+
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, image.width as u32, image.height as u32);
+    encoder.set_color(color_type);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.pixels)?;
+    Ok(())
+}
+
+///
+/// write a binary PGM for single channel images & a binary PPM otherwise (alpha is dropped)
+///
+pub fn write_ppm<P: AsRef<Path>>(path: P, image: &Image) -> Result<(), ImageError> {
+    let (magic, channels) = match image.channels {
+        1 | 2 => ("P5", 1),
+        _ => ("P6", 3),
+    };
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write!(file, "{}\n{} {}\n255\n", magic, image.width, image.height)?;
+    file.write_all(&select_channels(&image.pixels, image.channels, channels))?;
+    Ok(())
+}
+
+///
+/// write a little endian PFM, rows are stored bottom to top
+///
+pub fn write_pfm<P: AsRef<Path>>(path: P, image: &FloatImage) -> Result<(), ImageError> {
+    let (magic, channels) = match image.channels {
+        1 | 2 => ("Pf", 1),
+        _ => ("PF", 3),
+    };
+
+    let mut pixels = select_channels(&image.pixels, image.channels, channels);
+    flip_rows(&mut pixels, image.width * channels, image.height);
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write!(file, "{}\n{} {}\n-1.0\n", magic, image.width, image.height)?;
+    for p in pixels {
+        file.write_all(&p.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+///
+/// write an uncompressed scanline OpenEXR file with 32 bits float channels
+///
+pub fn write_exr<P: AsRef<Path>>(path: P, image: &FloatImage) -> Result<(), ImageError> {
+    // channels must be sorted by name, `order` maps them back to the source channel index
+    let (names, order): (&[&str], &[usize]) = match image.channels {
+        1 => (&["Y"], &[0]),
+        2 => (&["A", "Y"], &[1, 0]),
+        3 => (&["B", "G", "R"], &[2, 1, 0]),
+        4 => (&["A", "B", "G", "R"], &[3, 2, 1, 0]),
+        _ => return Err(ImageError::Unsupported("EXR channel count")),
+    };
+
+    fn attribute(header: &mut Vec<u8>, name: &str, ty: &str, value: &[u8]) {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(ty.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    }
+
+    let width = image.width as i32;
+    let height = image.height as i32;
+
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+    let mut chlist = Vec::new();
+    for name in names {
+        chlist.extend_from_slice(name.as_bytes());
+        chlist.push(0);
+        chlist.extend_from_slice(&2i32.to_le_bytes()); // FLOAT
+        chlist.extend_from_slice(&[0, 0, 0, 0]); // pLinear + reserved
+        chlist.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+        chlist.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+    }
+    chlist.push(0);
+    attribute(&mut header, "channels", "chlist", &chlist);
+    attribute(&mut header, "compression", "compression", &[0]);
+
+    let mut window = Vec::new();
+    for v in &[0, 0, width - 1, height - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1.0f32.to_le_bytes(),
+    );
+
+    let mut center = Vec::new();
+    center.extend_from_slice(&0.0f32.to_le_bytes());
+    center.extend_from_slice(&0.0f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &center);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1.0f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    // one scanline per chunk: y, size & the channels one after the other
+    let line_size = image.width * names.len() * 4;
+    let chunk_size = 8 + line_size;
+    let table_size = image.height * 8;
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    file.write_all(&header)?;
+    for y in 0..image.height {
+        let offset = (header.len() + table_size + y * chunk_size) as u64;
+        file.write_all(&offset.to_le_bytes())?;
+    }
+
+    for y in 0..image.height {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&(line_size as i32).to_le_bytes())?;
+        let row = &image.pixels[y * image.width * image.channels..][..image.width * image.channels];
+        for c in order {
+            for x in 0..image.width {
+                file.write_all(&row[x * image.channels + c].to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("neocogi-{}-{}", std::process::id(), name))
+    }
+
+    fn round_trip(name: &str, image: Image, format: ExportFormat) -> Image {
+        let path = temp_path(name);
+        ExportImage::Ldr(image).save(&path, format).unwrap();
+        let loaded = Image::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        loaded.unwrap()
+    }
+
+    fn rgb() -> Image {
+        Image {
+            width: 2,
+            height: 2,
+            channels: 3,
+            pixels: vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 10, 20, 30],
+        }
+    }
+
+    #[test]
+    fn ppm_round_trip() {
+        let loaded = round_trip("rgb.ppm", rgb(), ExportFormat::Ppm);
+        assert_eq!((loaded.width, loaded.height, loaded.channels), (2, 2, 3));
+        assert_eq!(loaded.pixels, rgb().pixels);
+
+        let gray = Image {
+            width: 3,
+            height: 1,
+            channels: 1,
+            pixels: vec![0, 128, 255],
+        };
+        let loaded = round_trip("gray.pgm", gray, ExportFormat::Ppm);
+        assert_eq!(loaded.channels, 1);
+        assert_eq!(loaded.pixels, [0, 128, 255]);
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let rgba = Image {
+            width: 2,
+            height: 1,
+            channels: 4,
+            pixels: vec![1, 2, 3, 4, 250, 251, 252, 253],
+        };
+        let loaded = round_trip("rgba.png", rgba, ExportFormat::Png);
+        assert_eq!((loaded.width, loaded.height, loaded.channels), (2, 1, 4));
+        assert_eq!(loaded.pixels, [1, 2, 3, 4, 250, 251, 252, 253]);
+    }
+
+    #[test]
+    fn pfm_rows_bottom_up() {
+        let image = FloatImage {
+            width: 1,
+            height: 2,
+            channels: 1,
+            pixels: vec![0.25, 0.75],
+        };
+        let path = temp_path("gray.pfm");
+        write_pfm(&path, &image).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut expected = b"Pf\n1 2\n-1.0\n".to_vec();
+        expected.extend_from_slice(&0.75f32.to_le_bytes());
+        expected.extend_from_slice(&0.25f32.to_le_bytes());
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn exr_scanlines() {
+        let image = FloatImage {
+            width: 1,
+            height: 1,
+            channels: 1,
+            pixels: vec![0.5],
+        };
+        let path = temp_path("gray.exr");
+        write_exr(&path, &image).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[0..4], &[0x76, 0x2f, 0x31, 0x01]);
+        // the single scanline chunk: y, data size & the pixel
+        let chunk = &bytes[bytes.len() - 12..];
+        assert_eq!(&chunk[0..4], &0i32.to_le_bytes());
+        assert_eq!(&chunk[4..8], &4i32.to_le_bytes());
+        assert_eq!(&chunk[8..12], &0.5f32.to_le_bytes());
+    }
+
+    #[test]
+    fn integer_readback() {
+        let image = ExportImage::from_readback(
+            ReadbackPayload::R32U(vec![1, 70000]),
+            &PixelFormat::R32U,
+            1,
+            2,
+            None,
+        );
+        match &image {
+            ExportImage::UInt(image) => assert_eq!(image.pixels, [70000.0, 1.0]),
+            _ => panic!("32 bits integers should not be clamped"),
+        }
+
+        let path = temp_path("ids.png");
+        match image.save(&path, ExportFormat::Png) {
+            Err(ImageError::Unsupported(_)) => (),
+            _ => panic!("8 bits export of integer surfaces should fail"),
+        }
+        assert!(!path.exists());
+    }
+}
@@ -31,6 +31,13 @@ use super::*;
 use crate::rs_math3d::*;
 use std::sync::*;
 
+#[cfg(feature = "image")]
+pub use super::golden::*;
+#[cfg(feature = "image")]
+pub use super::image::*;
+#[cfg(feature = "image")]
+pub use super::screenshot::*;
+
 ///
 /// create a frame buffer with only color & depth render targets
 ///
@@ -44,6 +51,7 @@ pub fn create_color_depth_frame_buffer(
     let color_buffer_desc = TextureDesc {
         sampler_desc: color_tex_desc,
         payload: None,
+        label: None,
     };
     let color_buffer = driver.create_texture(color_buffer_desc).unwrap();
 
@@ -51,6 +59,7 @@ pub fn create_color_depth_frame_buffer(
     let depth_buffer_desc = RenderTargetDesc {
         sampler_desc: depth_tex_desc,
         sample_count: 0,
+        label: None,
     };
     let depth_buffer = driver.create_render_target(depth_buffer_desc).unwrap();
 
@@ -62,6 +71,7 @@ pub fn create_color_depth_frame_buffer(
             None,
         ],
         depth_stencil_attachement: SurfaceAttachment::RenderTarget(depth_buffer),
+        label: None,
     };
 
     driver.create_frame_buffer(fb_desc)
@@ -80,6 +90,7 @@ pub fn create_color_normal_depth_frame_buffer(
     let normal_buffer_desc = TextureDesc {
         sampler_desc: normal_tex_desc,
         payload: None,
+        label: None,
     };
     let normal_buffer = driver.create_texture(normal_buffer_desc).unwrap();
 
@@ -88,6 +99,7 @@ pub fn create_color_normal_depth_frame_buffer(
     let color_buffer_desc = TextureDesc {
         sampler_desc: color_tex_desc,
         payload: None,
+        label: None,
     };
     let color_buffer = driver.create_texture(color_buffer_desc).unwrap();
 
@@ -95,6 +107,7 @@ pub fn create_color_normal_depth_frame_buffer(
     let depth_buffer_desc = RenderTargetDesc {
         sampler_desc: depth_tex_desc,
         sample_count: 0,
+        label: None,
     };
     let depth_buffer = driver.create_render_target(depth_buffer_desc).unwrap();
 
@@ -106,6 +119,7 @@ pub fn create_color_normal_depth_frame_buffer(
             None,
         ],
         depth_stencil_attachement: SurfaceAttachment::RenderTarget(depth_buffer),
+        label: None,
     };
     driver.create_frame_buffer(fb_desc)
 }
@@ -193,6 +207,7 @@ impl ScreenQuad {
 
             pixel_uniforms: Vec::new(),
             pixel_surfaces: Vec::from([String::from("uTexture")]),
+            label: None,
         };
 
         driver.create_shader(shader_desc).unwrap()
@@ -221,6 +236,8 @@ impl ScreenQuad {
             depth_test: true,
             blend: BlendOp::None,
             polygon_offset: PolygonOffset::None,
+            primitive_restart: false,
+            label: None,
         };
 
         driver.create_pipeline(model_pipeline_desc).unwrap()
@@ -248,10 +265,10 @@ impl ScreenQuad {
 
         let quad_index: Vec<u32> = vec![0, 1, 2, 2, 3, 0];
 
-        let vb_desc = DeviceBufferDesc::Vertex(Usage::Static(Arc::new(quad_verts)));
+        let vb_desc = DeviceBufferDesc::vertex(Usage::Static(Arc::new(quad_verts)));
         let vb = driver.create_device_buffer(vb_desc).unwrap();
 
-        let ib_desc = DeviceBufferDesc::Index(Usage::Static(Arc::new(quad_index)));
+        let ib_desc = DeviceBufferDesc::index(Usage::Static(Arc::new(quad_index)));
         let ib = driver.create_device_buffer(ib_desc).unwrap();
 
         Self {
@@ -280,3 +297,180 @@ impl ScreenQuad {
             .draw(pipeline, &bindings, Arc::new(Vec::<Vec3f>::new()), 2, 1);
     }
 }
+
+////////////////////////////////////////////////////////////////////////////////
+// Full screen effects
+////////////////////////////////////////////////////////////////////////////////
+
+// vUV is derived from the position: pixel shaders that do not read vUV would
+// otherwise get the uv attribute optimized away
+static EFFECT_VERTEX_SHADER: &'static str = "
+#version 300 es
+precision highp float;
+in          vec2        position;
+
+out highp   vec2        vUV;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    vUV = position * 0.5 + 0.5;
+}";
+
+///
+/// uniform block of the effects without uniforms
+///
+#[derive(Clone, Copy)]
+pub struct NoUniforms;
+
+impl UniformBlockTrait for NoUniforms {
+    fn get_uniform_descriptors() -> Vec<UniformDataDesc> {
+        Vec::new()
+    }
+
+    fn get_uniform_names() -> Vec<String> {
+        Vec::new()
+    }
+}
+
+///
+/// a full screen effect: the pixel shader receives the quad coordinates in
+/// `in highp vec2 vUV;` & samples the inputs by name
+///
+pub struct FullscreenEffectDesc {
+    pub pixel_shader: String,
+    pub inputs: Vec<String>,
+    pub blend: BlendOp,
+    pub label: Option<String>,
+}
+
+impl FullscreenEffectDesc {
+    pub fn new(pixel_shader: &str) -> Self {
+        Self {
+            pixel_shader: String::from(pixel_shader),
+            inputs: Vec::new(),
+            blend: BlendOp::None,
+            label: None,
+        }
+    }
+
+    ///
+    /// a sampler of the pixel shader, bound in order by `FullscreenEffect::render`
+    ///
+    pub fn with_input(mut self, name: &str) -> Self {
+        self.inputs.push(String::from(name));
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendOp) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(String::from(label));
+        self
+    }
+}
+
+///
+/// user pixel shader over the screen quad, `U` is its (pixel shader) uniform block
+///
+pub struct FullscreenEffect<U: UniformBlockTrait + Copy + Send + Sync + 'static> {
+    vb: DeviceBufferPtr,
+    ib: DeviceBufferPtr,
+    pipeline: PipelinePtr,
+    input_count: usize,
+    _uniforms: std::marker::PhantomData<U>,
+}
+
+impl<U: UniformBlockTrait + Copy + Send + Sync + 'static> FullscreenEffect<U> {
+    ///
+    /// returns None if the pixel shader fails to compile or link (the log is printed)
+    ///
+    pub fn new(
+        driver: &mut DriverPtr,
+        quad: &ScreenQuad,
+        desc: FullscreenEffectDesc,
+    ) -> Option<Self> {
+        let shader_desc = ShaderDesc {
+            vertex_shader: String::from(EFFECT_VERTEX_SHADER),
+            pixel_shader: desc.pixel_shader,
+
+            vertex_attributes: vec![vec![String::from("position")]],
+            vertex_uniforms: Vec::new(),
+            vertex_surfaces: Vec::new(),
+
+            pixel_uniforms: U::get_uniform_names(),
+            pixel_surfaces: desc.inputs.clone(),
+            label: desc.label.clone(),
+        };
+        let shader = driver.create_shader(shader_desc)?;
+
+        let vertex_layout = VertexBufferLayout {
+            buffer_id: 0,
+            vertex_attributes: QuadVertex::get_attribute_descriptors()
+                .into_iter()
+                .filter(|a| a.name() == "position")
+                .collect(),
+            stride: QuadVertex::stride(),
+            divisor: 0,
+        };
+
+        let pipeline_desc = PipelineDesc {
+            primitive_type: PrimitiveType::Triangles,
+            shader,
+            buffer_layouts: vec![vertex_layout],
+            uniform_descs: U::get_uniform_descriptors(),
+            index_type: IndexType::UInt32,
+            face_winding: FaceWinding::CCW,
+            cull_mode: CullMode::None,
+            depth_write: false,
+            depth_test: false,
+            blend: desc.blend,
+            polygon_offset: PolygonOffset::None,
+            primitive_restart: false,
+            label: desc.label,
+        };
+
+        Some(Self {
+            vb: quad.vb.clone(),
+            ib: quad.ib.clone(),
+            pipeline: driver.create_pipeline(pipeline_desc)?,
+            input_count: desc.inputs.len(),
+            _uniforms: std::marker::PhantomData,
+        })
+    }
+
+    pub fn pipeline(&self) -> &PipelinePtr {
+        &self.pipeline
+    }
+
+    ///
+    /// draw the effect over the whole pass, `inputs` are bound in the order of
+    /// the descriptor inputs
+    ///
+    pub fn render(&self, pass: &mut Pass, uniforms: U, inputs: &[TexturePtr]) {
+        assert_eq!(
+            inputs.len(),
+            self.input_count,
+            "the effect expects {} inputs",
+            self.input_count
+        );
+
+        let bindings = Bindings {
+            vertex_buffers: vec![self.vb.clone()],
+            index_buffer: Some(self.ib.clone()),
+
+            vertex_images: Vec::new(),
+            pixel_images: inputs.to_vec(),
+        };
+
+        pass.queue.draw(
+            &self.pipeline,
+            &bindings,
+            Arc::new(GenPayload::from(uniforms)),
+            2,
+            1,
+        );
+    }
+}
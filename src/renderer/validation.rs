@@ -0,0 +1,1141 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+use super::*;
+use crate::rs_math3d::*;
+use std::fmt;
+use std::sync::*;
+
+////////////////////////////////////////////////////////////////////////////////
+// Validation errors
+////////////////////////////////////////////////////////////////////////////////
+#[derive(Clone, Debug)]
+pub enum ValidationErrorKind {
+    PassExceedsFrameBuffer {
+        pass: (usize, usize),
+        frame_buffer: (usize, usize),
+    },
+    ClearValueMismatch {
+        attachment: usize,
+    },
+    UniformCountMismatch {
+        pipeline: usize,
+        shader: usize,
+    },
+    UniformPayloadTooSmall {
+        uniform: String,
+        required: usize,
+        size: usize,
+    },
+    VertexLayoutMismatch {
+        layouts: usize,
+        shader: usize,
+    },
+    VertexAttributeCountMismatch {
+        buffer_id: usize,
+        layout: usize,
+        shader: usize,
+    },
+    VertexAttributeOutOfStride {
+        attribute: String,
+        end: usize,
+        stride: usize,
+    },
+    MissingVertexBuffer {
+        buffer_id: usize,
+        bound: usize,
+    },
+    NotAVertexBuffer {
+        buffer_id: usize,
+    },
+    VertexBufferOverflow {
+        buffer_id: usize,
+        required: usize,
+        size: usize,
+    },
+    ImageCountMismatch {
+        stage: &'static str,
+        shader: usize,
+        bound: usize,
+    },
+    MissingIndexBuffer,
+    UnexpectedIndexBuffer,
+    RestartWithoutIndices,
+    NotAnIndexBuffer,
+    IndexBufferMisaligned {
+        size: usize,
+        index_size: usize,
+    },
+    IndexBufferOverflow {
+        required: usize,
+        size: usize,
+    },
+    IndexOutOfRange {
+        index: usize,
+        vertex_count: usize,
+    },
+    StaticBufferUpdate,
+    BufferUpdateOverflow {
+        end: usize,
+        size: usize,
+    },
+    TexturePayloadMismatch {
+        expected: usize,
+        size: usize,
+    },
+    MissingBlitAttachment {
+        attachment: usize,
+    },
+    BlitDepthFormatMismatch,
+    MultisampleBlitScaled,
+    CopyOutOfBounds {
+        texture: &'static str,
+    },
+    CopyFormatMismatch,
+    QueryAlreadyActive {
+        query_type: QueryType,
+    },
+    QueryNotActive,
+    QueryNotEnded,
+}
+
+#[derive(Clone, Debug)]
+pub struct ValidationError {
+    /// label of the pass
+    pub pass: Option<String>,
+    /// index of the faulty command in the pass queue, None for the pass itself
+    pub command: Option<usize>,
+    /// label of the pipeline of a draw command
+    pub pipeline: Option<String>,
+    pub kind: ValidationErrorKind,
+}
+
+impl fmt::Display for ValidationErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ValidationErrorKind::*;
+        match self {
+            PassExceedsFrameBuffer { pass, frame_buffer } => write!(
+                f,
+                "pass size {}x{} exceeds frame buffer size {}x{}",
+                pass.0, pass.1, frame_buffer.0, frame_buffer.1
+            ),
+            ClearValueMismatch { attachment } => write!(
+                f,
+                "clear value type does not match the type of color attachment {}",
+                attachment
+            ),
+            UniformCountMismatch { pipeline, shader } => write!(
+                f,
+                "pipeline describes {} uniforms but the shader declares {}",
+                pipeline, shader
+            ),
+            UniformPayloadTooSmall {
+                uniform,
+                required,
+                size,
+            } => write!(
+                f,
+                "uniform \"{}\" reads up to byte {} of a {} bytes uniform payload",
+                uniform, required, size
+            ),
+            VertexLayoutMismatch { layouts, shader } => write!(
+                f,
+                "pipeline has {} buffer layouts but the shader declares {}",
+                layouts, shader
+            ),
+            VertexAttributeCountMismatch {
+                buffer_id,
+                layout,
+                shader,
+            } => write!(
+                f,
+                "buffer layout {} has {} attributes but the shader declares {}",
+                buffer_id, layout, shader
+            ),
+            VertexAttributeOutOfStride {
+                attribute,
+                end,
+                stride,
+            } => write!(
+                f,
+                "vertex attribute \"{}\" ends at byte {} past the stride of {}",
+                attribute, end, stride
+            ),
+            MissingVertexBuffer { buffer_id, bound } => write!(
+                f,
+                "layout uses vertex buffer {} but only {} are bound",
+                buffer_id, bound
+            ),
+            NotAVertexBuffer { buffer_id } => {
+                write!(
+                    f,
+                    "buffer bound at slot {} is not a vertex buffer",
+                    buffer_id
+                )
+            }
+            VertexBufferOverflow {
+                buffer_id,
+                required,
+                size,
+            } => write!(
+                f,
+                "draw reads {} bytes from vertex buffer {} of {} bytes",
+                required, buffer_id, size
+            ),
+            ImageCountMismatch {
+                stage,
+                shader,
+                bound,
+            } => write!(
+                f,
+                "shader declares {} {} surfaces but {} are bound",
+                shader, stage, bound
+            ),
+            MissingIndexBuffer => write!(f, "pipeline is indexed but no index buffer is bound"),
+            UnexpectedIndexBuffer => {
+                write!(f, "index buffer bound to a pipeline with IndexType::None")
+            }
+            NotAnIndexBuffer => write!(f, "bound index buffer is not an index buffer"),
+            RestartWithoutIndices => {
+                write!(f, "primitive restart enabled on a pipeline with IndexType::None")
+            }
+            IndexBufferMisaligned { size, index_size } => write!(
+                f,
+                "index buffer of {} bytes is not a multiple of the pipeline index size ({})",
+                size, index_size
+            ),
+            IndexBufferOverflow { required, size } => write!(
+                f,
+                "draw reads {} bytes from an index buffer of {} bytes",
+                required, size
+            ),
+            IndexOutOfRange {
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "index {} is out of range of the {} bound vertices",
+                index, vertex_count
+            ),
+            StaticBufferUpdate => write!(f, "update of a static device buffer"),
+            BufferUpdateOverflow { end, size } => write!(
+                f,
+                "update ends at byte {} past the device buffer size of {}",
+                end, size
+            ),
+            TexturePayloadMismatch { expected, size } => {
+                write!(f, "texture update of {} bytes, expected {}", size, expected)
+            }
+            MissingBlitAttachment { attachment } => write!(
+                f,
+                "blit source has no color attachment {}",
+                attachment
+            ),
+            BlitDepthFormatMismatch => write!(
+                f,
+                "depth blit between depth/stencil attachments of different formats"
+            ),
+            MultisampleBlitScaled => write!(
+                f,
+                "blit out of a multisampled frame buffer with different source & destination regions"
+            ),
+            CopyOutOfBounds { texture } => {
+                write!(f, "texture copy region is out of the {} texture", texture)
+            }
+            QueryAlreadyActive { query_type } => {
+                write!(f, "a {:?} query is already active", query_type)
+            }
+            QueryNotActive => write!(f, "end of a query that was not begun"),
+            QueryNotEnded => write!(f, "query begun but not ended in the pass"),
+            CopyFormatMismatch => write!(
+                f,
+                "texture copy between textures of different kinds or formats"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pass \"{}\"", self.pass.as_deref().unwrap_or(""))?;
+        if let Some(command) = self.command {
+            write!(f, ", command #{}", command)?;
+        }
+        if let Some(pipeline) = &self.pipeline {
+            write!(f, ", pipeline \"{}\"", pipeline)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+pub type ValidationCallback = Box<dyn FnMut(&ValidationError) + Send>;
+
+////////////////////////////////////////////////////////////////////////////////
+// Validating driver
+////////////////////////////////////////////////////////////////////////////////
+
+///
+/// wraps a driver and checks every pass against the resource descriptors
+/// before forwarding it. By default, it panics listing the errors. With a report
+/// callback, the errors are forwarded to it and the faulty pass is dropped
+///
+pub struct ValidatingDriver {
+    driver: DriverPtr,
+    report: Option<ValidationCallback>,
+}
+
+fn vertex_buffer_size(buff: &DeviceBufferPtr) -> Option<usize> {
+    match buff.desc().buffer_type {
+        DeviceBufferType::Vertex => Some(buff.desc().size()),
+        _ => None,
+    }
+}
+
+fn index_size(index_type: &IndexType) -> usize {
+    match index_type {
+        IndexType::None => 0,
+        IndexType::UInt16 => 2,
+        IndexType::UInt32 => 4,
+    }
+}
+
+fn is_multisampled(attachment: &SurfaceAttachment) -> bool {
+    match attachment {
+        SurfaceAttachment::RenderTarget(rt) => rt.desc().sample_count > 1,
+        SurfaceAttachment::Texture(_) => false,
+    }
+}
+
+fn same_rect(a: &Recti, b: &Recti) -> bool {
+    a.x == b.x && a.y == b.y && a.width == b.width && a.height == b.height
+}
+
+fn rect_inside(x: i32, y: i32, width: i32, height: i32, sampler_desc: &SamplerDesc) -> bool {
+    x >= 0
+        && y >= 0
+        && width >= 0
+        && height >= 0
+        && (x + width) as usize <= sampler_desc.width()
+        && (y + height) as usize <= sampler_desc.height()
+}
+
+///
+/// largest index read by the draw, only known for static index buffers
+///
+fn max_static_index(
+    ib: &DeviceBufferPtr,
+    index_type: &IndexType,
+    count: usize,
+    primitive_restart: bool,
+) -> Option<usize> {
+    let payload = match ib.desc() {
+        DeviceBufferDesc {
+            buffer_type: DeviceBufferType::Index,
+            usage: Usage::Static(payload),
+            ..
+        } => payload,
+        _ => return None,
+    };
+
+    if count == 0 || count * index_size(index_type) > payload.size() {
+        return None;
+    }
+
+    unsafe {
+        match index_type {
+            IndexType::None => None,
+            IndexType::UInt16 => core::slice::from_raw_parts(payload.ptr() as *const u16, count)
+                .iter()
+                .filter(|i| !primitive_restart || **i != u16::MAX)
+                .max()
+                .map(|i| *i as usize),
+            IndexType::UInt32 => core::slice::from_raw_parts(payload.ptr() as *const u32, count)
+                .iter()
+                .filter(|i| !primitive_restart || **i != u32::MAX)
+                .max()
+                .map(|i| *i as usize),
+        }
+    }
+}
+
+impl ValidatingDriver {
+    pub fn new(driver: DriverPtr) -> Self {
+        Self {
+            driver,
+            report: None,
+        }
+    }
+
+    pub fn with_report(mut self, report: ValidationCallback) -> Self {
+        self.report = Some(report);
+        self
+    }
+
+    pub fn into_driver_ptr(self) -> DriverPtr {
+        DriverPtr::from(Arc::new(Mutex::new(self)))
+    }
+
+    fn validate_blit(blit: &BlitCommand, kinds: &mut Vec<ValidationErrorKind>) {
+        let src = match &blit.src {
+            Some(src) => src.desc(),
+            // the default frame buffer is not described
+            None => return,
+        };
+
+        if blit.color {
+            match src.color_attachements.get(blit.src_attachment) {
+                Some(Some(a))
+                    if is_multisampled(a) && !same_rect(&blit.src_rect, &blit.dst_rect) =>
+                {
+                    kinds.push(ValidationErrorKind::MultisampleBlitScaled)
+                }
+                Some(Some(_)) => (),
+                _ => kinds.push(ValidationErrorKind::MissingBlitAttachment {
+                    attachment: blit.src_attachment,
+                }),
+            }
+        }
+
+        if blit.depth {
+            let src_depth = &src.depth_stencil_attachement;
+            if is_multisampled(src_depth) && !same_rect(&blit.src_rect, &blit.dst_rect) {
+                kinds.push(ValidationErrorKind::MultisampleBlitScaled);
+            }
+            match &blit.dst {
+                Some(dst) => {
+                    let dst_format = dst.desc().depth_stencil_attachement.pixel_format();
+                    if std::mem::discriminant(&src_depth.pixel_format())
+                        != std::mem::discriminant(&dst_format)
+                    {
+                        kinds.push(ValidationErrorKind::BlitDepthFormatMismatch);
+                    }
+                }
+                None => (),
+            }
+        }
+    }
+
+    fn validate_copy(copy: &CopyTextureCommand, kinds: &mut Vec<ValidationErrorKind>) {
+        let src = &copy.src.desc().sampler_desc;
+        let dst = &copy.dst.desc().sampler_desc;
+        let r = &copy.src_rect;
+
+        if !rect_inside(r.x, r.y, r.width, r.height, src) {
+            kinds.push(ValidationErrorKind::CopyOutOfBounds { texture: "source" });
+        }
+        if !rect_inside(copy.dst_x, copy.dst_y, r.width, r.height, dst) {
+            kinds.push(ValidationErrorKind::CopyOutOfBounds {
+                texture: "destination",
+            });
+        }
+
+        let (src_format, dst_format) = (&src.pixel_format, &dst.pixel_format);
+        let matching = if src_format.is_depth() || dst_format.is_depth() {
+            std::mem::discriminant(src_format) == std::mem::discriminant(dst_format)
+        } else {
+            src_format.to_orig_surface_type() == dst_format.to_orig_surface_type()
+        };
+        if !matching {
+            kinds.push(ValidationErrorKind::CopyFormatMismatch);
+        }
+    }
+
+    fn validate_draw(cmd: &DrawCommand, errors: &mut Vec<ValidationErrorKind>) {
+        use ValidationErrorKind::*;
+
+        let pipe = cmd.pipe.desc();
+        let shader = pipe.shader.desc();
+        let bindings = &cmd.bindings;
+
+        // uniforms
+        let shader_uniforms = shader.vertex_uniforms.len() + shader.pixel_uniforms.len();
+        if pipe.uniform_descs.len() > shader_uniforms {
+            errors.push(UniformCountMismatch {
+                pipeline: pipe.uniform_descs.len(),
+                shader: shader_uniforms,
+            });
+        }
+
+        for u in &pipe.uniform_descs {
+            let required = u.offset() + u.desc().format().size();
+            if required > cmd.uniforms.size() {
+                errors.push(UniformPayloadTooSmall {
+                    uniform: String::from(u.desc().name()),
+                    required,
+                    size: cmd.uniforms.size(),
+                });
+            }
+        }
+
+        // surfaces
+        if bindings.vertex_images.len() != shader.vertex_surfaces.len() {
+            errors.push(ImageCountMismatch {
+                stage: "vertex",
+                shader: shader.vertex_surfaces.len(),
+                bound: bindings.vertex_images.len(),
+            });
+        }
+
+        if bindings.pixel_images.len() != shader.pixel_surfaces.len() {
+            errors.push(ImageCountMismatch {
+                stage: "pixel",
+                shader: shader.pixel_surfaces.len(),
+                bound: bindings.pixel_images.len(),
+            });
+        }
+
+        // indices
+        if pipe.primitive_restart && pipe.index_type == IndexType::None {
+            errors.push(RestartWithoutIndices);
+        }
+        let element_count = pipe.element_count(cmd.prim_count) as usize;
+        // the vertex range of indexed draws is checked against the indices
+        let vertex_count = match bindings.index_buffer {
+            None => Some(element_count),
+            Some(_) => None,
+        };
+        match (&bindings.index_buffer, &pipe.index_type) {
+            (None, IndexType::None) => (),
+            (None, _) => errors.push(MissingIndexBuffer),
+            (Some(_), IndexType::None) => errors.push(UnexpectedIndexBuffer),
+            (Some(ib), index_type) => {
+                let isize = index_size(index_type);
+                match ib.desc() {
+                    DeviceBufferDesc {
+                        buffer_type: DeviceBufferType::Index,
+                        usage: u,
+                        ..
+                    } => {
+                        if u.size() % isize != 0 {
+                            errors.push(IndexBufferMisaligned {
+                                size: u.size(),
+                                index_size: isize,
+                            });
+                        }
+                        if element_count * isize > u.size() {
+                            errors.push(IndexBufferOverflow {
+                                required: element_count * isize,
+                                size: u.size(),
+                            });
+                        }
+                    }
+                    _ => errors.push(NotAnIndexBuffer),
+                }
+            }
+        }
+
+        // vertices
+        if pipe.buffer_layouts.len() > shader.vertex_attributes.len() {
+            errors.push(VertexLayoutMismatch {
+                layouts: pipe.buffer_layouts.len(),
+                shader: shader.vertex_attributes.len(),
+            });
+        }
+
+        for (l, layout) in pipe.buffer_layouts.iter().enumerate() {
+            match shader.vertex_attributes.get(l) {
+                Some(names) if names.len() < layout.vertex_attributes.len() => {
+                    errors.push(VertexAttributeCountMismatch {
+                        buffer_id: layout.buffer_id,
+                        layout: layout.vertex_attributes.len(),
+                        shader: names.len(),
+                    })
+                }
+                _ => (),
+            }
+
+            let mut attrib_end = 0;
+            for a in &layout.vertex_attributes {
+                let end = a.offset() + a.format().size();
+                if end > layout.stride {
+                    errors.push(VertexAttributeOutOfStride {
+                        attribute: a.name().clone(),
+                        end,
+                        stride: layout.stride,
+                    });
+                }
+                attrib_end = usize::max(attrib_end, end);
+            }
+
+            let size = match bindings.vertex_buffers.get(layout.buffer_id) {
+                None => {
+                    errors.push(MissingVertexBuffer {
+                        buffer_id: layout.buffer_id,
+                        bound: bindings.vertex_buffers.len(),
+                    });
+                    continue;
+                }
+                Some(vb) => match vertex_buffer_size(vb) {
+                    Some(size) => size,
+                    None => {
+                        errors.push(NotAVertexBuffer {
+                            buffer_id: layout.buffer_id,
+                        });
+                        continue;
+                    }
+                },
+            };
+
+            let count = match layout.divisor {
+                0 => vertex_count,
+                d => Some((cmd.instance_count as usize).div_ceil(d)),
+            };
+
+            match count {
+                Some(count) if count > 0 => {
+                    let required = (count - 1) * layout.stride + attrib_end;
+                    if required > size {
+                        errors.push(VertexBufferOverflow {
+                            buffer_id: layout.buffer_id,
+                            required,
+                            size,
+                        });
+                    }
+                }
+                _ => (),
+            }
+
+            // the bound vertices limit the indices of static index buffers
+            match (&bindings.index_buffer, layout.divisor) {
+                (Some(ib), 0) if layout.stride > 0 && size >= attrib_end => {
+                    let available = (size - attrib_end) / layout.stride + 1;
+                    match max_static_index(
+                        ib,
+                        &pipe.index_type,
+                        element_count,
+                        pipe.primitive_restart,
+                    ) {
+                        Some(max) if max >= available => errors.push(IndexOutOfRange {
+                            index: max,
+                            vertex_count: available,
+                        }),
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    pub fn validate(pass: &Pass) -> Vec<ValidationError> {
+        use ValidationErrorKind::*;
+
+        let mut errors = Vec::new();
+        let error = |command, pipeline, kind| ValidationError {
+            pass: pass.label.clone(),
+            command,
+            pipeline,
+            kind,
+        };
+
+        for (idx, action) in pass.color_actions.iter().enumerate() {
+            let value = match action {
+                ColorPassAction::ClearValue(value) => value,
+                _ => continue,
+            };
+
+            let surface_type = match &pass.frame_buffer {
+                Some(fb) => match &fb.desc().color_attachements[idx] {
+                    Some(a) => a.pixel_format().to_orig_surface_type(),
+                    None => continue,
+                },
+                // the default frame buffer is fixed point
+                None => OrigSurfaceType::Float,
+            };
+
+            // there are no signed integer surfaces, Int4 never matches
+            let matching = match (value, surface_type) {
+                (ClearValue::Float4(_), OrigSurfaceType::Float) => true,
+                (ClearValue::UInt4(_), OrigSurfaceType::UInt) => true,
+                _ => false,
+            };
+            if !matching {
+                errors.push(error(None, None, ClearValueMismatch { attachment: idx }));
+            }
+        }
+
+        match &pass.frame_buffer {
+            Some(fb) => {
+                let desc = fb.desc();
+                let attachments = desc
+                    .color_attachements
+                    .iter()
+                    .filter_map(|a| a.as_ref())
+                    .chain(std::iter::once(&desc.depth_stencil_attachement));
+                for a in attachments {
+                    let sampler_desc = match a {
+                        SurfaceAttachment::Texture(t) => &t.desc().sampler_desc,
+                        SurfaceAttachment::RenderTarget(rt) => &rt.desc().sampler_desc,
+                    };
+                    let (w, h) = (sampler_desc.width(), sampler_desc.height());
+                    if pass.width > w || pass.height > h {
+                        errors.push(error(
+                            None,
+                            None,
+                            PassExceedsFrameBuffer {
+                                pass: (pass.width, pass.height),
+                                frame_buffer: (w, h),
+                            },
+                        ));
+                        break;
+                    }
+                }
+            }
+            None => (),
+        }
+
+        let mut active_queries: Vec<QueryPtr> = Vec::new();
+        for (i, cmd) in pass.queue.commands.iter().enumerate() {
+            match cmd {
+                RenderPassCommand::Viewport(..) | RenderPassCommand::Scissor(..) => (),
+                RenderPassCommand::Draw(draw) => {
+                    let mut kinds = Vec::new();
+                    Self::validate_draw(draw, &mut kinds);
+                    for kind in kinds {
+                        errors.push(error(Some(i), draw.pipe.desc().label.clone(), kind));
+                    }
+                }
+                RenderPassCommand::UpdateDeviceBuffer(update) => match update.buffer.desc() {
+                    DeviceBufferDesc {
+                        usage: Usage::Static(_),
+                        ..
+                    } => errors.push(error(Some(i), None, StaticBufferUpdate)),
+                    desc => {
+                        let end = update.offset + update.payload.size();
+                        if end > desc.size() {
+                            errors.push(error(
+                                Some(i),
+                                None,
+                                BufferUpdateOverflow {
+                                    end,
+                                    size: desc.size(),
+                                },
+                            ));
+                        }
+                    }
+                },
+                RenderPassCommand::UpdateTexture(update) => {
+                    let sampler_desc = &update.tex.desc().sampler_desc;
+                    let expected = sampler_desc.width()
+                        * sampler_desc.height()
+                        * sampler_desc.pixel_format.pixel_size();
+                    if update.payload.size() != expected {
+                        errors.push(error(
+                            Some(i),
+                            None,
+                            TexturePayloadMismatch {
+                                expected,
+                                size: update.payload.size(),
+                            },
+                        ));
+                    }
+                }
+                RenderPassCommand::Blit(blit) => {
+                    let mut kinds = Vec::new();
+                    Self::validate_blit(blit, &mut kinds);
+                    for kind in kinds {
+                        errors.push(error(Some(i), None, kind));
+                    }
+                }
+                RenderPassCommand::BeginQuery(query) => {
+                    let query_type = query.desc().query_type;
+                    if active_queries
+                        .iter()
+                        .any(|q| q.desc().query_type == query_type)
+                    {
+                        errors.push(error(Some(i), None, QueryAlreadyActive { query_type }));
+                    } else {
+                        active_queries.push(query.clone());
+                    }
+                }
+                RenderPassCommand::EndQuery(query) => {
+                    match active_queries.iter().position(|q| Arc::ptr_eq(q, query)) {
+                        Some(idx) => {
+                            active_queries.remove(idx);
+                        }
+                        None => errors.push(error(Some(i), None, QueryNotActive)),
+                    }
+                }
+                RenderPassCommand::CopyTexture(copy) => {
+                    let mut kinds = Vec::new();
+                    Self::validate_copy(copy, &mut kinds);
+                    for kind in kinds {
+                        errors.push(error(Some(i), None, kind));
+                    }
+                }
+            }
+        }
+
+        if !active_queries.is_empty() {
+            errors.push(error(None, None, QueryNotEnded));
+        }
+
+        errors
+    }
+}
+
+impl Driver for ValidatingDriver {
+    fn get_caps(&self) -> DriverCaps {
+        self.driver.get_caps()
+    }
+
+    fn create_device_buffer(&mut self, desc: DeviceBufferDesc) -> Option<DeviceBufferPtr> {
+        self.driver.create_device_buffer(desc)
+    }
+
+    fn create_texture(&mut self, desc: TextureDesc) -> Option<TexturePtr> {
+        self.driver.create_texture(desc)
+    }
+
+    fn create_render_target(&mut self, desc: RenderTargetDesc) -> Option<RenderTargetPtr> {
+        self.driver.create_render_target(desc)
+    }
+
+    fn create_shader(&mut self, desc: ShaderDesc) -> Option<ShaderPtr> {
+        self.driver.create_shader(desc)
+    }
+
+    fn create_pipeline(&mut self, desc: PipelineDesc) -> Option<PipelinePtr> {
+        self.driver.create_pipeline(desc)
+    }
+
+    fn create_frame_buffer(&mut self, desc: FrameBufferDesc) -> Option<FrameBufferPtr> {
+        self.driver.create_frame_buffer(desc)
+    }
+
+    fn create_query(&mut self, desc: QueryDesc) -> Option<QueryPtr> {
+        self.driver.create_query(desc)
+    }
+
+    fn delete_resource(&mut self, resource_type: &ResourceType, res_id: usize) {
+        self.driver.delete_resource(resource_type, res_id)
+    }
+
+    fn set_label(&mut self, resource_type: &ResourceType, res_id: usize, label: &str) {
+        self.driver.set_label(resource_type, res_id, label)
+    }
+
+    fn render_pass(&mut self, pass: &mut Pass) {
+        let errors = Self::validate(pass);
+        if errors.is_empty() {
+            return self.driver.render_pass(pass);
+        }
+
+        match &mut self.report {
+            Some(report) => {
+                for e in &errors {
+                    report(e);
+                }
+                pass.queue.drain();
+            }
+            None => {
+                let list: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                panic!("{} validation error(s):\n{}", errors.len(), list.join("\n"));
+            }
+        }
+    }
+
+    fn read_back(
+        &mut self,
+        surface: &TexturePtr,
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+    ) -> Option<ReadbackPayload> {
+        self.driver.read_back(surface, x, y, w, h)
+    }
+
+    fn query_result(&mut self, query: &QueryPtr) -> Option<bool> {
+        self.driver.query_result(query)
+    }
+
+    fn stats(&self) -> DriverStats {
+        self.driver.stats()
+    }
+
+    fn end_frame(&mut self) {
+        self.driver.end_frame()
+    }
+
+    fn live_resources(&self) -> Vec<LiveResource> {
+        self.driver.live_resources()
+    }
+
+    fn set_leak_report(&mut self, enabled: bool) {
+        self.driver.set_leak_report(enabled)
+    }
+
+    fn set_debug_callback(&mut self, callback: Option<DebugCallback>) {
+        self.driver.set_debug_callback(callback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::null_driver::NullDriver;
+    use super::*;
+
+    crate::render_data! {
+        vertex TestVertex {
+            position    : Vec3f,
+        }
+
+        uniforms TestUniforms {
+            pvm         : Mat4f,
+        }
+    }
+
+    struct Fixture {
+        driver: DriverPtr,
+        texture: TexturePtr,
+        vertices: DeviceBufferPtr,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let mut driver = NullDriver::new(Vec::new());
+            let texture = driver
+                .create_texture(TextureDesc {
+                    sampler_desc: SamplerDesc::default(4, 4),
+                    payload: None,
+                    label: None,
+                })
+                .unwrap();
+            let origin = TestVertex {
+                position: Vec3f::new(0.0, 0.0, 0.0),
+            };
+            let triangle = vec![origin; 3];
+            let vertices = driver
+                .create_device_buffer(DeviceBufferDesc::vertex(Usage::Static(Arc::new(triangle))))
+                .unwrap();
+            Self {
+                driver,
+                texture,
+                vertices,
+            }
+        }
+
+        fn pipeline(&mut self, index_type: IndexType) -> PipelinePtr {
+            let shader = self
+                .driver
+                .create_shader(ShaderDesc {
+                    vertex_shader: String::new(),
+                    pixel_shader: String::new(),
+
+                    vertex_attributes: vec![TestVertex::get_attribute_names()],
+                    vertex_uniforms: TestUniforms::get_uniform_names(),
+                    vertex_surfaces: Vec::new(),
+
+                    pixel_uniforms: Vec::new(),
+                    pixel_surfaces: vec![String::from("uTexture")],
+                    label: None,
+                })
+                .unwrap();
+            self.driver
+                .create_pipeline(PipelineDesc {
+                    primitive_type: PrimitiveType::Triangles,
+                    shader,
+                    buffer_layouts: vec![VertexBufferLayout {
+                        buffer_id: 0,
+                        vertex_attributes: TestVertex::get_attribute_descriptors(),
+                        stride: TestVertex::stride(),
+                        divisor: 0,
+                    }],
+                    uniform_descs: TestUniforms::get_uniform_descriptors(),
+                    index_type,
+                    face_winding: FaceWinding::CCW,
+                    cull_mode: CullMode::None,
+                    depth_write: true,
+                    depth_test: true,
+                    blend: BlendOp::None,
+                    polygon_offset: PolygonOffset::None,
+                    primitive_restart: false,
+                    label: Some(String::from("test")),
+                })
+                .unwrap()
+        }
+
+        fn bindings(&self) -> Bindings {
+            Bindings {
+                vertex_buffers: vec![self.vertices.clone()],
+                index_buffer: None,
+
+                vertex_images: Vec::new(),
+                pixel_images: vec![self.texture.clone()],
+            }
+        }
+    }
+
+    fn uniforms() -> Arc<dyn Payload> {
+        Arc::new(GenPayload::from(TestUniforms {
+            pvm: Mat4f::identity(),
+        }))
+    }
+
+    fn validate_draw(
+        pipeline: &PipelinePtr,
+        bindings: &Bindings,
+        uniforms: Arc<dyn Payload>,
+        prim_count: u32,
+    ) -> Vec<ValidationErrorKind> {
+        let mut pass = Pass::new(
+            4,
+            4,
+            None,
+            [ColorPassAction::Previous; 4],
+            DepthPassAction::Previous,
+        );
+        pass.queue.draw(pipeline, bindings, uniforms, prim_count, 1);
+        ValidatingDriver::validate(&pass)
+            .into_iter()
+            .map(|e| {
+                assert_eq!(e.command, Some(0));
+                assert_eq!(e.pipeline.as_deref(), Some("test"));
+                e.kind
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clean_pass() {
+        let mut fixture = Fixture::new();
+        let pipeline = fixture.pipeline(IndexType::None);
+        let errors = validate_draw(&pipeline, &fixture.bindings(), uniforms(), 1);
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn short_uniform_payload() {
+        let mut fixture = Fixture::new();
+        let pipeline = fixture.pipeline(IndexType::None);
+        let short = Arc::new(GenPayload::from(0u32));
+        let errors = validate_draw(&pipeline, &fixture.bindings(), short, 1);
+        match errors.as_slice() {
+            [ValidationErrorKind::UniformPayloadTooSmall {
+                uniform,
+                required: 64,
+                size: 4,
+            }] => assert_eq!(uniform, "pvm"),
+            _ => panic!("unexpected {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn missing_pixel_images() {
+        let mut fixture = Fixture::new();
+        let pipeline = fixture.pipeline(IndexType::None);
+        let mut bindings = fixture.bindings();
+        bindings.pixel_images.clear();
+        let errors = validate_draw(&pipeline, &bindings, uniforms(), 1);
+        assert!(
+            matches!(
+                errors.as_slice(),
+                [ValidationErrorKind::ImageCountMismatch {
+                    stage: "pixel",
+                    shader: 1,
+                    bound: 0,
+                }]
+            ),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn mismatched_index_type() {
+        let mut fixture = Fixture::new();
+        let pipeline = fixture.pipeline(IndexType::UInt32);
+        let indices: Vec<u16> = vec![0, 1, 2];
+        let mut bindings = fixture.bindings();
+        bindings.index_buffer = fixture
+            .driver
+            .create_device_buffer(DeviceBufferDesc::index(Usage::Static(Arc::new(indices))));
+        let errors = validate_draw(&pipeline, &bindings, uniforms(), 1);
+        assert!(
+            matches!(
+                errors.as_slice(),
+                [
+                    ValidationErrorKind::IndexBufferMisaligned {
+                        size: 6,
+                        index_size: 4,
+                    },
+                    ValidationErrorKind::IndexBufferOverflow {
+                        required: 12,
+                        size: 6,
+                    },
+                ]
+            ),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn draw_past_buffer_end() {
+        let mut fixture = Fixture::new();
+        let pipeline = fixture.pipeline(IndexType::None);
+        let errors = validate_draw(&pipeline, &fixture.bindings(), uniforms(), 2);
+        assert!(
+            matches!(
+                errors.as_slice(),
+                [ValidationErrorKind::VertexBufferOverflow {
+                    buffer_id: 0,
+                    required: 72,
+                    size: 36,
+                }]
+            ),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn index_past_vertex_end() {
+        let mut fixture = Fixture::new();
+        let pipeline = fixture.pipeline(IndexType::UInt16);
+        let indices: Vec<u16> = vec![0, 1, 3];
+        let mut bindings = fixture.bindings();
+        bindings.index_buffer = fixture
+            .driver
+            .create_device_buffer(DeviceBufferDesc::index(Usage::Static(Arc::new(indices))));
+        let errors = validate_draw(&pipeline, &bindings, uniforms(), 1);
+        assert!(
+            matches!(
+                errors.as_slice(),
+                [ValidationErrorKind::IndexOutOfRange {
+                    index: 3,
+                    vertex_count: 3,
+                }]
+            ),
+            "{:?}",
+            errors
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//
+// Copyright 2021-Present (c) Raja Lehtihet & Wael El Oraiby
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice,
+// this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice,
+// this list of conditions and the following disclaimer in the documentation
+// and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors
+// may be used to endorse or promote products derived from this software without
+// specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+//
+//
+// Infinite ground grid on the XZ plane
+//
+// A screen quad is unprojected to the near & far planes: the pixel shader
+// intersects the view ray with the plane, anti-aliases the lines with the
+// screen space derivatives & writes the plane depth, so the grid is depth
+// tested against the scene drawn before it.
+//
+use super::*;
+use crate::renderer::*;
+use crate::rs_math3d::*;
+use std::sync::*;
+
+static VERTEX_SHADER: &'static str = "
+#version 300 es
+in          vec2    position;
+
+uniform     mat4    inv_view_projection;
+
+out         vec3    v_near;
+out         vec3    v_far;
+
+vec3 unproject(float z) {
+    vec4 p = inv_view_projection * vec4(position, z, 1.0);
+    return p.xyz / p.w;
+}
+
+void main() {
+    v_near          = unproject(-1.0);
+    v_far           = unproject(1.0);
+    gl_Position     = vec4(position, 0.0, 1.0);
+}";
+
+static PIXEL_SHADER: &'static str = "
+#version 300 es
+precision highp float;
+
+in          vec3    v_near;
+in          vec3    v_far;
+
+uniform     mat4    view_projection;
+uniform     vec4    camera;
+uniform     vec4    spacing;
+uniform     vec4    minor_color;
+uniform     vec4    major_color;
+uniform     vec4    x_axis_color;
+uniform     vec4    z_axis_color;
+
+layout(location = 0) out vec4 color_buffer;
+
+// fades out the cells too small to be resolved (far away & at grazing angles)
+float resolved(vec2 p, float cell) {
+    vec2 d = fwidth(p / cell);
+    return 1.0 - smoothstep(0.1, 0.3, max(d.x, d.y));
+}
+
+// coverage of the lines every `cell`
+float lines(vec2 p, float cell, float width) {
+    vec2 coord = p / cell;
+    vec2 g = abs(fract(coord - 0.5) - 0.5) / fwidth(coord);
+    return clamp(width * 0.5 + 0.5 - min(g.x, g.y), 0.0, 1.0) * resolved(p, cell);
+}
+
+float axis(float p, float width) {
+    return clamp(width * 0.5 + 0.5 - abs(p) / fwidth(p), 0.0, 1.0);
+}
+
+void main() {
+    // behind the camera or beyond the far plane (also catches the rays parallel
+    // to the plane)
+    vec3 dir = v_far - v_near;
+    float t = -v_near.y / dir.y;
+    if (!(t > 0.0 && t < 1.0)) {
+        discard;
+    }
+
+    vec3 p = v_near + dir * t;
+    vec4 clip = view_projection * vec4(p, 1.0);
+    gl_FragDepth = clip.z / clip.w * 0.5 + 0.5;
+
+    float minor = lines(p.xz, spacing.x, spacing.w) * spacing.z;
+    float major = lines(p.xz, spacing.y, spacing.w);
+    vec4 c = vec4(minor_color.rgb, minor_color.a * minor);
+    c = vec4(mix(c.rgb, major_color.rgb, major), max(c.a, major_color.a * major));
+
+    // the axes vanish with the major lines of the next level
+    float axis_resolved = resolved(p.xz, spacing.y * spacing.y / spacing.x);
+    float x_axis = axis(p.z, spacing.w * 2.0) * axis_resolved;
+    float z_axis = axis(p.x, spacing.w * 2.0) * axis_resolved;
+    c = vec4(mix(c.rgb, x_axis_color.rgb, x_axis), max(c.a, x_axis_color.a * x_axis));
+    c = vec4(mix(c.rgb, z_axis_color.rgb, z_axis), max(c.a, z_axis_color.a * z_axis));
+
+    c.a *= 1.0 - smoothstep(camera.w * 0.5, camera.w, length(p - camera.xyz));
+    if (c.a <= 0.0) {
+        discard;
+    }
+    color_buffer = vec4(c.rgb * c.a, c.a);
+}";
+
+crate::render_data! {
+    vertex GridVertex {
+        position            : Vec2f,
+    }
+
+    uniforms GridUniforms {
+        inv_view_projection : Mat4f,
+        view_projection     : Mat4f,
+        camera              : Vec4f,
+        spacing             : Vec4f,
+        minor_color         : Vec4f,
+        major_color         : Vec4f,
+        x_axis_color        : Vec4f,
+        z_axis_color        : Vec4f,
+    }
+}
+
+fn color4f(c: &Color4b) -> Vec4f {
+    Vec4f::new(
+        c.x as f32 / 255.0,
+        c.y as f32 / 255.0,
+        c.z as f32 / 255.0,
+        c.w as f32 / 255.0,
+    )
+}
+
+pub struct GridSettings {
+    /// minor cell size at a camera distance of 10 cells
+    pub spacing: f32,
+    /// minor cells per major cell, also the spacing ratio between zoom levels
+    pub subdivisions: u32,
+    /// in pixels, the axes are twice as wide
+    pub line_width: f32,
+    /// distance where the grid vanishes, in camera distances
+    pub fade_distance: f32,
+    pub minor_color: Color4b,
+    pub major_color: Color4b,
+    pub x_axis_color: Color4b,
+    pub z_axis_color: Color4b,
+}
+
+impl GridSettings {
+    pub fn default() -> Self {
+        Self {
+            spacing: 1.0,
+            subdivisions: 10,
+            line_width: 1.0,
+            fade_distance: 20.0,
+            minor_color: color4b(0x60, 0x60, 0x60, 0x80),
+            major_color: color4b(0x90, 0x90, 0x90, 0xC0),
+            x_axis_color: color4b(0xE0, 0x40, 0x40, 0xFF),
+            z_axis_color: color4b(0x40, 0x60, 0xE0, 0xFF),
+        }
+    }
+}
+
+///
+/// draw it after the opaque geometry: it blends over it without writing depth
+///
+pub struct InfiniteGrid {
+    pipeline: PipelinePtr,
+    vb: DeviceBufferPtr,
+}
+
+impl InfiniteGrid {
+    pub fn new(driver: &mut DriverPtr) -> Self {
+        let shader = driver
+            .create_shader(ShaderDesc {
+                vertex_shader: String::from(VERTEX_SHADER),
+                pixel_shader: String::from(PIXEL_SHADER),
+
+                vertex_attributes: vec![GridVertex::get_attribute_names()],
+                vertex_uniforms: GridUniforms::get_uniform_names(),
+                vertex_surfaces: Vec::new(),
+
+                pixel_uniforms: Vec::new(),
+                pixel_surfaces: Vec::new(),
+                label: Some(String::from("grid")),
+            })
+            .unwrap();
+
+        let pipeline = driver
+            .create_pipeline(PipelineDesc {
+                primitive_type: PrimitiveType::Triangles,
+                shader,
+                buffer_layouts: vec![VertexBufferLayout {
+                    buffer_id: 0,
+                    vertex_attributes: GridVertex::get_attribute_descriptors(),
+                    stride: GridVertex::stride(),
+                    divisor: 0,
+                }],
+                uniform_descs: GridUniforms::get_uniform_descriptors(),
+                index_type: IndexType::None,
+                face_winding: FaceWinding::CCW,
+                cull_mode: CullMode::None,
+                depth_write: false,
+                depth_test: true,
+                blend: BlendOp::Add(Blend::default()),
+                polygon_offset: PolygonOffset::None,
+                primitive_restart: false,
+                label: Some(String::from("grid")),
+            })
+            .unwrap();
+
+        let verts: Vec<GridVertex> = [
+            (-1.0, -1.0),
+            (1.0, -1.0),
+            (1.0, 1.0),
+            (1.0, 1.0),
+            (-1.0, 1.0),
+            (-1.0, -1.0),
+        ]
+        .iter()
+        .map(|(x, y)| GridVertex {
+            position: Vec2f::new(*x, *y),
+        })
+        .collect();
+        let vb = driver
+            .create_device_buffer(DeviceBufferDesc::vertex(Usage::Static(Arc::new(verts))))
+            .unwrap();
+
+        Self { pipeline, vb }
+    }
+
+    ///
+    /// the spacing adapts to the camera distance: the minor lines of a level
+    /// fade out as the camera moves away, until the major lines become the minor
+    /// lines of the next level
+    ///
+    pub fn draw(&self, pass: &mut PassCommandQueue, camera: &Camera, settings: &GridSettings) {
+        let subdivisions = settings.subdivisions.max(2) as f32;
+        let distance = camera.distance().max(f32::EPSILON);
+        let level = (distance / (settings.spacing * 10.0)).log(subdivisions);
+        let minor = settings.spacing * subdivisions.powf(level.floor());
+
+        let view_projection = camera.projection_matrix() * camera.view_matrix();
+        let position = camera.position();
+        let uniforms = GridUniforms {
+            inv_view_projection: view_projection.inverse(),
+            view_projection,
+            camera: Vec4f::new(
+                position.x,
+                position.y,
+                position.z,
+                distance * settings.fade_distance,
+            ),
+            spacing: Vec4f::new(
+                minor,
+                minor * subdivisions,
+                1.0 - (level - level.floor()),
+                settings.line_width,
+            ),
+            minor_color: color4f(&settings.minor_color),
+            major_color: color4f(&settings.major_color),
+            x_axis_color: color4f(&settings.x_axis_color),
+            z_axis_color: color4f(&settings.z_axis_color),
+        };
+
+        let bindings = Bindings {
+            vertex_buffers: vec![self.vb.clone()],
+            index_buffer: None,
+
+            vertex_images: Vec::new(),
+            pixel_images: Vec::new(),
+        };
+        pass.draw(
+            &self.pipeline,
+            &bindings,
+            Arc::new(GenPayload::from(uniforms)),
+            2,
+            1,
+        );
+    }
+}
@@ -1,7 +1,9 @@
 mod camera;
+mod grid;
 pub mod utility_mesh;
 
 pub use camera::*;
+pub use grid::*;
 
 // use rs_math3d::*;
 
@@ -30,6 +30,7 @@
 use crate::renderer::*;
 use crate::rs_math3d::*;
 use crate::*;
+use std::collections::HashMap;
 use std::ops::*;
 use std::sync::*;
 
@@ -59,24 +60,317 @@ void main() {
     color_buffer    = v_color;
 }";
 
+static INSTANCED_VERTEX_SHADER: &'static str = "
+#version 300 es
+in          vec4    position;
+in lowp     vec4    color;
+in          vec4    model_0;
+in          vec4    model_1;
+in          vec4    model_2;
+in          vec4    model_3;
+in lowp     vec4    instance_color;
+
+uniform     mat4    pvm;
+
+out lowp    vec4    v_color;
+
+void main() {
+    mat4 model      = mat4(model_0, model_1, model_2, model_3);
+    gl_Position     = pvm * model * vec4(position.xyz, 1.0);
+    v_color         = color * instance_color;
+}";
+
+static LIT_VERTEX_SHADER: &'static str = "
+#version 300 es
+in          vec4    position;
+in          vec3    normal;
+in lowp     vec4    color;
+
+uniform     mat4    pvm;
+uniform     mat4    normal_matrix;
+
+out         vec3    v_normal;
+out lowp    vec4    v_color;
+
+void main() {
+    gl_Position     = pvm * vec4(position.xyz, 1.0);
+    v_normal        = mat3(normal_matrix) * normal;
+    v_color         = color;
+}";
+
+// two sided lighting in view space: the normals are flipped to face the viewer
+static LIT_PIXEL_SHADER: &'static str = "
+#version 300 es
+precision mediump float;
+
+in          vec3    v_normal;
+in lowp     vec4    v_color;
+
+uniform     vec4    ambient;
+uniform     vec4    headlight;
+uniform     vec4    light_direction;
+uniform     vec4    light_color;
+
+layout(location = 0) out lowp vec4    color_buffer;
+
+void main() {
+    vec3 n = dot(v_normal, v_normal) > 0.0 ? normalize(v_normal) : vec3(0.0, 0.0, 1.0);
+    n = n.z < 0.0 ? -n : n;
+
+    vec3 light = ambient.rgb
+        + headlight.rgb * n.z
+        + light_color.rgb * max(dot(n, -light_direction.xyz), 0.0);
+    color_buffer    = vec4(v_color.rgb * light, v_color.a);
+}";
+
+static OIT_PIXEL_SHADER: &'static str = "
+#version 300 es
+precision highp float;
+
+in lowp     vec4   v_color;
+";
+
+// segments expanded to screen space quads: round joins & caps are the capsule
+// around the segment, miter joins move the quad corners to the miter of the
+// connected neighbour segments
+static LINE_VERTEX_SHADER: &'static str = "
+#version 300 es
+precision highp float;
+in          vec2        corner;
+in          vec3        prev;
+in          vec3        start;
+in          vec3        end;
+in          vec3        next;
+in lowp     vec4        color;
+in          float       dash_offset;
+
+uniform     mat4        pvm;
+uniform     vec2        viewport;
+uniform     vec4        line_style;
+
+out lowp    vec4        v_color;
+out         vec2        v_position;
+flat out    vec2        v_start;
+flat out    vec2        v_end;
+flat out    float       v_dash_offset;
+
+const float NEAR_W = 1e-4;
+
+vec2 to_screen(vec4 c) {
+    return (c.xy / c.w * 0.5 + 0.5) * viewport;
+}
+
+void main() {
+    vec4 c0 = pvm * vec4(start, 1.0);
+    vec4 c1 = pvm * vec4(end, 1.0);
+    if (c0.w < NEAR_W && c1.w < NEAR_W) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+
+    // clip against the camera plane
+    bool clipped0 = c0.w < NEAR_W;
+    bool clipped1 = c1.w < NEAR_W;
+    if (clipped0) {
+        c0 = mix(c0, c1, (NEAR_W - c0.w) / (c1.w - c0.w));
+    }
+    if (clipped1) {
+        c1 = mix(c1, c0, (NEAR_W - c1.w) / (c0.w - c1.w));
+    }
+
+    vec2 s0 = to_screen(c0);
+    vec2 s1 = to_screen(c1);
+    vec2 d = s1 - s0;
+    float len = length(d);
+    vec2 dir = len > 1e-4 ? d / len : vec2(1.0, 0.0);
+    vec2 n = vec2(-dir.y, dir.x);
+
+    // + anti-aliasing fringe
+    float half_width = line_style.x * 0.5 + 1.0;
+
+    bool at_end = corner.x > 0.5;
+    vec4 c = at_end ? c1 : c0;
+    vec2 p = at_end ? s1 : s0;
+    vec2 offset = n * corner.y * half_width;
+
+    if (line_style.y > 0.5) {
+        vec3 own = at_end ? end : start;
+        vec3 neighbour = at_end ? next : prev;
+        vec4 cn = pvm * vec4(neighbour, 1.0);
+        bool clipped = at_end ? clipped1 : clipped0;
+        if (neighbour != own && !clipped && cn.w >= NEAR_W) {
+            vec2 sn = to_screen(cn);
+            vec2 dn = at_end ? sn - s1 : s0 - sn;
+            if (length(dn) > 1e-4 && dot(normalize(dn), dir) > -0.99) {
+                vec2 tangent = normalize(normalize(dn) + dir);
+                vec2 miter = vec2(-tangent.y, tangent.x);
+                // limit the miter length at sharp angles
+                offset = miter * corner.y * half_width / max(dot(miter, n), 0.25);
+            }
+        }
+    } else {
+        offset += dir * (at_end ? half_width : -half_width);
+    }
+
+    v_color         = color;
+    v_position      = p + offset;
+    v_start         = s0;
+    v_end           = s1;
+    v_dash_offset   = dash_offset;
+    gl_Position     = vec4(v_position / viewport * 2.0 - 1.0, c.z / c.w, 1.0);
+}";
+
+pub(crate) static LINE_PIXEL_SHADER: &'static str = "
+#version 300 es
+precision highp float;
+
+in lowp     vec4        v_color;
+in          vec2        v_position;
+flat in     vec2        v_start;
+flat in     vec2        v_end;
+flat in     float       v_dash_offset;
+
+uniform     vec4        line_style;
+
+layout(location = 0) out lowp vec4    color_buffer;
+
+void main() {
+    vec2 d = v_end - v_start;
+    float len2 = dot(d, d);
+    vec2 dir = len2 > 1e-8 ? d * inversesqrt(len2) : vec2(1.0, 0.0);
+    vec2 p = v_position - v_start;
+
+    float dist;
+    if (line_style.y > 0.5) {
+        dist = abs(dot(p, vec2(-dir.y, dir.x)));
+    } else {
+        float t = len2 > 1e-8 ? clamp(dot(p, d) / len2, 0.0, 1.0) : 0.0;
+        dist = length(p - d * t);
+    }
+    float alpha = clamp(line_style.x * 0.5 + 0.5 - dist, 0.0, 1.0);
+
+    if (line_style.z > 0.0 && line_style.w > 0.0) {
+        float phase = mod(max(dot(p, dir), 0.0) + v_dash_offset, line_style.z + line_style.w);
+        alpha *= clamp(min(phase, line_style.z - phase) + 0.5, 0.0, 1.0);
+    }
+
+    if (alpha <= 0.0) {
+        discard;
+    }
+    // premultiplied blending: the coverage scales the color
+    color_buffer    = v_color * alpha;
+}";
+
+render_data! {
+    vertex LineCorner {
+        corner      : Vec2f,
+    }
+
+    vertex LineInstance {
+        prev        : Vec3f,
+        start       : Vec3f,
+        end         : Vec3f,
+        next        : Vec3f,
+        color       : Color4b,
+        dash_offset : f32,
+    }
+
+    uniforms LineUniforms {
+        pvm         : Mat4f,
+        viewport    : Vec2f,
+        line_style  : Vec4f,
+    }
+}
+
 render_data! {
     vertex Vertex {
         position: Vec3f,
+        normal  : Vec3f,
         color   : Color4b,
     }
 
     uniforms Uniforms {
         pvm     : Mat4f,
     }
+
+    vertex Instance {
+        model_0         : Vec4f,
+        model_1         : Vec4f,
+        model_2         : Vec4f,
+        model_3         : Vec4f,
+        instance_color  : Color4b,
+    }
+
+    uniforms LitUniforms {
+        pvm             : Mat4f,
+        normal_matrix   : Mat4f,
+        ambient         : Vec4f,
+        headlight       : Vec4f,
+        light_direction : Vec4f,
+        light_color     : Vec4f,
+    }
+}
+
+// the unlit shaders ignore the normals
+fn unlit_attribute_names() -> Vec<String> {
+    Vertex::get_attribute_names()
+        .into_iter()
+        .filter(|name| name != "normal")
+        .collect()
+}
+
+fn unlit_attribute_descriptors() -> Vec<VertexAttributeDesc> {
+    Vertex::get_attribute_descriptors()
+        .into_iter()
+        .filter(|desc| desc.name() != "normal")
+        .collect()
+}
+
+fn normal_matrix(m: &Mat4f) -> Mat4f {
+    m.inverse().transpose()
+}
+
+fn transform_normal(nm: &Mat4f, n: &Vec3f) -> Vec3f {
+    let t = *nm * Vec4f::new(n.x, n.y, n.z, 0.0);
+    let t = Vec3f::new(t.x, t.y, t.z);
+    if t.length() > 0.0 {
+        t.normalize()
+    } else {
+        t
+    }
+}
+
+fn face_normal(v0: &Vec3f, v1: &Vec3f, v2: &Vec3f) -> Vec3f {
+    let n = Vec3f::cross(&(*v1 - *v0), &(*v2 - *v0));
+    if n.length() > 0.0 {
+        n.normalize()
+    } else {
+        n
+    }
+}
+
+impl Vertex {
+    fn new(position: &Vec3f, normal: &Vec3f, color: &Color4b) -> Self {
+        Self {
+            position: *position,
+            normal: *normal,
+            color: *color,
+        }
+    }
+
+    fn transformed(&self, m: &Mat4f, nm: &Mat4f) -> Self {
+        Self {
+            position: transform_vec3(m, &self.position),
+            normal: transform_normal(nm, &self.normal),
+            color: self.color,
+        }
+    }
 }
 
 impl std::ops::Mul<Vertex> for Mat4f {
     type Output = Vertex;
     fn mul(self, rhs: Vertex) -> Self::Output {
-        Vertex {
-            position: transform_vec3(&self, &rhs.position),
-            color: rhs.color,
-        }
+        rhs.transformed(&self, &normal_matrix(&self))
     }
 }
 
@@ -85,6 +379,7 @@ impl std::ops::Add<Vertex> for Vertex {
     fn add(self, rhs: Vertex) -> Self::Output {
         Vertex {
             position: self.position + rhs.position,
+            normal: self.normal + rhs.normal,
             color: rhs.color,
         }
     }
@@ -95,6 +390,7 @@ impl std::ops::Sub<Vertex> for Vertex {
     fn sub(self, rhs: Vertex) -> Self::Output {
         Vertex {
             position: self.position - rhs.position,
+            normal: self.normal - rhs.normal,
             color: rhs.color,
         }
     }
@@ -105,6 +401,7 @@ impl std::ops::Mul<f32> for Vertex {
     fn mul(self, rhs: f32) -> Self::Output {
         Vertex {
             position: self.position * rhs,
+            normal: self.normal * rhs,
             color: self.color,
         }
     }
@@ -115,6 +412,7 @@ impl std::ops::Mul<Vertex> for f32 {
     fn mul(self, rhs: Vertex) -> Self::Output {
         Vertex {
             position: rhs.position * self,
+            normal: rhs.normal * self,
             color: rhs.color,
         }
     }
@@ -125,6 +423,7 @@ impl std::ops::Div<f32> for Vertex {
     fn div(self, rhs: f32) -> Self::Output {
         Vertex {
             position: self.position / rhs,
+            normal: self.normal / rhs,
             color: self.color,
         }
     }
@@ -138,16 +437,11 @@ pub struct Segment {
 
 impl Segment {
     pub fn new(start: &Vec3f, end: &Vec3f, color: &Color4b) -> Self {
+        let normal = Vec3f::zero();
         Self {
             verts: [
-                Vertex {
-                    position: *start,
-                    color: *color,
-                },
-                Vertex {
-                    position: *end,
-                    color: *color,
-                },
+                Vertex::new(start, &normal, color),
+                Vertex::new(end, &normal, color),
             ],
         }
     }
@@ -177,9 +471,9 @@ impl Index<usize> for Segment {
 impl std::ops::Mul<Segment> for Mat4f {
     type Output = Segment;
     fn mul(self, rhs: Segment) -> Self::Output {
-        let v0 = self.clone() * rhs.verts[0];
-        let v1 = self * rhs.verts[1];
-        Segment::new(&v0.position, &v1.position, &rhs.verts[0].color)
+        let v0 = transform_vec3(&self, rhs.start());
+        let v1 = transform_vec3(&self, rhs.end());
+        Segment::new(&v0, &v1, &rhs.verts[0].color)
     }
 }
 
@@ -190,25 +484,27 @@ pub struct Triangle {
 }
 
 impl Triangle {
+    ///
+    /// flat shaded: the vertices have the face normal
+    ///
     pub fn new(v0: &Vec3f, v1: &Vec3f, v2: &Vec3f, color: &Color4b) -> Self {
+        let normal = face_normal(v0, v1, v2);
         Self {
             verts: [
-                Vertex {
-                    position: *v0,
-                    color: *color,
-                },
-                Vertex {
-                    position: *v1,
-                    color: *color,
-                },
-                Vertex {
-                    position: *v2,
-                    color: *color,
-                },
+                Vertex::new(v0, &normal, color),
+                Vertex::new(v1, &normal, color),
+                Vertex::new(v2, &normal, color),
             ],
         }
     }
 
+    pub fn with_normals(mut self, n0: &Vec3f, n1: &Vec3f, n2: &Vec3f) -> Self {
+        self.verts[0].normal = *n0;
+        self.verts[1].normal = *n1;
+        self.verts[2].normal = *n2;
+        self
+    }
+
     pub fn v0(&self) -> &Vec3f {
         &self.verts[0].position
     }
@@ -237,15 +533,14 @@ impl Index<usize> for Triangle {
 impl std::ops::Mul<Triangle> for Mat4f {
     type Output = Triangle;
     fn mul(self, rhs: Triangle) -> Self::Output {
-        let v0 = self.clone() * rhs.verts[0];
-        let v1 = self.clone() * rhs.verts[1];
-        let v2 = self.clone() * rhs.verts[2];
-        Triangle::new(
-            &v0.position,
-            &v1.position,
-            &v2.position,
-            &rhs.verts[0].color,
-        )
+        let nm = normal_matrix(&self);
+        Triangle {
+            verts: [
+                rhs.verts[0].transformed(&self, &nm),
+                rhs.verts[1].transformed(&self, &nm),
+                rhs.verts[2].transformed(&self, &nm),
+            ],
+        }
     }
 }
 
@@ -256,37 +551,39 @@ pub struct Quad {
 }
 
 impl Quad {
+    ///
+    /// flat shaded: the vertices have the face normal
+    ///
     pub fn new(v0: &Vec3f, v1: &Vec3f, v2: &Vec3f, v3: &Vec3f, color: &Color4b) -> Self {
+        // the diagonals are robust to a degenerate corner
+        let normal = face_normal(v0, v1, v2) + face_normal(v2, v3, v0);
+        let normal = if normal.length() > 0.0 {
+            normal.normalize()
+        } else {
+            normal
+        };
         Self {
             verts: [
-                Vertex {
-                    position: *v0,
-                    color: *color,
-                },
-                Vertex {
-                    position: *v1,
-                    color: *color,
-                },
-                Vertex {
-                    position: *v2,
-                    color: *color,
-                },
-                Vertex {
-                    position: *v2,
-                    color: *color,
-                },
-                Vertex {
-                    position: *v3,
-                    color: *color,
-                },
-                Vertex {
-                    position: *v0,
-                    color: *color,
-                },
+                Vertex::new(v0, &normal, color),
+                Vertex::new(v1, &normal, color),
+                Vertex::new(v2, &normal, color),
+                Vertex::new(v2, &normal, color),
+                Vertex::new(v3, &normal, color),
+                Vertex::new(v0, &normal, color),
             ],
         }
     }
 
+    pub fn with_normals(mut self, n0: &Vec3f, n1: &Vec3f, n2: &Vec3f, n3: &Vec3f) -> Self {
+        self.verts[0].normal = *n0;
+        self.verts[1].normal = *n1;
+        self.verts[2].normal = *n2;
+        self.verts[3].normal = *n2;
+        self.verts[4].normal = *n3;
+        self.verts[5].normal = *n0;
+        self
+    }
+
     pub fn v0(&self) -> &Vec3f {
         &self.verts[0].position
     }
@@ -322,17 +619,12 @@ impl Index<usize> for Quad {
 impl std::ops::Mul<Quad> for Mat4f {
     type Output = Quad;
     fn mul(self, rhs: Quad) -> Self::Output {
-        let v0 = self.clone() * rhs.verts[0];
-        let v1 = self.clone() * rhs.verts[1];
-        let v2 = self.clone() * rhs.verts[2];
-        let v3 = self.clone() * rhs.verts[4];
-        Quad::new(
-            &v0.position,
-            &v1.position,
-            &v2.position,
-            &v3.position,
-            &rhs.verts[0].color,
-        )
+        let nm = normal_matrix(&self);
+        let mut verts = rhs.verts;
+        for v in verts.iter_mut() {
+            *v = v.transformed(&self, &nm);
+        }
+        Quad { verts }
     }
 }
 
@@ -486,6 +778,7 @@ impl UMNode {
         Self::disk_tris(center, &-*normal, color, seg_count, &mut tris);
 
         let [_, y_axis, x_axis] = basis_from_unit(&normal);
+        let axis = Vec3f::normalize(normal);
         let step = 2.0 * std::f32::consts::PI / (seg_count as f32);
 
         for i in 0..seg_count {
@@ -501,12 +794,17 @@ impl UMNode {
 
             let p1 = (x_axis * c + y_axis * s) * scale + *center;
 
-            tris.push(Triangle::new(
-                &(*center + Vec3f::normalize(normal) * height),
-                &p0,
-                &p1,
-                color,
-            ));
+            // smooth sides: the normals lean from the radial direction to the axis
+            let n0 = (Vec3f::normalize(&(p0 - *center)) * height + axis * scale).normalize();
+            let n1 = (Vec3f::normalize(&(p1 - *center)) * height + axis * scale).normalize();
+            let apex_normal = (n0 + n1).normalize();
+            tris.push(
+                Triangle::new(&(*center + axis * height), &p0, &p1, color).with_normals(
+                    &apex_normal,
+                    &n0,
+                    &n1,
+                ),
+            );
         }
 
         Self::Tris(tris)
@@ -695,7 +993,13 @@ impl UMNode {
         let vp2 = *center + (*v2 - *center).normalize() * radius;
         let vp3 = *center + (*v3 - *center).normalize() * radius;
 
-        Quad::new(&vp0, &vp1, &vp2, &vp3, &q.verts[0].color)
+        // smooth: radial normals
+        Quad::new(&vp0, &vp1, &vp2, &vp3, &q.verts[0].color).with_normals(
+            &(*v0 - *center).normalize(),
+            &(*v1 - *center).normalize(),
+            &(*v2 - *center).normalize(),
+            &(*v3 - *center).normalize(),
+        )
     }
 
     pub fn sphere(center: &Vec3f, radius: f32, subdiv: usize, color: &Color4b) -> Self {
@@ -796,10 +1100,197 @@ impl UMNode {
     }
 }
 
+///
+/// static GPU copy of a node: indexed vertices of its segments & of its
+/// triangles/quads, see `UMRenderer::upload`
+///
+#[derive(Clone)]
+pub struct UMMeshHandle {
+    wire: Option<UMMeshBuffers>,
+    solid: Option<UMMeshBuffers>,
+}
+
+#[derive(Clone)]
+struct UMMeshBuffers {
+    vb: DeviceBufferPtr,
+    ib: DeviceBufferPtr,
+    prim_count: u32,
+}
+
+struct UMMeshPipelines {
+    wire: PipelinePtr,
+    solid: PipelinePtr,
+    lit: PipelinePtr,
+    oit_wire: PipelinePtr,
+    oit_solid: PipelinePtr,
+    instanced_wire: PipelinePtr,
+    instanced_solid: PipelinePtr,
+}
+
+///
+/// merges the identical vertices of the node elements
+///
+#[derive(Default)]
+struct UMMeshBuilder {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    vertex_map: HashMap<([u32; 6], [u8; 4]), u32>,
+}
+
+impl UMMeshBuilder {
+    fn push(&mut self, v: &Vertex) {
+        let key = (
+            [
+                v.position.x.to_bits(),
+                v.position.y.to_bits(),
+                v.position.z.to_bits(),
+                v.normal.x.to_bits(),
+                v.normal.y.to_bits(),
+                v.normal.z.to_bits(),
+            ],
+            [v.color.x, v.color.y, v.color.z, v.color.w],
+        );
+        let vertices = &mut self.vertices;
+        let index = *self.vertex_map.entry(key).or_insert_with(|| {
+            vertices.push(*v);
+            (vertices.len() - 1) as u32
+        });
+        self.indices.push(index);
+    }
+
+    fn build(self, driver: &mut DriverPtr, verts_per_prim: usize) -> Option<UMMeshBuffers> {
+        if self.indices.is_empty() {
+            return None;
+        }
+
+        let prim_count = (self.indices.len() / verts_per_prim) as u32;
+        let vb = driver
+            .create_device_buffer(DeviceBufferDesc::vertex(Usage::Static(Arc::new(
+                self.vertices,
+            ))))
+            .unwrap();
+        let ib = driver
+            .create_device_buffer(DeviceBufferDesc::index(Usage::Static(Arc::new(
+                self.indices,
+            ))))
+            .unwrap();
+        Some(UMMeshBuffers { vb, ib, prim_count })
+    }
+}
+
+///
+/// lights of the lit pipelines, in view space: a headlight along the view
+/// direction & a directional light
+///
+#[derive(Debug, Clone)]
+pub struct UMLighting {
+    pub ambient: Vec3f,
+    pub headlight: Vec3f,
+    /// direction the light travels to
+    pub light_direction: Vec3f,
+    pub light_color: Vec3f,
+}
+
+impl UMLighting {
+    pub fn default() -> Self {
+        Self {
+            ambient: Vec3f::new(0.2, 0.2, 0.2),
+            headlight: Vec3f::new(0.6, 0.6, 0.6),
+            light_direction: Vec3f::new(-0.5, -1.0, -0.5),
+            light_color: Vec3f::new(0.3, 0.3, 0.3),
+        }
+    }
+
+    pub fn with_ambient(mut self, ambient: &Vec3f) -> Self {
+        self.ambient = *ambient;
+        self
+    }
+
+    pub fn with_headlight(mut self, headlight: &Vec3f) -> Self {
+        self.headlight = *headlight;
+        self
+    }
+
+    pub fn with_light(mut self, direction: &Vec3f, color: &Vec3f) -> Self {
+        self.light_direction = *direction;
+        self.light_color = *color;
+        self
+    }
+}
+
+fn same_point(a: &Vec3f, b: &Vec3f) -> bool {
+    a.x == b.x && a.y == b.y && a.z == b.z
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UMLineJoin {
+    Miter,
+    Round,
+}
+
+///
+/// screen space line style: width & dash pattern are in pixels. Hidden lines are
+/// drawn without depth test (usually dashed), before the visible ones
+///
+#[derive(Debug, Clone)]
+pub struct UMLineStyle {
+    pub width: f32,
+    pub join: UMLineJoin,
+    /// (dash, gap) lengths
+    pub dash: Option<(f32, f32)>,
+    pub depth_test: bool,
+}
+
+impl UMLineStyle {
+    pub fn new(width: f32) -> Self {
+        Self {
+            width,
+            join: UMLineJoin::Round,
+            dash: None,
+            depth_test: true,
+        }
+    }
+
+    pub fn with_join(mut self, join: UMLineJoin) -> Self {
+        self.join = join;
+        self
+    }
+
+    pub fn with_dash(mut self, dash: f32, gap: f32) -> Self {
+        self.dash = Some((dash, gap));
+        self
+    }
+
+    pub fn with_depth_test(mut self, depth_test: bool) -> Self {
+        self.depth_test = depth_test;
+        self
+    }
+
+    fn uniform(&self) -> Vec4f {
+        let (dash, gap) = self.dash.unwrap_or((0.0, 0.0));
+        let miter = match self.join {
+            UMLineJoin::Miter => 1.0,
+            UMLineJoin::Round => 0.0,
+        };
+        Vec4f::new(self.width, miter, dash, gap)
+    }
+}
+
 pub struct UMRenderer {
     driver: DriverPtr,
     wire_pipeline: PipelinePtr,
     solid_pipeline: PipelinePtr,
+    lit_pipeline: PipelinePtr,
+    oit_wire_pipeline: PipelinePtr,
+    oit_solid_pipeline: PipelinePtr,
+    mesh_pipelines: UMMeshPipelines,
+    line_pipeline: PipelinePtr,
+    hidden_line_pipeline: PipelinePtr,
+    line_corners: DeviceBufferPtr,
+    line_instances: DeviceBufferPtr,
+    instanced_wire_pipeline: PipelinePtr,
+    instanced_solid_pipeline: PipelinePtr,
+    instances: DeviceBufferPtr,
 
     max_verts: usize,
     vb: DeviceBufferPtr,
@@ -809,9 +1300,15 @@ unsafe impl Sync for UMRenderer {}
 unsafe impl Send for UMRenderer {}
 
 impl UMRenderer {
+    ///
+    /// `max_verts` is the size of the vertex & instance buffers, it is raised
+    /// to hold at least one quad (6 vertices)
+    ///
     pub fn new(driver: &mut DriverPtr, max_verts: usize) -> Self {
+        let max_verts = usize::max(max_verts, 6);
+
         let mut model_attribs = Vec::new();
-        model_attribs.push(Vertex::get_attribute_names());
+        model_attribs.push(unlit_attribute_names());
 
         let model_shader_desc = ShaderDesc {
             vertex_shader: String::from(VERTEX_SHADER),
@@ -823,13 +1320,14 @@ impl UMRenderer {
 
             pixel_uniforms: Vec::new(),
             pixel_surfaces: Vec::new(),
+            label: None,
         };
 
         let model_program = driver.create_shader(model_shader_desc).unwrap();
 
         let vertex_layout = VertexBufferLayout {
             buffer_id: 0,
-            vertex_attributes: Vertex::get_attribute_descriptors(),
+            vertex_attributes: unlit_attribute_descriptors(),
             stride: Vertex::stride(),
             divisor: 0,
         };
@@ -851,9 +1349,11 @@ impl UMRenderer {
             depth_test: true,
             blend: BlendOp::Add(Blend::default()),
             polygon_offset: PolygonOffset::None,
+            primitive_restart: false,
+            label: None,
         };
 
-        let solid_pipeline = driver.create_pipeline(solid_pipeline_desc).unwrap();
+        let solid_pipeline = driver.create_pipeline(solid_pipeline_desc.clone()).unwrap();
 
         let wire_pipeline_desc = PipelineDesc {
             primitive_type: PrimitiveType::Lines,
@@ -872,27 +1372,249 @@ impl UMRenderer {
             depth_test: true,
             blend: BlendOp::Add(Blend::default()),
             polygon_offset: PolygonOffset::None,
+            primitive_restart: false,
+            label: None,
+        };
+
+        let wire_pipeline = driver.create_pipeline(wire_pipeline_desc.clone()).unwrap();
+
+        let lit_program = driver
+            .create_shader(ShaderDesc {
+                vertex_shader: String::from(LIT_VERTEX_SHADER),
+                pixel_shader: String::from(LIT_PIXEL_SHADER),
+
+                vertex_attributes: vec![Vertex::get_attribute_names()],
+                vertex_uniforms: LitUniforms::get_uniform_names(),
+                vertex_surfaces: Vec::new(),
+
+                pixel_uniforms: Vec::new(),
+                pixel_surfaces: Vec::new(),
+                label: Some(String::from("utility mesh: lit")),
+            })
+            .unwrap();
+
+        let lit_pipeline_desc = PipelineDesc {
+            shader: lit_program,
+            buffer_layouts: vec![VertexBufferLayout {
+                buffer_id: 0,
+                vertex_attributes: Vertex::get_attribute_descriptors(),
+                stride: Vertex::stride(),
+                divisor: 0,
+            }],
+            uniform_descs: LitUniforms::get_uniform_descriptors(),
+            ..solid_pipeline_desc.clone()
+        };
+        let lit_pipeline = driver.create_pipeline(lit_pipeline_desc.clone()).unwrap();
+
+        let oit_shader_desc = ShaderDesc {
+            vertex_shader: String::from(VERTEX_SHADER),
+            pixel_shader: format!(
+                "{}{}\nvoid main() {{\n    oit_output(v_color);\n}}",
+                OIT_PIXEL_SHADER,
+                oit::OIT_OUTPUTS
+            ),
+
+            vertex_attributes: vec![unlit_attribute_names()],
+            vertex_uniforms: vec![String::from("pvm")],
+            vertex_surfaces: Vec::new(),
+
+            pixel_uniforms: Vec::new(),
+            pixel_surfaces: Vec::new(),
+            label: Some(String::from("utility mesh: oit")),
         };
 
-        let wire_pipeline = driver.create_pipeline(wire_pipeline_desc).unwrap();
+        let oit_program = driver.create_shader(oit_shader_desc).unwrap();
 
-        let vb_desc = DeviceBufferDesc::Vertex(Usage::new_dynamic::<Vertex>(max_verts));
+        let oit_solid_pipeline_desc = PipelineDesc {
+            shader: oit_program.clone(),
+            depth_write: false,
+            blend: oit::oit_blend(),
+            ..solid_pipeline_desc.clone()
+        };
+        let oit_solid_pipeline = driver
+            .create_pipeline(oit_solid_pipeline_desc.clone())
+            .unwrap();
+
+        let oit_wire_pipeline_desc = PipelineDesc {
+            shader: oit_program,
+            depth_write: false,
+            blend: oit::oit_blend(),
+            ..wire_pipeline_desc.clone()
+        };
+        let oit_wire_pipeline = driver
+            .create_pipeline(oit_wire_pipeline_desc.clone())
+            .unwrap();
+
+        let instanced_program = driver
+            .create_shader(ShaderDesc {
+                vertex_shader: String::from(INSTANCED_VERTEX_SHADER),
+                pixel_shader: String::from(PIXEL_SHADER),
+
+                vertex_attributes: vec![unlit_attribute_names(), Instance::get_attribute_names()],
+                vertex_uniforms: vec![String::from("pvm")],
+                vertex_surfaces: Vec::new(),
+
+                pixel_uniforms: Vec::new(),
+                pixel_surfaces: Vec::new(),
+                label: Some(String::from("utility mesh: instanced")),
+            })
+            .unwrap();
+
+        let instance_layout = VertexBufferLayout {
+            buffer_id: 1,
+            vertex_attributes: Instance::get_attribute_descriptors(),
+            stride: Instance::stride(),
+            divisor: 1,
+        };
+        let instanced_solid_pipeline_desc = PipelineDesc {
+            shader: instanced_program.clone(),
+            buffer_layouts: vec![vertex_layout.clone(), instance_layout.clone()],
+            ..solid_pipeline_desc.clone()
+        };
+        let instanced_solid_pipeline = driver
+            .create_pipeline(instanced_solid_pipeline_desc.clone())
+            .unwrap();
+        let instanced_wire_pipeline_desc = PipelineDesc {
+            shader: instanced_program,
+            buffer_layouts: vec![vertex_layout.clone(), instance_layout],
+            ..wire_pipeline_desc.clone()
+        };
+        let instanced_wire_pipeline = driver
+            .create_pipeline(instanced_wire_pipeline_desc.clone())
+            .unwrap();
+
+        // retained meshes are indexed
+        let mut indexed = |desc: PipelineDesc| {
+            driver
+                .create_pipeline(PipelineDesc {
+                    index_type: IndexType::UInt32,
+                    ..desc
+                })
+                .unwrap()
+        };
+        let mesh_pipelines = UMMeshPipelines {
+            wire: indexed(wire_pipeline_desc),
+            solid: indexed(solid_pipeline_desc),
+            lit: indexed(lit_pipeline_desc),
+            oit_wire: indexed(oit_wire_pipeline_desc),
+            oit_solid: indexed(oit_solid_pipeline_desc),
+            instanced_wire: indexed(instanced_wire_pipeline_desc),
+            instanced_solid: indexed(instanced_solid_pipeline_desc),
+        };
+
+        let line_program = driver
+            .create_shader(ShaderDesc {
+                vertex_shader: String::from(LINE_VERTEX_SHADER),
+                pixel_shader: String::from(LINE_PIXEL_SHADER),
+
+                vertex_attributes: vec![
+                    LineCorner::get_attribute_names(),
+                    LineInstance::get_attribute_names(),
+                ],
+                vertex_uniforms: LineUniforms::get_uniform_names(),
+                vertex_surfaces: Vec::new(),
+
+                pixel_uniforms: Vec::new(),
+                pixel_surfaces: Vec::new(),
+                label: Some(String::from("utility mesh: lines")),
+            })
+            .unwrap();
+
+        let line_pipeline_desc = PipelineDesc {
+            primitive_type: PrimitiveType::Triangles,
+            shader: line_program,
+            buffer_layouts: vec![
+                VertexBufferLayout {
+                    buffer_id: 0,
+                    vertex_attributes: LineCorner::get_attribute_descriptors(),
+                    stride: LineCorner::stride(),
+                    divisor: 0,
+                },
+                VertexBufferLayout {
+                    buffer_id: 1,
+                    vertex_attributes: LineInstance::get_attribute_descriptors(),
+                    stride: LineInstance::stride(),
+                    divisor: 1,
+                },
+            ],
+            uniform_descs: LineUniforms::get_uniform_descriptors(),
+            index_type: IndexType::None,
+            face_winding: FaceWinding::CCW,
+            cull_mode: CullMode::None,
+            depth_write: true,
+            depth_test: true,
+            blend: BlendOp::Add(Blend::default()),
+            polygon_offset: PolygonOffset::None,
+            primitive_restart: false,
+            label: Some(String::from("utility mesh: lines")),
+        };
+        let line_pipeline = driver.create_pipeline(line_pipeline_desc.clone()).unwrap();
+        let hidden_line_pipeline = driver
+            .create_pipeline(PipelineDesc {
+                depth_write: false,
+                depth_test: false,
+                label: Some(String::from("utility mesh: hidden lines")),
+                ..line_pipeline_desc
+            })
+            .unwrap();
+
+        // (start/end, side) corners of the quads
+        let corners: Vec<LineCorner> = [
+            (0.0, -1.0),
+            (1.0, -1.0),
+            (1.0, 1.0),
+            (1.0, 1.0),
+            (0.0, 1.0),
+            (0.0, -1.0),
+        ]
+        .iter()
+        .map(|(x, y)| LineCorner {
+            corner: Vec2f::new(*x, *y),
+        })
+        .collect();
+        let line_corners = driver
+            .create_device_buffer(DeviceBufferDesc::vertex(Usage::Static(Arc::new(corners))))
+            .unwrap();
+        let line_instances = driver
+            .create_device_buffer(DeviceBufferDesc::vertex(
+                Usage::new_dynamic::<LineInstance>(max_verts / 2),
+            ))
+            .unwrap();
+
+        let instances = driver
+            .create_device_buffer(DeviceBufferDesc::vertex(Usage::new_dynamic::<Instance>(
+                max_verts,
+            )))
+            .unwrap();
+
+        let vb_desc = DeviceBufferDesc::vertex(Usage::new_dynamic::<Vertex>(max_verts));
         let vb = driver.create_device_buffer(vb_desc).unwrap();
 
         Self {
             driver: driver.clone(),
             wire_pipeline: wire_pipeline,
             solid_pipeline: solid_pipeline,
+            lit_pipeline,
+            oit_wire_pipeline,
+            oit_solid_pipeline,
+            mesh_pipelines,
+            line_pipeline,
+            hidden_line_pipeline,
+            line_corners,
+            line_instances,
+            instanced_wire_pipeline,
+            instanced_solid_pipeline,
+            instances,
             max_verts: max_verts,
             vb: vb,
         }
     }
 
     fn draw_chunks<T: Sized + Clone + Sync + Send + 'static>(
-        &mut self,
+        &self,
         pass: &mut PassCommandQueue,
         pipeline: &PipelinePtr,
-        pvm: &Mat4f,
+        uniforms: &Arc<dyn Payload>,
         chunk_size: usize,
         elems: &Vec<T>,
         count_mul: usize,
@@ -906,7 +1628,7 @@ impl UMRenderer {
 
             let pl = elems[start_chnk_idx..start_chnk_idx + count].to_vec();
 
-            pass.update_device_buffer(&mut self.vb, 0, Arc::new(pl));
+            pass.update_device_buffer(&self.vb, 0, Arc::new(pl));
             let bindings = Bindings {
                 vertex_buffers: vec![self.vb.clone()],
                 index_buffer: None,
@@ -918,7 +1640,7 @@ impl UMRenderer {
             pass.draw(
                 pipeline,
                 &bindings,
-                Arc::new(GenPayload::from(pvm.clone())),
+                uniforms.clone(),
                 (count * count_mul) as u32,
                 1,
             );
@@ -927,44 +1649,544 @@ impl UMRenderer {
         }
     }
 
-    pub fn draw_segments(
-        &mut self,
+    fn draw_segments_with(
+        &self,
         pass: &mut PassCommandQueue,
-        pvm: &Mat4f,
+        pipeline: &PipelinePtr,
+        uniforms: &Arc<dyn Payload>,
         lines: &Vec<Segment>,
     ) {
         let chunk_size = self.max_verts / 2;
-        let pipeline = self.wire_pipeline.clone();
-        self.draw_chunks(pass, &pipeline, pvm, chunk_size, lines, 1);
+        self.draw_chunks(pass, pipeline, uniforms, chunk_size, lines, 1);
     }
 
-    pub fn draw_tris(&mut self, pass: &mut PassCommandQueue, pvm: &Mat4f, tris: &Vec<Triangle>) {
+    fn draw_tris_with(
+        &self,
+        pass: &mut PassCommandQueue,
+        pipeline: &PipelinePtr,
+        uniforms: &Arc<dyn Payload>,
+        tris: &Vec<Triangle>,
+    ) {
         let chunk_size = self.max_verts / 3;
-        let pipeline = self.solid_pipeline.clone();
-        self.draw_chunks(pass, &pipeline, pvm, chunk_size, tris, 1);
+        self.draw_chunks(pass, pipeline, uniforms, chunk_size, tris, 1);
     }
 
-    pub fn draw_quads(&mut self, pass: &mut PassCommandQueue, pvm: &Mat4f, quads: &Vec<Quad>) {
+    fn draw_quads_with(
+        &self,
+        pass: &mut PassCommandQueue,
+        pipeline: &PipelinePtr,
+        uniforms: &Arc<dyn Payload>,
+        quads: &Vec<Quad>,
+    ) {
         let chunk_size = self.max_verts / 6;
-        let pipeline = self.solid_pipeline.clone();
-        self.draw_chunks(pass, &pipeline, pvm, chunk_size, quads, 2);
+        self.draw_chunks(pass, pipeline, uniforms, chunk_size, quads, 2);
+    }
+
+    pub fn draw_segments(&self, pass: &mut PassCommandQueue, pvm: &Mat4f, lines: &Vec<Segment>) {
+        let uniforms = Self::pvm_uniforms(pvm);
+        self.draw_segments_with(pass, &self.wire_pipeline, &uniforms, lines);
+    }
+
+    pub fn draw_tris(&self, pass: &mut PassCommandQueue, pvm: &Mat4f, tris: &Vec<Triangle>) {
+        let uniforms = Self::pvm_uniforms(pvm);
+        self.draw_tris_with(pass, &self.solid_pipeline, &uniforms, tris);
+    }
+
+    pub fn draw_quads(&self, pass: &mut PassCommandQueue, pvm: &Mat4f, quads: &Vec<Quad>) {
+        let uniforms = Self::pvm_uniforms(pvm);
+        self.draw_quads_with(pass, &self.solid_pipeline, &uniforms, quads);
     }
 
     pub fn driver(&self) -> DriverPtr {
         self.driver.clone()
     }
 
-    pub fn draw_node(&mut self, pass: &mut PassCommandQueue, pvm: &Mat4f, node: &UMNode) {
+    fn pvm_uniforms(pvm: &Mat4f) -> Arc<dyn Payload> {
+        Arc::new(GenPayload::from(pvm.clone()))
+    }
+
+    fn lit_uniforms(pvm: &Mat4f, view_model: &Mat4f, lighting: &UMLighting) -> Arc<dyn Payload> {
+        let vec4 = |v: &Vec3f| Vec4f::new(v.x, v.y, v.z, 0.0);
+        Arc::new(GenPayload::from(LitUniforms {
+            pvm: *pvm,
+            normal_matrix: normal_matrix(view_model),
+            ambient: vec4(&lighting.ambient),
+            headlight: vec4(&lighting.headlight),
+            light_direction: vec4(&lighting.light_direction.normalize()),
+            light_color: vec4(&lighting.light_color),
+        }))
+    }
+
+    fn draw_node_with(
+        &self,
+        pass: &mut PassCommandQueue,
+        wire_pipeline: &PipelinePtr,
+        wire_uniforms: &Arc<dyn Payload>,
+        solid_pipeline: &PipelinePtr,
+        solid_uniforms: &Arc<dyn Payload>,
+        node: &UMNode,
+    ) {
+        match node {
+            UMNode::Empty => (),
+            UMNode::Segments(segs) => {
+                self.draw_segments_with(pass, wire_pipeline, wire_uniforms, segs)
+            }
+            UMNode::Tris(tris) => self.draw_tris_with(pass, solid_pipeline, solid_uniforms, tris),
+            UMNode::Quads(quads) => {
+                self.draw_quads_with(pass, solid_pipeline, solid_uniforms, quads)
+            }
+            UMNode::Assembly(asms) => {
+                for n in asms {
+                    self.draw_node_with(
+                        pass,
+                        wire_pipeline,
+                        wire_uniforms,
+                        solid_pipeline,
+                        solid_uniforms,
+                        n,
+                    )
+                }
+            }
+        }
+    }
+
+    ///
+    /// only records commands: several threads can draw nodes into their own queues
+    ///
+    pub fn draw_node(&self, pass: &mut PassCommandQueue, pvm: &Mat4f, node: &UMNode) {
+        let uniforms = Self::pvm_uniforms(pvm);
+        self.draw_node_with(
+            pass,
+            &self.wire_pipeline,
+            &uniforms,
+            &self.solid_pipeline,
+            &uniforms,
+            node,
+        );
+    }
+
+    ///
+    /// `draw_node` with shaded triangles & quads, `view_model` transforms the
+    /// normals to the view space of the lights. Segments are not lit
+    ///
+    pub fn draw_node_lit(
+        &self,
+        pass: &mut PassCommandQueue,
+        pvm: &Mat4f,
+        view_model: &Mat4f,
+        lighting: &UMLighting,
+        node: &UMNode,
+    ) {
+        self.draw_node_with(
+            pass,
+            &self.wire_pipeline,
+            &Self::pvm_uniforms(pvm),
+            &self.lit_pipeline,
+            &Self::lit_uniforms(pvm, view_model, lighting),
+            node,
+        );
+    }
+
+    ///
+    /// order independent translucent drawing: the queue must target the
+    /// accumulation pass of an `oit::WeightedBlendedOit`. Depth tested against
+    /// the opaque depth, without depth write. Like `WeightedBlendedOit`, this
+    /// needs float render targets & float blending (`DriverCaps`)
+    ///
+    pub fn draw_node_oit(&self, pass: &mut PassCommandQueue, pvm: &Mat4f, node: &UMNode) {
+        let uniforms = Self::pvm_uniforms(pvm);
+        self.draw_node_with(
+            pass,
+            &self.oit_wire_pipeline,
+            &uniforms,
+            &self.oit_solid_pipeline,
+            &uniforms,
+            node,
+        );
+    }
+
+    fn collect_mesh(node: &UMNode, wire: &mut UMMeshBuilder, solid: &mut UMMeshBuilder) {
+        match node {
+            UMNode::Empty => (),
+            UMNode::Segments(segs) => segs
+                .iter()
+                .flat_map(|s| s.verts.iter())
+                .for_each(|v| wire.push(v)),
+            UMNode::Tris(tris) => tris
+                .iter()
+                .flat_map(|t| t.verts.iter())
+                .for_each(|v| solid.push(v)),
+            UMNode::Quads(quads) => quads
+                .iter()
+                .flat_map(|q| q.verts.iter())
+                .for_each(|v| solid.push(v)),
+            UMNode::Assembly(asms) => {
+                for n in asms {
+                    Self::collect_mesh(n, wire, solid)
+                }
+            }
+        }
+    }
+
+    ///
+    /// store the node in static vertex & index buffers: drawing it then costs
+    /// no tessellation nor upload. The buffers are freed with the last handle:
+    /// keep it alive until the passes drawing it are rendered
+    ///
+    pub fn upload(&self, node: &UMNode) -> UMMeshHandle {
+        let mut wire = UMMeshBuilder::default();
+        let mut solid = UMMeshBuilder::default();
+        Self::collect_mesh(node, &mut wire, &mut solid);
+
+        let mut driver = self.driver.clone();
+        UMMeshHandle {
+            wire: wire.build(&mut driver, 2),
+            solid: solid.build(&mut driver, 3),
+        }
+    }
+
+    fn draw_mesh_with(
+        &self,
+        pass: &mut PassCommandQueue,
+        wire_pipeline: &PipelinePtr,
+        wire_uniforms: &Arc<dyn Payload>,
+        solid_pipeline: &PipelinePtr,
+        solid_uniforms: &Arc<dyn Payload>,
+        mesh: &UMMeshHandle,
+    ) {
+        let parts = [
+            (&mesh.wire, wire_pipeline, wire_uniforms),
+            (&mesh.solid, solid_pipeline, solid_uniforms),
+        ];
+        for (buffers, pipeline, uniforms) in parts.iter() {
+            if let Some(buffers) = buffers {
+                let bindings = Bindings {
+                    vertex_buffers: vec![buffers.vb.clone()],
+                    index_buffer: Some(buffers.ib.clone()),
+
+                    vertex_images: Vec::new(),
+                    pixel_images: Vec::new(),
+                };
+
+                pass.draw(
+                    pipeline,
+                    &bindings,
+                    (*uniforms).clone(),
+                    buffers.prim_count,
+                    1,
+                );
+            }
+        }
+    }
+
+    ///
+    /// only records commands, like `draw_node`
+    ///
+    pub fn draw_mesh(&self, pass: &mut PassCommandQueue, mesh: &UMMeshHandle, pvm: &Mat4f) {
+        let uniforms = Self::pvm_uniforms(pvm);
+        self.draw_mesh_with(
+            pass,
+            &self.mesh_pipelines.wire,
+            &uniforms,
+            &self.mesh_pipelines.solid,
+            &uniforms,
+            mesh,
+        );
+    }
+
+    ///
+    /// shaded drawing of the mesh, like `draw_node_lit`
+    ///
+    pub fn draw_mesh_lit(
+        &self,
+        pass: &mut PassCommandQueue,
+        mesh: &UMMeshHandle,
+        pvm: &Mat4f,
+        view_model: &Mat4f,
+        lighting: &UMLighting,
+    ) {
+        self.draw_mesh_with(
+            pass,
+            &self.mesh_pipelines.wire,
+            &Self::pvm_uniforms(pvm),
+            &self.mesh_pipelines.lit,
+            &Self::lit_uniforms(pvm, view_model, lighting),
+            mesh,
+        );
+    }
+
+    ///
+    /// order independent translucent drawing of the mesh, like `draw_node_oit`
+    /// (same float render targets & float blending requirement)
+    ///
+    pub fn draw_mesh_oit(&self, pass: &mut PassCommandQueue, mesh: &UMMeshHandle, pvm: &Mat4f) {
+        let uniforms = Self::pvm_uniforms(pvm);
+        self.draw_mesh_with(
+            pass,
+            &self.mesh_pipelines.oit_wire,
+            &uniforms,
+            &self.mesh_pipelines.oit_solid,
+            &uniforms,
+            mesh,
+        );
+    }
+
+    ///
+    /// None (nothing is drawn) if there are colors but not one per transform
+    ///
+    fn instances(transforms: &[Mat4f], colors: &[Color4b]) -> Option<Vec<Instance>> {
+        let matching = colors.is_empty() || colors.len() == transforms.len();
+        debug_assert!(
+            matching,
+            "{} instance colors for {} transforms",
+            colors.len(),
+            transforms.len()
+        );
+        if !matching {
+            return None;
+        }
+
+        let white = color4b(0xFF, 0xFF, 0xFF, 0xFF);
+        let instances = transforms
+            .iter()
+            .enumerate()
+            .map(|(i, m)| Instance {
+                model_0: m.col[0],
+                model_1: m.col[1],
+                model_2: m.col[2],
+                model_3: m.col[3],
+                instance_color: if colors.is_empty() { white } else { colors[i] },
+            })
+            .collect();
+        Some(instances)
+    }
+
+    fn collect_vertices(node: &UMNode, wire: &mut Vec<Vertex>, solid: &mut Vec<Vertex>) {
         match node {
             UMNode::Empty => (),
-            UMNode::Segments(segs) => self.draw_segments(pass, pvm, segs),
-            UMNode::Tris(tris) => self.draw_tris(pass, pvm, tris),
-            UMNode::Quads(quads) => self.draw_quads(pass, pvm, quads),
+            UMNode::Segments(segs) => segs.iter().for_each(|s| wire.extend_from_slice(&s.verts)),
+            UMNode::Tris(tris) => tris.iter().for_each(|t| solid.extend_from_slice(&t.verts)),
+            UMNode::Quads(quads) => quads.iter().for_each(|q| solid.extend_from_slice(&q.verts)),
+            UMNode::Assembly(asms) => {
+                for n in asms {
+                    Self::collect_vertices(n, wire, solid)
+                }
+            }
+        }
+    }
+
+    ///
+    /// draw a copy of `node` per transform: the template is uploaded once & the
+    /// instances in one buffer. The instance colors modulate the template colors
+    /// (build it white), no colors keeps the template ones. `colors` is either
+    /// empty or has one color per transform, nothing is drawn otherwise
+    ///
+    pub fn draw_instanced(
+        &self,
+        pass: &mut PassCommandQueue,
+        pvm: &Mat4f,
+        node: &UMNode,
+        transforms: &[Mat4f],
+        colors: &[Color4b],
+    ) {
+        let instances = match Self::instances(transforms, colors) {
+            Some(instances) => instances,
+            None => return,
+        };
+        let mut wire = Vec::new();
+        let mut solid = Vec::new();
+        Self::collect_vertices(node, &mut wire, &mut solid);
+
+        let uniforms = Self::pvm_uniforms(pvm);
+        let parts = [
+            (wire, &self.instanced_wire_pipeline, 2),
+            (solid, &self.instanced_solid_pipeline, 3),
+        ];
+        for (verts, pipeline, verts_per_prim) in parts.iter() {
+            let chunk_size = self.max_verts / verts_per_prim * verts_per_prim;
+            for template in verts.chunks(chunk_size) {
+                pass.update_device_buffer(&self.vb, 0, Arc::new(template.to_vec()));
+                for chunk in instances.chunks(self.max_verts) {
+                    pass.update_device_buffer(&self.instances, 0, Arc::new(chunk.to_vec()));
+                    let bindings = Bindings {
+                        vertex_buffers: vec![self.vb.clone(), self.instances.clone()],
+                        index_buffer: None,
+
+                        vertex_images: Vec::new(),
+                        pixel_images: Vec::new(),
+                    };
+
+                    pass.draw(
+                        pipeline,
+                        &bindings,
+                        uniforms.clone(),
+                        (template.len() / verts_per_prim) as u32,
+                        chunk.len() as u32,
+                    );
+                }
+            }
+        }
+    }
+
+    ///
+    /// `draw_instanced` with a retained template
+    ///
+    pub fn draw_mesh_instanced(
+        &self,
+        pass: &mut PassCommandQueue,
+        mesh: &UMMeshHandle,
+        pvm: &Mat4f,
+        transforms: &[Mat4f],
+        colors: &[Color4b],
+    ) {
+        let instances = match Self::instances(transforms, colors) {
+            Some(instances) => instances,
+            None => return,
+        };
+        let uniforms = Self::pvm_uniforms(pvm);
+        let parts = [
+            (&mesh.wire, &self.mesh_pipelines.instanced_wire),
+            (&mesh.solid, &self.mesh_pipelines.instanced_solid),
+        ];
+        for (buffers, pipeline) in parts.iter() {
+            if let Some(buffers) = buffers {
+                for chunk in instances.chunks(self.max_verts) {
+                    pass.update_device_buffer(&self.instances, 0, Arc::new(chunk.to_vec()));
+                    let bindings = Bindings {
+                        vertex_buffers: vec![buffers.vb.clone(), self.instances.clone()],
+                        index_buffer: Some(buffers.ib.clone()),
+
+                        vertex_images: Vec::new(),
+                        pixel_images: Vec::new(),
+                    };
+
+                    pass.draw(
+                        pipeline,
+                        &bindings,
+                        uniforms.clone(),
+                        buffers.prim_count,
+                        chunk.len() as u32,
+                    );
+                }
+            }
+        }
+    }
+
+    fn line_instances(pvm: &Mat4f, viewport: &Vec2f, lines: &Vec<Segment>) -> Vec<LineInstance> {
+        let to_screen = |p: &Vec3f| {
+            let c = *pvm * Vec4f::new(p.x, p.y, p.z, 1.0);
+            if c.w > 1e-4 {
+                Some(Vec2f::new(
+                    c.x / c.w * 0.5 * viewport.x,
+                    c.y / c.w * 0.5 * viewport.y,
+                ))
+            } else {
+                None
+            }
+        };
+
+        let count = lines.len();
+        let mut dash_offset = 0.0;
+        let mut instances = Vec::with_capacity(count);
+        for (i, seg) in lines.iter().enumerate() {
+            // consecutive segments sharing an end are joined (closed loops too)
+            let prev = &lines[(i + count - 1) % count];
+            let next = &lines[(i + 1) % count];
+            let prev_connected = count > 1 && same_point(prev.end(), seg.start());
+            let next_connected = count > 1 && same_point(next.start(), seg.end());
+
+            if !prev_connected || i == 0 {
+                dash_offset = 0.0;
+            }
+
+            instances.push(LineInstance {
+                prev: if prev_connected {
+                    *prev.start()
+                } else {
+                    *seg.start()
+                },
+                start: *seg.start(),
+                end: *seg.end(),
+                next: if next_connected {
+                    *next.end()
+                } else {
+                    *seg.end()
+                },
+                color: seg.verts[0].color,
+                dash_offset,
+            });
+
+            // the dashes continue along the polylines
+            if let (Some(s0), Some(s1)) = (to_screen(seg.start()), to_screen(seg.end())) {
+                dash_offset += (s1 - s0).length();
+            }
+        }
+        instances
+    }
+
+    ///
+    /// draw the segments as screen space quads of `style`, `viewport` is the
+    /// pass size in pixels
+    ///
+    pub fn draw_styled_segments(
+        &self,
+        pass: &mut PassCommandQueue,
+        pvm: &Mat4f,
+        viewport: &Vec2f,
+        style: &UMLineStyle,
+        lines: &Vec<Segment>,
+    ) {
+        let pipeline = if style.depth_test {
+            &self.line_pipeline
+        } else {
+            &self.hidden_line_pipeline
+        };
+        let uniforms = LineUniforms {
+            pvm: *pvm,
+            viewport: *viewport,
+            line_style: style.uniform(),
+        };
+
+        let instances = Self::line_instances(pvm, viewport, lines);
+        for chunk in instances.chunks(usize::max(self.max_verts / 2, 1)) {
+            pass.update_device_buffer(&self.line_instances, 0, Arc::new(chunk.to_vec()));
+            let bindings = Bindings {
+                vertex_buffers: vec![self.line_corners.clone(), self.line_instances.clone()],
+                index_buffer: None,
+
+                vertex_images: Vec::new(),
+                pixel_images: Vec::new(),
+            };
+
+            pass.draw(
+                pipeline,
+                &bindings,
+                Arc::new(GenPayload::from(uniforms)),
+                2,
+                chunk.len() as u32,
+            );
+        }
+    }
+
+    ///
+    /// `draw_node` with the segments drawn in `style`
+    ///
+    pub fn draw_styled_node(
+        &self,
+        pass: &mut PassCommandQueue,
+        pvm: &Mat4f,
+        viewport: &Vec2f,
+        style: &UMLineStyle,
+        node: &UMNode,
+    ) {
+        match node {
+            UMNode::Segments(segs) => self.draw_styled_segments(pass, pvm, viewport, style, segs),
             UMNode::Assembly(asms) => {
                 for n in asms {
-                    self.draw_node(pass, pvm, n)
+                    self.draw_styled_node(pass, pvm, viewport, style, n)
                 }
             }
+            _ => self.draw_node(pass, pvm, node),
         }
     }
 }
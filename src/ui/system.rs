@@ -151,6 +151,7 @@ impl Renderer {
 
                 pixel_uniforms: vec![],
                 pixel_surfaces: vec![String::from("u_sampler")],
+                label: None,
             })
             .unwrap();
 
@@ -181,16 +182,18 @@ impl Renderer {
             depth_test: false,
             blend: BlendOp::Add(blend),
             polygon_offset: PolygonOffset::None,
+            primitive_restart: false,
+            label: None,
         };
 
         let pipeline = drv.create_pipeline(pipeline_desc).unwrap();
 
         let vertex_buffer = drv
-            .create_device_buffer(DeviceBufferDesc::Vertex(Usage::Dynamic(MAX_VERTEX_COUNT * std::mem::size_of::<Vertex>())))
+            .create_device_buffer(DeviceBufferDesc::vertex(Usage::Dynamic(MAX_VERTEX_COUNT * std::mem::size_of::<Vertex>())))
             .unwrap();
 
         let index_buffer = drv
-            .create_device_buffer(DeviceBufferDesc::Vertex(Usage::Dynamic(MAX_INDEX_COUNT * std::mem::size_of::<u16>())))
+            .create_device_buffer(DeviceBufferDesc::vertex(Usage::Dynamic(MAX_INDEX_COUNT * std::mem::size_of::<u16>())))
             .unwrap();
 
         let tex_desc = TextureDesc {
@@ -200,6 +203,7 @@ impl Renderer {
                 ))
                 .with_wrap_mode(WrapMode::ClampToEdge),
             payload: Some(Arc::new(ATLAS.pixels.to_vec())),
+            label: None,
         };
 
         let ui_texture = drv.create_texture(tex_desc).unwrap();
@@ -514,7 +518,7 @@ impl App {
         window.make_current();
         glfw.set_swap_interval(glfw::SwapInterval::Sync(0));
 
-        let mut driver = renderer::get_driver();
+        let mut driver = renderer::get_driver_with_loader(|name| window.get_proc_address(name));
 
         let (width, height) = window.get_framebuffer_size();
         let renderer = system::Renderer::new(&mut driver, width as u32, height as u32);
@@ -551,10 +555,12 @@ impl App {
                     ColorPassAction::Previous,
                 ],
                 DepthPassAction::Clear(1.0),
-            );
+            )
+            .with_label("ui");
             pass.queue.append(queue);
             self.driver.render_pass(&mut pass);
             self.window.swap_buffers();
+            self.driver.end_frame();
 
             self.glfw.wait_events_timeout(0.007);
             for (_, event) in glfw::flush_messages(&self.events) {
@@ -0,0 +1,49 @@
+#![cfg(all(feature = "headless", feature = "image"))]
+
+use neocogi::renderer::*;
+use neocogi::rs_math3d::*;
+
+fn reference(name: &str) -> String {
+    format!("{}/tests/golden/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn golden_clear() {
+    let mut ctx = HeadlessContext::new(16, 16).unwrap();
+    let mut driver = ctx.driver().clone();
+
+    let mut pass = Pass::new(
+        16,
+        16,
+        Some(ctx.frame_buffer().clone()),
+        [
+            ColorPassAction::Clear(color4b(255, 128, 0, 255)),
+            ColorPassAction::Previous,
+            ColorPassAction::Previous,
+            ColorPassAction::Previous,
+        ],
+        DepthPassAction::Clear(1.0),
+    );
+
+    let options = GoldenOptions::default();
+    let report =
+        check_golden_pass(&mut driver, &mut pass, reference("clear.png"), &options).unwrap();
+    assert!(report.passed(&options));
+}
+
+#[test]
+fn golden_size_mismatch() {
+    let image = |width, height| Image {
+        width,
+        height,
+        channels: 4,
+        pixels: vec![0; width * height * 4],
+    };
+
+    match compare_images(&image(2, 2), &image(2, 3), &GoldenOptions::default()) {
+        Err(GoldenError::SizeMismatch { expected, actual }) => {
+            assert_eq!((expected, actual), ((2, 3), (2, 2)))
+        }
+        _ => panic!("expected a size mismatch"),
+    }
+}